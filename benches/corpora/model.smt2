@@ -0,0 +1,16 @@
+; a model printed by a solver after (check-sat) and (get-model)
+(set-logic QF_AUFLIA)
+(declare-fun f (Int Int) Int)
+(declare-fun a () (Array Int Int))
+(declare-fun x () Int)
+(declare-fun y () Int)
+(assert (forall ((i Int) (j Int)) (=> (and (<= 0 i) (< i j) (< j 100)) (<= (select a i) (select a j)))))
+(assert (let ((z (+ x (* 2 y)))) (and (> z 10) (= (f x y) (select (store a x z) y)))))
+(check-sat)
+(model
+  (define-fun x () Int 7)
+  (define-fun y () Int (- 3))
+  (define-fun a () (Array Int Int) (store (store ((as const (Array Int Int)) 0) 7 1) (- 3) 4))
+  (define-fun f ((x!0 Int) (x!1 Int)) Int (ite (and (= x!0 7) (= x!1 (- 3))) 4 (ite (= x!0 0) 1 0)))
+)
+(get-value ((select a 7) (f x y)))
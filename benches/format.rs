@@ -0,0 +1,95 @@
+//! Benchmarks for reading and pretty printing, over inputs shaped like the ones the formatter is used on
+//! run them with `cargo bench`, or `cargo bench -- print` for just the printing ones (or `-- ident_len` for the scanning of atoms)
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use sexpr_fmt::{Sexpr, FormatArgs};
+use sexpr_fmt::sexpr::{ident_len, MAX_DEPTH};
+
+/// The number of times the sample files are repeated, so that each input is big enough to time
+const REPEATS: usize = 200;
+
+/// A single form nested `depth` deep, like `(f (f (f ... x)))`, which the reader allows up to `MAX_DEPTH`
+fn deep_nesting(depth: usize) -> String {
+    format!("{}x{}\n", "(f ".repeat(depth), ")".repeat(depth))
+}
+
+/// A single flat list of `len` atoms, like a long `(data ...)` in a log
+fn wide_list(len: usize) -> String {
+    let atoms: Vec<String> = (0..len).map(|i| format!("atom{}", i)).collect();
+    format!("(data {})\n", atoms.join(" "))
+}
+
+/// The inputs, by name
+fn corpora() -> Vec<(&'static str, String)> {
+    vec![
+        ("deep-nesting", deep_nesting(MAX_DEPTH)),
+        ("wide-list", wide_list(50_000)),
+        ("smt-model", include_str!("corpora/model.smt2").repeat(REPEATS)),
+        ("kicad-board", include_str!("corpora/board.kicad_pcb").repeat(REPEATS)),
+    ]
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, input) in corpora() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| Sexpr::parse_many(black_box(input)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn print(c: &mut Criterion) {
+    let mut group = c.benchmark_group("print");
+    for (name, input) in corpora() {
+        let forms = Sexpr::parse_many(&input).unwrap();
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &forms, |b, forms| {
+            b.iter(|| forms.iter().map(|form| form.format(FormatArgs::new()).len()).sum::<usize>())
+        });
+    }
+    group.finish();
+}
+
+/// The way `ident_len` used to find the end of an atom: the first space, `)`, or newline in bulk,
+/// then the bytes before it one at a time for the rarer characters which end an atom too
+fn two_pass_ident_len(input: &str) -> usize {
+    let bytes = input.as_bytes();
+    let end = memchr::memchr3(b' ', b')', b'\n', bytes).unwrap_or(bytes.len());
+    let rare = bytes[..end].iter().position(|&b| matches!(b, b'(' | b'[' | b']' | b'\t' | b'\r' | b'\x0b' | b'\x0c') || !b.is_ascii());
+    match rare {
+        None => end,
+        Some(idx) => input[idx..].char_indices()
+            .find(|&(_, ch)| matches!(ch, '(' | ')' | '[' | ']') || ch.is_whitespace())
+            .map_or(input.len(), |(i, _)| idx + i),
+    }
+}
+
+/// The total length of the atoms found by going through `input` with `len`, skipping a character wherever it finds none
+fn scan_atoms(input: &str, len: fn(&str) -> usize) -> usize {
+    let (mut idx, mut total) = (0, 0);
+    while idx < input.len() {
+        let atom = len(&input[idx..]);
+        total += atom;
+        idx += if atom == 0 { input[idx..].chars().next().map_or(1, char::len_utf8) } else { atom };
+    }
+    total
+}
+
+/// `ident_len` against the loop it replaced, over the atoms of each input
+fn atoms(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ident_len");
+    for (name, input) in corpora() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::new("one-pass", name), &input, |b, input| {
+            b.iter(|| scan_atoms(black_box(input), ident_len))
+        });
+        group.bench_with_input(BenchmarkId::new("two-pass", name), &input, |b, input| {
+            b.iter(|| scan_atoms(black_box(input), two_pass_ident_len))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, print, atoms);
+criterion_main!(benches);
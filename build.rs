@@ -0,0 +1,25 @@
+fn main() {
+    #[cfg(feature = "capi")]
+    write_c_header();
+}
+
+/// Write the header for the C interface, so that it can never fall behind the functions it declares
+#[cfg(feature = "capi")]
+fn write_c_header() {
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    let dir = std::env::var("CARGO_MANIFEST_DIR").expect("cargo sets the manifest directory");
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        include_guard: Some("SEXPR_FMT_H".to_string()),
+        autogen_warning: Some("/* written by the build with `--features capi`, from src/capi.rs: do not edit */".to_string()),
+        documentation_style: cbindgen::DocumentationStyle::C99,
+        usize_is_size_t: true,
+        ..cbindgen::Config::default()
+    };
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(format!("{}/src/capi.rs", dir))
+        .generate()
+        .expect("the C interface can be written as a header")
+        .write_to_file(format!("{}/include/sexpr_fmt.h", dir));
+}
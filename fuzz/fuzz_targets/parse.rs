@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use sexpr_fmt::Sexpr;
+
+// the parser must reject bad input with an error, never by panicking
+fuzz_target!(|data: &[u8]| {
+    let _ = Sexpr::parse_bytes(data);
+});
@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use sexpr_fmt::{FormatArgs, Sexpr};
+
+// whatever the parser accepts, formatting it gives text that reads back the same
+fuzz_target!(|data: &[u8]| {
+    if let Ok(forms) = Sexpr::parse_bytes(data) {
+        for sexpr in forms {
+            let formatted = sexpr.format(FormatArgs::new());
+            assert_eq!(Sexpr::parse(&formatted), Ok(sexpr));
+        }
+    }
+});
@@ -0,0 +1,67 @@
+#ifndef SEXPR_FMT_H
+#define SEXPR_FMT_H
+
+/* written by the build with `--features capi`, from src/capi.rs: do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The input was formatted
+#define SEXPRFMT_OK 0
+
+// The input or the place to put the output was null, or the input was not utf-8
+#define SEXPRFMT_BAD_ARGUMENT 1
+
+// The input could not be read as s-expressions
+#define SEXPRFMT_PARSE_ERROR 2
+
+// The layout options, which `sexprfmt_default_options` gives the defaults of
+typedef struct SexprFmtOptions {
+  // the nesting depth of an s-expression to display on a single line
+  uint32_t complexity_threshold;
+  // squish the arguments of quantifiers onto the same line
+  bool short_quantifiers;
+  // keep the unquoted fragments (`,x` and `,@x`) of quasiquoted templates on one line
+  bool inline_unquoted;
+  // re-wrap the prose of block comments to the comment width
+  bool reflow_comments;
+  // the width to wrap block comments to
+  size_t comment_width;
+  // the number of columns a tab stands for
+  size_t tab_width;
+  // right-align the numbers in the columns of runs of rows like `(at 1.0 2.54)`
+  bool align_numbers;
+  // keep keywords like `:key` on the same line as the value after them
+  bool pair_keywords;
+  // line up the values in the binding lists of forms like `let`
+  bool align_bindings;
+  // the widest a line may be, or 0 for no limit
+  size_t max_width;
+} SexprFmtOptions;
+
+// The options the command line uses when it is given no flags
+struct SexprFmtOptions sexprfmt_default_options(void);
+
+// Format every top-level form of the nul-terminated utf-8 `input`, each on its own line,
+// with `options` (or the defaults if it is null)
+// on success, `*out` is set to the formatted text and `SEXPRFMT_OK` is returned;
+// otherwise `*out` is set to a message saying what went wrong (unless `out` is null), and an error code is returned
+// `*out` must be freed with `sexprfmt_free`
+//
+// # Safety
+// `input` must be null or a nul-terminated string, `options` must be null or point to options,
+// and `out` must be null or point to where a pointer can be written
+int sexprfmt_format(const char *input,
+                    const struct SexprFmtOptions *options,
+                    char **out);
+
+// Free a string given out by `sexprfmt_format`; freeing null does nothing
+//
+// # Safety
+// `text` must be null or a string from `sexprfmt_format` which has not been freed yet
+void sexprfmt_free(char *text);
+
+#endif  /* SEXPR_FMT_H */
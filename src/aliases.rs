@@ -0,0 +1,38 @@
+use crate::prelude::*;
+use core::fmt;
+use core::str::FromStr;
+use crate::sexpr::Sexpr;
+
+/// A family of heads which are treated the same by the head-specific rules,
+/// written as `defun=define=define-fun`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AliasGroup(Vec<String>);
+impl FromStr for AliasGroup {
+    type Err = String;
+    fn from_str(s: &str) -> Result<AliasGroup, String> {
+        let names: Vec<String> = s.split('=').map(|name| name.trim().to_string()).collect();
+        if names.len() < 2 || names.iter().any(|name| name.is_empty()) {
+            return Err(format!("expected aliases separated by `=`, such as `defun=define`, found `{}`", s));
+        }
+        Ok(AliasGroup(names))
+    }
+}
+impl fmt::Display for AliasGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join("="))
+    }
+}
+impl AliasGroup {
+    fn contains(&self, name: &str) -> bool {
+        self.0.iter().any(|alias| alias == name)
+    }
+}
+
+impl Sexpr<'_> {
+    /// Whether this is an atom named `name`, or named one of the aliases of `name`
+    pub fn is_named_as(&self, name: &str, aliases: &[AliasGroup]) -> bool {
+        self.is_named(name) || aliases.iter()
+            .filter(|group| group.contains(name))
+            .any(|group| group.0.iter().any(|alias| self.is_named(alias)))
+    }
+}
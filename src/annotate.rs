@@ -0,0 +1,112 @@
+use std::fmt;
+use crate::sexpr::{Sexpr, FormatArgs, SexprWrite};
+
+/// Collects formatted output, remembering the byte offset at which each node starts
+struct Annotator {
+    out: String,
+    starts: Vec<(usize, String)>,
+}
+impl fmt::Write for Annotator {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.out.push_str(s);
+        Ok(())
+    }
+}
+impl SexprWrite for Annotator {
+    fn node_start(&mut self, path: &[usize]) {
+        self.starts.push((self.out.len(), format_path(path)));
+    }
+}
+
+/// Writes a path in the form `.2.1.0`, with the root being `.`
+pub fn format_path(path: &[usize]) -> String {
+    if path.is_empty() {
+        return ".".to_string();
+    }
+    path.iter().map(|i| format!(".{}", i)).collect()
+}
+
+/// Formats `sexpr`, following each line with a comment holding the path of the first node that starts on it
+pub fn annotate(sexpr: &Sexpr<'_>, fmt_args: FormatArgs) -> Result<String, fmt::Error> {
+    let mut annotator = Annotator { out: String::new(), starts: Vec::new() };
+    sexpr.write_helper(&mut annotator, fmt_args, &mut Vec::new())?;
+    // widths are in characters, so that the comments line up for multi-byte atoms too
+    let width = annotator.out.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+    let mut starts = annotator.starts.iter().peekable();
+    let mut result = String::new();
+    let mut line_start = 0;
+    for line in annotator.out.split('\n') {
+        let line_end = line_start + line.len();
+        // consume every node that starts on this line, keeping the first
+        let mut first = None;
+        while let Some((offset, path)) = starts.peek() {
+            if *offset > line_end { break; }
+            first = first.or(Some(path));
+            starts.next();
+        }
+        result.push_str(line);
+        if let Some(path) = first {
+            result.push_str(&" ".repeat(width - line.chars().count()));
+            result.push_str("  ; ");
+            result.push_str(path);
+        }
+        result.push('\n');
+        line_start = line_end + 1;
+    }
+    Ok(result)
+}
+
+/// Collects formatted output, remembering where each compound broken over several lines ends
+struct SizeAnnotator {
+    out: String,
+    open: Vec<(Vec<usize>, bool)>,
+    ends: Vec<(usize, Vec<usize>)>,
+}
+impl fmt::Write for SizeAnnotator {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.out.push_str(s);
+        Ok(())
+    }
+}
+impl SexprWrite for SizeAnnotator {
+    fn open_group(&mut self, path: &[usize], multiline: bool) {
+        self.open.push((path.to_vec(), multiline));
+    }
+    fn close_group(&mut self) {
+        if let Some((path, true)) = self.open.pop() {
+            self.ends.push((self.out.len(), path));
+        }
+    }
+}
+
+/// Writes a number of nodes like `12 nodes` or `1.2k nodes`
+pub fn format_size(nodes: usize) -> String {
+    match nodes {
+        1 => "1 node".to_string(),
+        _ if nodes < 1_000 => format!("{} nodes", nodes),
+        _ if nodes < 1_000_000 => format!("{:.1}k nodes", nodes as f64 / 1e3),
+        _ => format!("{:.1}M nodes", nodes as f64 / 1e6),
+    }
+}
+
+/// Formats `sexpr`, ending it with a comment like `; 1.2k nodes` holding its size
+/// with a threshold, each compound broken over several lines with at least that many nodes gets one after its `)` too
+pub fn annotate_sizes(sexpr: &Sexpr<'_>, fmt_args: FormatArgs, threshold: Option<usize>) -> Result<String, fmt::Error> {
+    let mut annotator = SizeAnnotator { out: String::new(), open: Vec::new(), ends: Vec::new() };
+    sexpr.write_helper(&mut annotator, fmt_args, &mut Vec::new())?;
+    let mut out = annotator.out;
+    let mut sizes: Vec<(usize, usize)> = match threshold {
+        Some(threshold) => annotator.ends.iter()
+            // only a `)` ending its line has room for a comment after it
+            .filter(|(offset, path)| !path.is_empty() && out[*offset..].starts_with('\n'))
+            .filter_map(|(offset, path)| Some((*offset, sexpr.get_path(path)?.node_count())))
+            .filter(|&(_, nodes)| nodes >= threshold)
+            .collect(),
+        None => Vec::new(),
+    };
+    sizes.push((out.len(), sexpr.node_count()));
+    for &(offset, nodes) in sizes.iter().rev() {
+        out.insert_str(offset, &format!(" ; {}", format_size(nodes)));
+    }
+    Ok(out)
+}
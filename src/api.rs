@@ -0,0 +1,105 @@
+use std::io::{self, BufRead, Write};
+use serde_json::{json, Value};
+use sexpr_fmt::textdiff;
+use crate::diagnostic::Diagnostic;
+use crate::lsp;
+
+/// The version of the json schema of `--api`, which `--api-version` gives
+/// it only goes up when the schema changes in a way that would break a client written for the one before
+pub const API_VERSION: u64 = 1;
+
+/// The most lines (of a text and its formatted text together) which are diffed into an edit for each changed part
+const MAX_DIFF_LINES: usize = 20_000;
+
+/// A request to format some text, read from a line like
+/// `{"apiVersion": 1, "id": 7, "path": "a.scm", "text": "(f  x)", "options": {"complexity-threshold": 2}, "range": ...}`
+/// only the text is needed: the id is given back in the response, the path is used for the overrides which match it,
+/// the options are settings like those of `--override`, and the range (if any) is the part of the text to format,
+/// like `{"start": {"line": 0, "character": 0}, "end": {"line": 3, "character": 0}}`
+pub struct Request {
+    pub path: Option<String>,
+    pub text: String,
+    pub options: Value,
+    /// the lines of the range, counting from 1 and including both ends
+    pub lines: Option<(usize, usize)>,
+}
+
+/// Formats the text of a request
+pub trait Formatter: Fn(&Request) -> Result<String, Diagnostic> {}
+impl<F: Fn(&Request) -> Result<String, Diagnostic>> Formatter for F {}
+
+/// What `--api-version` prints, for an extension to check that it can talk to us before sending any requests
+pub fn handshake() -> Value {
+    json!({ "apiVersion": API_VERSION, "version": env!("CARGO_PKG_VERSION") })
+}
+
+/// Answer each request read from stdin, one on each line, with a response on a line of stdout, until stdin ends
+/// a response is `{"apiVersion": 1, "id": 7, "edits": [{"range": ..., "newText": "..."}, ...]}`, with an edit for
+/// each run of lines that formatting changes (none if the text is already formatted), or, if the text can not be formatted,
+/// `{"apiVersion": 1, "id": 7, "error": {"message": "...", "range": ...}}`, where the range is the place of the error, if it has one
+/// ranges count lines from 0 and characters in utf-16 code units, like VS Code does
+pub fn serve(format: impl Formatter) -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(output, "{}", answer(&format, &line))?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn answer(format: &impl Formatter, line: &str) -> Value {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(error) => return error_response(&Value::Null, format!("the request is not json: {}", error), Value::Null),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    if let Some(version) = request.get("apiVersion").filter(|&version| *version != json!(API_VERSION)) {
+        return error_response(&id, format!("unsupported api version {}, expected {}", version, API_VERSION), Value::Null);
+    }
+    let text = match request.get("text").and_then(Value::as_str) {
+        Some(text) => text,
+        None => return error_response(&id, "expected the text to format as the string `text`".to_string(), Value::Null),
+    };
+    let request = Request {
+        path: request.get("path").and_then(Value::as_str).map(str::to_string),
+        text: text.to_string(),
+        options: request.get("options").cloned().unwrap_or_else(|| json!({})),
+        lines: request.get("range").map(lsp::lines_of),
+    };
+    match format(&request) {
+        Ok(formatted) => json!({ "apiVersion": API_VERSION, "id": id, "edits": edits(text, &formatted) }),
+        Err(diagnostic) => {
+            let range = diagnostic.position.map_or(Value::Null, |(line, column)| lsp::range_at(text, line, column));
+            error_response(&id, diagnostic.message, range)
+        }
+    }
+}
+
+fn error_response(id: &Value, message: String, range: Value) -> Value {
+    json!({ "apiVersion": API_VERSION, "id": id, "error": { "message": message, "range": range } })
+}
+
+/// The edits turning `text` into `formatted`, one for each run of lines which differs,
+/// or a single edit replacing all of it when there are too many lines to diff cheaply
+fn edits(text: &str, formatted: &str) -> Vec<Value> {
+    if text == formatted {
+        return Vec::new();
+    }
+    let old: Vec<&str> = text.split_inclusive('\n').collect();
+    let new: Vec<&str> = formatted.split_inclusive('\n').collect();
+    if old.len() + new.len() > MAX_DIFF_LINES {
+        return vec![json!({ "range": { "start": lsp::position(0, 0), "end": lsp::end_of(text) }, "newText": formatted })];
+    }
+    textdiff::line_hunks(&old, &new).iter().map(|hunk| {
+        let end = hunk.old_start + hunk.removed.len();
+        // the last line may not end with a newline, so there may be no start of a line after it
+        let end = if end < old.len() { lsp::position(end, 0) } else { lsp::end_of(text) };
+        json!({ "range": { "start": lsp::position(hunk.old_start, 0), "end": end }, "newText": hunk.inserted.concat() })
+    }).collect()
+}
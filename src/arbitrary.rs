@@ -0,0 +1,27 @@
+use proptest::prelude::*;
+use crate::sexpr::{Sexpr, PREFIXES};
+
+impl Sexpr<'static> {
+    /// A proptest strategy for random sexprs that the parser can read back,
+    /// made of atoms, block comments, prefixed forms, and compounds (in parens or brackets) nested a few levels deep
+    pub fn arbitrary() -> impl Strategy<Value = Sexpr<'static>> {
+        // atoms may not start with a reader prefix or `#|`, which would be read as something else
+        let symbol = "[a-zA-Z0-9_+*/<>=!?:.-][a-zA-Z0-9_+*/<>=!?:.'-]{0,7}";
+        // strings, byte vectors, and `|symbols|`, which hold spaces but are still one atom
+        let string = "\"[a-z ]{0,12}\"";
+        let bytes = "#u8\\(([0-9]{1,3}( [0-9]{1,3}){0,3})?\\)";
+        let quoted = ":?\\|([a-z ()]|\\\\\\|){0,8}\\|";
+        let atom = prop_oneof![6 => symbol, 1 => string, 1 => bytes, 1 => quoted].prop_map(Sexpr::atom);
+        let comment = "[a-z ]{0,12}".prop_map(|text| Sexpr::comment(format!("#| {} |#", text)));
+        atom.prop_recursive(4, 64, 6, move |inner| {
+            // some lists are written in square brackets, like `[x 1]`
+            let compound = (inner.clone(), prop::collection::vec(prop_oneof![4 => inner, 1 => comment.clone()], 0..6), prop::bool::weighted(0.2))
+                .prop_map(|(head, args, brackets)| Sexpr::compound(head, args).with_brackets(brackets));
+            prop_oneof![
+                4 => compound.clone(),
+                // a prefix only applies to the compound (or prefixed form) right after it
+                1 => (prop::sample::select(PREFIXES), compound).prop_map(|(prefix, sexpr)| Sexpr::prefixed(prefix, sexpr)),
+            ]
+        })
+    }
+}
@@ -0,0 +1,70 @@
+use crate::prelude::*;
+use alloc::borrow::Cow;
+use crate::numbers;
+
+/// The broad class of an atom, judged from its text
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AtomKind {
+    /// a plain symbol like `forall` or `x`
+    Symbol,
+    /// a number like `42`, `-1.5`, `1/3`, `#xff`, or `#b1010`
+    Number,
+    /// a quoted string like `"hello"`
+    String,
+    /// a keyword like `:named`
+    Keyword,
+}
+impl AtomKind {
+    /// The kind of the atom with the given text
+    pub fn of(text: &str) -> AtomKind {
+        if text.starts_with('"') {
+            AtomKind::String
+        } else if text.starts_with(':') {
+            AtomKind::Keyword
+        } else if numbers::NumberKind::of(text).is_some() {
+            AtomKind::Number
+        } else {
+            AtomKind::Symbol
+        }
+    }
+}
+
+/// The text of a quoted string atom, without its quotes and escapes
+pub fn unquoted(text: &str) -> Option<Cow<'_, str>> {
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    if !inner.contains('\\') {
+        return Some(Cow::Borrowed(inner));
+    }
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => out.extend(chars.next()),
+            _ => out.push(ch),
+        }
+    }
+    Some(Cow::Owned(out))
+}
+
+/// Where an atom is being printed
+#[derive(Copy, Clone, Debug)]
+pub struct AtomContext<'p> {
+    /// the path from the root to the atom, where the head of a compound is index 0
+    pub path: &'p [usize],
+    /// the number of spaces that lines are currently indented by
+    pub depth: usize,
+}
+impl AtomContext<'_> {
+    /// Whether the atom is the head of a compound expression
+    pub fn is_head(&self) -> bool {
+        self.path.last() == Some(&0)
+    }
+}
+
+/// Decides how each atom is rendered, so that atoms can be redacted, shortened, or decorated
+/// without changing the layout code
+/// set `FormatArgs::atom_printer` to use one
+pub trait AtomPrinter {
+    /// The text to write in place of the atom `text`
+    fn print_atom<'t>(&self, text: &'t str, kind: AtomKind, context: &AtomContext<'_>) -> Cow<'t, str>;
+}
@@ -0,0 +1,58 @@
+use crate::prelude::*;
+use crate::sexpr::Sexpr;
+
+/// An s-expression which owns all of its text, rather than borrowing it from the input it was read from
+/// one can be made from a borrowed `Sexpr` with `Sexpr::into_owned`, and turned back with `Sexpr::borrowed`
+pub type SexprOwned = Sexpr<'static>;
+
+/// Builds a list one element at a time, for programs which make their own expressions to pretty print
+///
+/// ```
+/// use sexpr_fmt::builder::SexprBuilder;
+///
+/// let sexpr = SexprBuilder::new()
+///     .push(SexprBuilder::atom("assert"))
+///     .push(SexprBuilder::list(vec![SexprBuilder::atom("="), SexprBuilder::atom("x"), SexprBuilder::atom("1")]))
+///     .build();
+/// assert_eq!(sexpr.to_compact_string(), "(assert (= x 1))");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SexprBuilder {
+    elements: Vec<SexprOwned>,
+}
+impl SexprBuilder {
+    /// Start an empty list
+    pub fn new() -> SexprBuilder {
+        SexprBuilder::default()
+    }
+    /// An atom with the given text
+    pub fn atom(text: impl Into<String>) -> SexprOwned {
+        Sexpr::atom(text.into())
+    }
+    /// The list of `elements`, whose first element is its head, or `()` if there are none
+    pub fn list(elements: impl IntoIterator<Item = SexprOwned>) -> SexprOwned {
+        let mut elements = elements.into_iter();
+        match elements.next() {
+            Some(head) => Sexpr::compound(head, elements.collect()),
+            None => Sexpr::compound(Sexpr::blank(), Vec::new()),
+        }
+    }
+    /// Add an element to the end of the list, such as an atom or another built list
+    pub fn push(mut self, element: impl Into<SexprOwned>) -> SexprBuilder {
+        self.elements.push(element.into());
+        self
+    }
+    /// Add an atom with the given text to the end of the list
+    pub fn push_atom(self, text: impl Into<String>) -> SexprBuilder {
+        self.push(SexprBuilder::atom(text))
+    }
+    /// Finish the list
+    pub fn build(self) -> SexprOwned {
+        SexprBuilder::list(self.elements)
+    }
+}
+impl From<SexprBuilder> for SexprOwned {
+    fn from(builder: SexprBuilder) -> SexprOwned {
+        builder.build()
+    }
+}
@@ -0,0 +1,245 @@
+use crate::prelude::*;
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+use crate::aliases::AliasGroup;
+use core::cmp::Reverse;
+use alloc::collections::{BTreeMap, BinaryHeap};
+
+impl Sexpr<'_> {
+    /// Write this sexpr on a single line, with a single space between each element
+    pub fn to_compact_string(&self) -> String {
+        let mut out = String::new();
+        self.write_compact(&mut out);
+        out
+    }
+    fn write_compact(&self, out: &mut String) {
+        match self.kind {
+            Atom(ref text) | Comment(ref text) => out.push_str(text),
+            Prefixed(prefix, ref sexpr) => {
+                out.push_str(prefix);
+                sexpr.write_compact(out);
+            }
+            Compound(ref head, ref args) => {
+                out.push(if self.is_bracketed() { '[' } else { '(' });
+                head.write_compact(out);
+                let mut previous = &**head;
+                for arg in args.iter() {
+                    end_line_comment(previous, out);
+                    out.push(' ');
+                    arg.write_compact(out);
+                    previous = arg;
+                }
+                end_line_comment(previous, out);
+                out.push(if self.is_bracketed() { ']' } else { ')' });
+            }
+        }
+    }
+    /// Write this sexpr on a single line with as little whitespace as it can be read back from,
+    /// leaving out the spaces next to parens
+    pub fn to_minified_string(&self) -> String {
+        let mut out = String::new();
+        self.write_minified(&mut out);
+        out
+    }
+    fn write_minified(&self, out: &mut String) {
+        match self.kind {
+            Atom(ref text) | Comment(ref text) => out.push_str(text),
+            Prefixed(prefix, ref sexpr) => {
+                out.push_str(prefix);
+                sexpr.write_minified(out);
+            }
+            Compound(ref head, ref args) => {
+                out.push(if self.is_bracketed() { '[' } else { '(' });
+                head.write_minified(out);
+                let mut previous = &**head;
+                for arg in args.iter() {
+                    // an atom would run into whatever comes after it, unless that starts with a paren
+                    if matches!(previous.kind, Atom(_)) && !matches!(arg.kind, Compound(..)) {
+                        out.push(' ');
+                    }
+                    end_line_comment(previous, out);
+                    arg.write_minified(out);
+                    previous = arg;
+                }
+                end_line_comment(previous, out);
+                out.push(if self.is_bracketed() { ']' } else { ')' });
+            }
+        }
+    }
+    /// Sort the arguments of every form whose head is one of `heads`, such as `and` or `set`,
+    /// into the order of their compact text
+    /// forms with comments among their arguments are left alone, so the comments stay where they were written
+    pub fn sort_args(&mut self, heads: &[String], aliases: &[AliasGroup]) {
+        match self.kind {
+            Compound(ref mut head, ref mut args) => {
+                head.sort_args(heads, aliases);
+                for arg in args.iter_mut() {
+                    arg.sort_args(heads, aliases);
+                }
+                if heads.iter().any(|name| head.is_named_as(name, aliases)) && !args.iter().any(Sexpr::is_comment) {
+                    sort_by_text(args);
+                }
+            }
+            Prefixed(_, ref mut sexpr) => sexpr.sort_args(heads, aliases),
+            Atom(_) | Comment(_) => {}
+        }
+    }
+    /// The head of this form, if it is an atom
+    pub fn head_name(&self) -> Option<&str> {
+        match self.kind {
+            Compound(ref head, _) => match head.kind {
+                Atom(ref text) => Some(text),
+                _ => None,
+            },
+            Prefixed(..) | Atom(_) | Comment(_) => None,
+        }
+    }
+}
+
+/// End the line after `sexpr` if it is a line comment, which would otherwise swallow what comes after it
+fn end_line_comment(sexpr: &Sexpr<'_>, out: &mut String) {
+    if sexpr.is_line_comment() {
+        out.push('\n');
+    }
+}
+
+/// Sort each run of consecutive top-level forms with the same head, if it is one of `heads`,
+/// such as a block of `declare-fun`s, into the order of their compact text
+/// forms with other heads are left where they are, and act as a barrier between runs
+pub fn sort_toplevel(forms: &mut [Sexpr<'_>], heads: &[String], aliases: &[AliasGroup]) {
+    let is_sortable = |sexpr: &Sexpr<'_>| match sexpr.kind {
+        Compound(ref head, _) => heads.iter().any(|name| head.is_named_as(name, aliases)),
+        Prefixed(..) | Atom(_) | Comment(_) => false,
+    };
+    let mut start = 0;
+    while start < forms.len() {
+        let mut end = start + 1;
+        if is_sortable(&forms[start]) {
+            let head = forms[start].head_name().map(str::to_string);
+            while end < forms.len() && is_sortable(&forms[end]) && forms[end].head_name().map(str::to_string) == head {
+                end += 1;
+            }
+            sort_by_text(&mut forms[start..end]);
+        }
+        start = end;
+    }
+}
+
+/// Stably sort `sexprs` by their compact text
+fn sort_by_text(sexprs: &mut [Sexpr<'_>]) {
+    sexprs.sort_by_cached_key(Sexpr::to_compact_string);
+}
+
+/// The heads of the top-level forms which define a name, in the common dialects
+pub const DEFINITION_FORMS: &[&str] = &["declare-fun", "declare-const", "declare-sort", "declare-datatype",
+    "define-fun", "define-fun-rec", "define-sort", "define-const", "define", "defun", "defmacro", "defvar",
+    "defparameter", "defconst"];
+
+/// Reorder the top-level `forms` so that every definition (a form with one of `heads`, like `define-fun`)
+/// comes before the forms which refer to the name it defines, moving as few forms as possible
+/// every other form stays in the same order relative to the others, since the order of commands matters,
+/// and comments stay right before the form after them
+/// names are defined like `(define-fun name ...)` or `(define (name args...) ...)`
+/// if the definitions refer to each other in a cycle, the forms in the cycle are left in their original order
+pub fn sort_definitions(forms: &mut Vec<Sexpr<'_>>, heads: &[String], aliases: &[AliasGroup]) {
+    // each comment moves along with the form after it
+    let mut units: Vec<Vec<Sexpr<'_>>> = Vec::new();
+    let mut pending = Vec::new();
+    for form in forms.drain(..) {
+        let is_comment = form.is_comment();
+        pending.push(form);
+        if !is_comment {
+            units.push(core::mem::take(&mut pending));
+        }
+    }
+    let defined: Vec<Option<String>> = units.iter()
+        .map(|unit| unit.last().and_then(|form| defined_name(form, heads, aliases)))
+        .collect();
+    let definers: BTreeMap<&str, usize> = defined.iter()
+        .enumerate()
+        .filter_map(|(i, name)| Some((name.as_deref()?, i)))
+        .collect();
+    // the units which have to come after each unit, and how many units each one has to come after
+    let mut before: Vec<Vec<usize>> = vec![Vec::new(); units.len()];
+    let mut waiting_on = vec![0usize; units.len()];
+    let mut previous_command = None;
+    for (i, unit) in units.iter().enumerate() {
+        let mut atoms = Vec::new();
+        for form in unit.iter() {
+            form.collect_atoms(&mut atoms);
+        }
+        let mut dependencies: Vec<usize> = atoms.iter()
+            .filter_map(|atom| definers.get(atom.as_str()).copied())
+            .filter(|&j| j != i)
+            .collect();
+        if defined[i].is_none() {
+            dependencies.extend(previous_command);
+            previous_command = Some(i);
+        }
+        dependencies.sort_unstable();
+        dependencies.dedup();
+        for j in dependencies {
+            before[j].push(i);
+            waiting_on[i] += 1;
+        }
+    }
+    // repeatedly take the earliest unit which is not waiting on any others
+    let mut ready: BinaryHeap<Reverse<usize>> = (0..units.len()).filter(|&i| waiting_on[i] == 0).map(Reverse).collect();
+    let mut placed = vec![false; units.len()];
+    let mut order = Vec::with_capacity(units.len());
+    while order.len() < units.len() {
+        let next = match ready.pop() {
+            Some(Reverse(next)) => next,
+            // a cycle: take the earliest unit left
+            None => (0..units.len()).find(|&i| !placed[i]).expect("some unit is left"),
+        };
+        if placed[next] { continue; }
+        placed[next] = true;
+        order.push(next);
+        for &later in before[next].iter() {
+            waiting_on[later] = waiting_on[later].saturating_sub(1);
+            if waiting_on[later] == 0 && !placed[later] {
+                ready.push(Reverse(later));
+            }
+        }
+    }
+    let mut units: Vec<Option<Vec<Sexpr<'_>>>> = units.into_iter().map(Some).collect();
+    for i in order {
+        forms.extend(units[i].take().expect("each unit is placed once"));
+    }
+    forms.extend(pending);
+}
+
+/// The name defined by `form`, if its head is one of `heads`
+fn defined_name(form: &Sexpr<'_>, heads: &[String], aliases: &[AliasGroup]) -> Option<String> {
+    match form.kind {
+        Compound(ref head, ref args) if heads.iter().any(|name| head.is_named_as(name, aliases)) => {
+            match args.first()?.kind {
+                Atom(ref name) => Some(name.to_string()),
+                Compound(ref name, _) => match name.kind {
+                    Atom(ref name) => Some(name.to_string()),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl Sexpr<'_> {
+    /// Push the text of every atom in this sexpr onto `atoms`
+    fn collect_atoms(&self, atoms: &mut Vec<String>) {
+        match self.kind {
+            Atom(ref text) => atoms.push(text.to_string()),
+            Prefixed(_, ref sexpr) => sexpr.collect_atoms(atoms),
+            Compound(ref head, ref args) => {
+                head.collect_atoms(atoms);
+                for arg in args.iter() {
+                    arg.collect_atoms(atoms);
+                }
+            }
+            Comment(_) => {}
+        }
+    }
+}
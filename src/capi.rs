@@ -0,0 +1,128 @@
+//! The C interface, for editors and tools written in C or C++ to embed the formatter
+//! build it with `cargo rustc --release --lib --features capi --crate-type cdylib` (or `staticlib`),
+//! and include `include/sexpr_fmt.h`, which is written again by every build with the feature
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use crate::sexpr::{Sexpr, FormatArgs};
+
+/// The input was formatted
+pub const SEXPRFMT_OK: c_int = 0;
+/// The input or the place to put the output was null, or the input was not utf-8
+pub const SEXPRFMT_BAD_ARGUMENT: c_int = 1;
+/// The input could not be read as s-expressions
+pub const SEXPRFMT_PARSE_ERROR: c_int = 2;
+
+/// The layout options, which `sexprfmt_default_options` gives the defaults of
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SexprFmtOptions {
+    /// the nesting depth of an s-expression to display on a single line
+    pub complexity_threshold: u32,
+    /// squish the arguments of quantifiers onto the same line
+    pub short_quantifiers: bool,
+    /// keep the unquoted fragments (`,x` and `,@x`) of quasiquoted templates on one line
+    pub inline_unquoted: bool,
+    /// re-wrap the prose of block comments to the comment width
+    pub reflow_comments: bool,
+    /// the width to wrap block comments to
+    pub comment_width: usize,
+    /// the number of columns a tab stands for
+    pub tab_width: usize,
+    /// right-align the numbers in the columns of runs of rows like `(at 1.0 2.54)`, and of the rows nested in them
+    pub align_numbers: bool,
+    /// keep keywords like `:key` on the same line as the value after them
+    pub pair_keywords: bool,
+    /// line up the values in the binding lists of forms like `let`
+    pub align_bindings: bool,
+    /// the widest a line may be, or 0 for no limit
+    pub max_width: usize,
+}
+impl SexprFmtOptions {
+    fn format_args(&self) -> FormatArgs<'static> {
+        let mut args = FormatArgs::new();
+        args.complexity_threshold = self.complexity_threshold;
+        args.short_quantifiers = self.short_quantifiers;
+        args.inline_unquoted = self.inline_unquoted;
+        args.reflow_comments = self.reflow_comments;
+        args.comment_width = self.comment_width;
+        args.tab_width = self.tab_width;
+        args.align_numbers = self.align_numbers;
+        args.pair_keywords = self.pair_keywords;
+        args.align_bindings = self.align_bindings;
+        args.max_width = if self.max_width == 0 { None } else { Some(self.max_width) };
+        args
+    }
+}
+
+/// The options the command line uses when it is given no flags
+#[no_mangle]
+pub extern "C" fn sexprfmt_default_options() -> SexprFmtOptions {
+    let args = FormatArgs::new();
+    SexprFmtOptions {
+        complexity_threshold: args.complexity_threshold,
+        short_quantifiers: args.short_quantifiers,
+        inline_unquoted: args.inline_unquoted,
+        reflow_comments: args.reflow_comments,
+        comment_width: args.comment_width,
+        tab_width: args.tab_width,
+        align_numbers: args.align_numbers,
+        pair_keywords: args.pair_keywords,
+        align_bindings: args.align_bindings,
+        max_width: args.max_width.unwrap_or(0),
+    }
+}
+
+/// A copy of `text` for C, which `sexprfmt_free` frees
+fn to_c(text: String) -> *mut c_char {
+    // the reader stops at a nul, so no output has one, but a message could quote one from the input
+    CString::new(text.replace('\0', "\\0")).expect("the nuls were replaced").into_raw()
+}
+
+/// Format every top-level form of the nul-terminated utf-8 `input`, each on its own line,
+/// with `options` (or the defaults if it is null)
+/// on success, `*out` is set to the formatted text and `SEXPRFMT_OK` is returned;
+/// otherwise `*out` is set to a message saying what went wrong (unless `out` is null), and an error code is returned
+/// `*out` must be freed with `sexprfmt_free`
+///
+/// # Safety
+/// `input` must be null or a nul-terminated string, `options` must be null or point to options,
+/// and `out` must be null or point to where a pointer can be written
+#[no_mangle]
+pub unsafe extern "C" fn sexprfmt_format(input: *const c_char, options: *const SexprFmtOptions, out: *mut *mut c_char) -> c_int {
+    if out.is_null() {
+        return SEXPRFMT_BAD_ARGUMENT;
+    }
+    let (code, text) = if input.is_null() {
+        (SEXPRFMT_BAD_ARGUMENT, "the input is null".to_string())
+    } else {
+        let options = if options.is_null() { sexprfmt_default_options() } else { *options };
+        match CStr::from_ptr(input).to_str() {
+            Err(_) => (SEXPRFMT_BAD_ARGUMENT, "the input is not utf-8".to_string()),
+            Ok(input) => match Sexpr::parse_many(input) {
+                Err(error) => (SEXPRFMT_PARSE_ERROR, error.to_string()),
+                Ok(forms) => {
+                    let args = options.format_args();
+                    let mut formatted = String::new();
+                    for form in forms {
+                        formatted.push_str(&form.format(args));
+                        formatted.push('\n');
+                    }
+                    (SEXPRFMT_OK, formatted)
+                }
+            },
+        }
+    };
+    *out = to_c(text);
+    code
+}
+
+/// Free a string given out by `sexprfmt_format`; freeing null does nothing
+///
+/// # Safety
+/// `text` must be null or a string from `sexprfmt_format` which has not been freed yet
+#[no_mangle]
+pub unsafe extern "C" fn sexprfmt_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
@@ -0,0 +1,49 @@
+use crate::prelude::*;
+use core::fmt;
+use core::str::FromStr;
+
+/// A frozen version of the layout algorithm, written `X.Y` after the release that introduced it
+/// pinning one lets a project upgrade the formatter without reformatting every file at once
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LayoutVersion {
+    /// the layout of the first releases
+    /// the first argument of every `exists` form is kept on the head's line, even without `--short-quantifiers`
+    V0_1,
+    /// the first argument of `exists` is only kept on the head's line when asked for, like for `forall`
+    V0_2,
+    /// the term of an SMT-LIB annotation like `(! term :named t)` is kept on the head's line,
+    /// and its attributes are written one `:key value` pair to a line, with the values lined up and each kept on one line
+    V0_3,
+}
+impl LayoutVersion {
+    /// the layout used when no version is pinned
+    pub const CURRENT: LayoutVersion = LayoutVersion::V0_3;
+    /// every version that can be pinned, oldest first
+    pub const ALL: &'static [LayoutVersion] = &[LayoutVersion::V0_1, LayoutVersion::V0_2, LayoutVersion::V0_3];
+}
+impl Default for LayoutVersion {
+    fn default() -> LayoutVersion {
+        LayoutVersion::CURRENT
+    }
+}
+impl fmt::Display for LayoutVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutVersion::V0_1 => write!(f, "0.1"),
+            LayoutVersion::V0_2 => write!(f, "0.2"),
+            LayoutVersion::V0_3 => write!(f, "0.3"),
+        }
+    }
+}
+impl FromStr for LayoutVersion {
+    type Err = String;
+    fn from_str(s: &str) -> Result<LayoutVersion, String> {
+        LayoutVersion::ALL.iter()
+            .copied()
+            .find(|version| version.to_string() == s)
+            .ok_or_else(|| {
+                let known: Vec<String> = LayoutVersion::ALL.iter().map(ToString::to_string).collect();
+                format!("unknown layout version `{}`, expected one of: {}", s, known.join(", "))
+            })
+    }
+}
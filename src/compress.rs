@@ -0,0 +1,124 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How the bytes of a file or stream are compressed, like those of the `.smt2.gz` files of solver benchmarks
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// The bytes each kind of stream starts with
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+impl Compression {
+    /// The compression of `bytes`, from the magic number they start with
+    pub fn of_bytes(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+    /// The compression of a file called `path`, from its extension
+    pub fn of_path(path: &Path) -> Compression {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+    /// The compression of `bytes` read from `path`, from their magic number, or else from the extension of the path
+    pub fn detect(path: Option<&Path>, bytes: &[u8]) -> Compression {
+        match Compression::of_bytes(bytes) {
+            Compression::None => path.map_or(Compression::None, Compression::of_path),
+            compression => compression,
+        }
+    }
+    pub fn decompress(self, bytes: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+        let mut out = Vec::new();
+        match self {
+            Compression::None => return Ok(Cow::Borrowed(bytes)),
+            Compression::Gzip => flate2::read::MultiGzDecoder::new(bytes).read_to_end(&mut out)?,
+            Compression::Zstd => zstd::stream::read::Decoder::new(bytes)?.read_to_end(&mut out)?,
+        };
+        Ok(Cow::Owned(out))
+    }
+    pub fn compress(self, bytes: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            Compression::None => Ok(Cow::Borrowed(bytes)),
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish().map(Cow::Owned)
+            }
+            Compression::Zstd => zstd::stream::encode_all(bytes, zstd::DEFAULT_COMPRESSION_LEVEL).map(Cow::Owned),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Compression, String> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("unknown compression `{}`, expected one of: none, gzip, zstd", s)),
+        }
+    }
+}
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// `bytes` as text, decompressed if they are compressed, along with how they were compressed
+///
+/// ```
+/// use sexpr_fmt::compress::{decode, Compression};
+///
+/// let compressed = Compression::Gzip.compress(b"(f x)").unwrap();
+/// assert_eq!(decode(None, &compressed).unwrap(), ("(f x)".to_string(), Compression::Gzip));
+/// assert_eq!(decode(None, b"(f x)").unwrap(), ("(f x)".to_string(), Compression::None));
+/// ```
+pub fn decode(path: Option<&Path>, bytes: &[u8]) -> io::Result<(String, Compression)> {
+    let compression = Compression::detect(path, bytes);
+    let text = String::from_utf8(compression.decompress(bytes)?.into_owned())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))?;
+    Ok((text, compression))
+}
+
+/// Read `path` as text, decompressing it if it is compressed, along with how it was compressed
+pub fn read_file(path: &Path) -> io::Result<(String, Compression)> {
+    decode(Some(path), &fs::read(path)?)
+}
+
+/// Read `path` as text, decompressing it if it is compressed, like `fs::read_to_string`
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    read_file(path).map(|(text, _)| text)
+}
+
+/// Write `text` to `path`, compressed with `compression`
+pub fn write_file(path: &Path, text: &str, compression: Compression) -> io::Result<()> {
+    fs::write(path, compression.compress(text.as_bytes())?)
+}
+
+/// `path` without the extension of a compressed file, like `a.smt2` for `a.smt2.gz`,
+/// so that it is matched by the globs for the files it holds
+pub fn strip_extension(path: &str) -> &str {
+    path.strip_suffix(".gz").or_else(|| path.strip_suffix(".zst")).unwrap_or(path)
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The public files of the benchmark corpus, as the name to save each under and where to download it from,
+/// picked to cover the dialects we format: SMT-LIB problems, KiCad boards, and WebAssembly text
+const CORPUS: &[(&str, &str)] = &[
+    ("smt/quickstart.smt2", "https://raw.githubusercontent.com/cvc5/cvc5/main/examples/api/smtlib/quickstart.smt2"),
+    ("kicad/pic_programmer.kicad_pcb", "https://gitlab.com/kicad/code/kicad/-/raw/master/demos/pic_programmer/pic_programmer.kicad_pcb"),
+    ("kicad/video.kicad_pcb", "https://gitlab.com/kicad/code/kicad/-/raw/master/demos/video/video.kicad_pcb"),
+    ("wat/fac.wast", "https://raw.githubusercontent.com/WebAssembly/spec/main/test/core/fac.wast"),
+    ("wat/br_table.wast", "https://raw.githubusercontent.com/WebAssembly/spec/main/test/core/br_table.wast"),
+    ("wat/address.wast", "https://raw.githubusercontent.com/WebAssembly/spec/main/test/core/address.wast"),
+];
+
+/// Where the corpus is kept when no directory is given: the user's cache directory
+pub fn default_dir() -> Option<PathBuf> {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache.join("sexpr-fmt").join("corpus"))
+}
+
+/// Download each file of the corpus into `dir`, skipping the ones already there unless `refresh` is set
+/// a file which fails to download is reported, and the rest are still tried
+/// returns whether every file is now in `dir`
+pub fn fetch(dir: &Path, refresh: bool) -> Result<bool, Box<dyn Error>> {
+    let mut complete = true;
+    for (name, url) in CORPUS {
+        let path = dir.join(name);
+        if path.exists() && !refresh {
+            println!("{}: already downloaded", path.display());
+            continue;
+        }
+        match download(url) {
+            Ok(bytes) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, &bytes)?;
+                println!("{}: downloaded {} bytes", path.display(), bytes.len());
+            }
+            Err(e) => {
+                eprintln!("{}: could not download: {}", path.display(), e);
+                complete = false;
+            }
+        }
+    }
+    Ok(complete)
+}
+
+fn download(url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    ureq::get(url).call()?.into_reader().read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use crate::diagnostic::Diagnostic;
+
+/// The largest request we read, so that one request cannot take all of the memory
+const MAX_REQUEST: usize = 16 * 1024 * 1024;
+
+/// How the requests to a daemon, and its responses, are told apart from each other
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// each message ends with a NUL byte
+    Nul,
+    /// each message starts with a line giving its length in bytes
+    Length,
+}
+impl FromStr for Framing {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Framing, String> {
+        match s {
+            "nul" => Ok(Framing::Nul),
+            "length" => Ok(Framing::Length),
+            _ => Err(format!("unknown framing `{}`, expected one of: nul, length", s)),
+        }
+    }
+}
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Framing::Nul => write!(f, "nul"),
+            Framing::Length => write!(f, "length"),
+        }
+    }
+}
+
+/// Format each request read from stdin, writing the response to it on stdout, until stdin ends
+/// with `nul` framing, a request is the text to format and then a NUL byte,
+/// and a response is `ok` or `error` on a line, then the formatted text or the error, then a NUL byte
+/// with `length` framing, a request is its length in bytes on a line and then the text to format,
+/// and a response is `ok` or `error` and the length of what follows on a line, like `ok 16`, then the formatted text or the error
+pub fn serve(framing: Framing, format: impl Fn(&str) -> Result<String, Diagnostic>) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    while let Some(request) = read_request(&mut input, framing)? {
+        let response = match String::from_utf8(request) {
+            Ok(text) => format(&text).map_err(|error| error.to_string()),
+            Err(_) => Err("the request is not utf-8".to_string()),
+        };
+        let (status, body) = match response {
+            Ok(formatted) => ("ok", formatted),
+            Err(error) => ("error", error),
+        };
+        match framing {
+            Framing::Nul => write!(output, "{}\n{}\0", status, body)?,
+            Framing::Length => write!(output, "{} {}\n{}", status, body.len(), body)?,
+        }
+        // the editor is waiting for this response before it sends another request
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// The next request, or nothing at the end of the input
+fn read_request(input: &mut impl BufRead, framing: Framing) -> io::Result<Option<Vec<u8>>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let too_long = |length: usize| invalid(format!("the request of {} bytes is longer than the most we read, {} bytes", length, MAX_REQUEST));
+    match framing {
+        Framing::Nul => {
+            let mut request = Vec::new();
+            // one byte more than the most we read, for the NUL ending it
+            if io::Read::take(&mut *input, MAX_REQUEST as u64 + 1).read_until(0, &mut request)? == 0 {
+                return Ok(None);
+            }
+            // the last request may end with the input instead
+            if request.last() == Some(&0) {
+                request.pop();
+            } else if request.len() > MAX_REQUEST {
+                return Err(too_long(request.len()));
+            }
+            Ok(Some(request))
+        }
+        Framing::Length => {
+            let mut header = String::new();
+            // blank lines between requests are let through, for typing them in by hand
+            while header.trim().is_empty() {
+                header.clear();
+                if input.read_line(&mut header)? == 0 {
+                    return Ok(None);
+                }
+            }
+            let length: usize = header.trim().parse()
+                .map_err(|_| invalid(format!("expected the length of a request, found `{}`", header.trim())))?;
+            if length > MAX_REQUEST {
+                return Err(too_long(length));
+            }
+            let mut request = vec![0; length];
+            input.read_exact(&mut request)?;
+            Ok(Some(request))
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::borrow::Cow;
+use serde::de::{self, Deserialize, DeserializeSeed, Visitor};
+use crate::atom::unquoted;
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed};
+pub use crate::ser::{Error, Result};
+
+/// Read a value from the s-expression `sexpr`, written the way `ser::to_sexpr` writes it
+/// strings may borrow from the input that `sexpr` was read from
+pub fn from_sexpr<'de, T: Deserialize<'de>>(sexpr: &Sexpr<'de>) -> Result<T> {
+    T::deserialize(Deserializer { sexpr })
+}
+
+/// Read a value from the single s-expression in `text`
+pub fn from_str<'de, T: Deserialize<'de>>(text: &'de str) -> Result<T> {
+    let sexpr = Sexpr::parse(text).map_err(|error| Error(format!("could not read the s-expression: {}", error)))?;
+    from_sexpr(&sexpr)
+}
+
+fn is_keyword(sexpr: &Sexpr<'_>) -> bool {
+    matches!(sexpr.kind, Atom(ref text) if text.starts_with(':'))
+}
+
+/// Pair up the `:name value` fields of a struct
+fn fields<'s, 'de>(elements: &[&'s Sexpr<'de>]) -> Result<Vec<(&'s Sexpr<'de>, &'s Sexpr<'de>)>> {
+    elements.chunks(2)
+        .map(|pair| match *pair {
+            [key, value] if is_keyword(key) => Ok((key, value)),
+            _ => Err(Error(format!("expected a field like `:name value`, found `{}`", pair[0].to_compact_string()))),
+        })
+        .collect()
+}
+
+/// Reads a value from one s-expression
+pub struct Deserializer<'s, 'de> {
+    sexpr: &'s Sexpr<'de>,
+}
+impl<'s, 'de> Deserializer<'s, 'de> {
+    pub fn new(sexpr: &'s Sexpr<'de>) -> Deserializer<'s, 'de> {
+        Deserializer { sexpr }
+    }
+    fn unexpected(&self, expected: &str) -> Error {
+        Error(format!("expected {}, found `{}`", expected, self.sexpr.to_compact_string()))
+    }
+    /// The sexpr a prefix like `'` applies to, which is read the same as it
+    fn unprefixed(&self) -> &'s Sexpr<'de> {
+        let mut sexpr = self.sexpr;
+        while let Prefixed(_, ref inner) = sexpr.kind {
+            sexpr = inner;
+        }
+        sexpr
+    }
+    fn atom(&self) -> Option<&'s Cow<'de, str>> {
+        match self.unprefixed().kind {
+            Atom(ref text) => Some(text),
+            _ => None,
+        }
+    }
+    /// The elements of a list, including its head, leaving out comments
+    fn elements(&self) -> Option<Vec<&'s Sexpr<'de>>> {
+        match self.unprefixed().kind {
+            Compound(ref head, ref args) if head.is_blank() && args.is_empty() => Some(Vec::new()),
+            Compound(ref head, ref args) => Some(std::iter::once(&**head).chain(args.iter()).filter(|sexpr| !sexpr.is_comment()).collect()),
+            _ => None,
+        }
+    }
+    /// Visit the text of a string, or of a symbol, borrowing it from the input when it can
+    fn visit_text<V: Visitor<'de>>(&self, visitor: V, strip_colon: bool) -> Result<V::Value> {
+        let strip = |text: &'de str| if strip_colon { text.strip_prefix(':').unwrap_or(text) } else { text };
+        match self.atom().ok_or_else(|| self.unexpected("a string"))? {
+            Cow::Borrowed(text) => match unquoted(text) {
+                Some(Cow::Borrowed(inner)) => visitor.visit_borrowed_str(inner),
+                Some(Cow::Owned(inner)) => visitor.visit_string(inner),
+                None => visitor.visit_borrowed_str(strip(text)),
+            },
+            Cow::Owned(text) => match unquoted(text) {
+                Some(inner) => visitor.visit_str(&inner),
+                None if strip_colon => visitor.visit_str(text.strip_prefix(':').unwrap_or(text)),
+                None => visitor.visit_str(text),
+            },
+        }
+    }
+}
+
+impl<'s, 'de> de::Deserializer<'de> for Deserializer<'s, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if let Some(elements) = self.elements() {
+            return visitor.visit_seq(Seq(elements.into_iter()));
+        }
+        let text = match self.atom() {
+            Some(text) => text,
+            None => return Err(self.unexpected("a value")),
+        };
+        let numeric = text.starts_with(|ch: char| ch.is_ascii_digit() || ch == '-' || ch == '+' || ch == '.');
+        match &**text {
+            "true" => visitor.visit_bool(true),
+            "false" => visitor.visit_bool(false),
+            "nil" => visitor.visit_unit(),
+            _ if numeric => match (text.parse::<u64>(), text.parse::<i64>(), text.parse::<f64>()) {
+                (Ok(n), _, _) => visitor.visit_u64(n),
+                (_, Ok(n), _) => visitor.visit_i64(n),
+                (_, _, Ok(n)) => visitor.visit_f64(n),
+                _ => self.visit_text(visitor, false),
+            },
+            _ => self.visit_text(visitor, false),
+        }
+    }
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.visit_text(visitor, false)
+    }
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.visit_text(visitor, false)
+    }
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.visit_text(visitor, true)
+    }
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let elements = self.elements().ok_or_else(|| self.unexpected("a list of bytes"))?;
+        let bytes = elements.into_iter()
+            .map(|byte| match byte.kind {
+                Atom(ref text) => text.parse::<u8>().ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| self.unexpected("a list of bytes"))?;
+        visitor.visit_byte_buf(bytes)
+    }
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.atom() {
+            Some(text) if text == "nil" => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match (self.atom(), self.elements()) {
+            (Some(text), _) if text == "nil" => visitor.visit_unit(),
+            (_, Some(elements)) if elements.is_empty() => visitor.visit_unit(),
+            _ => Err(self.unexpected("`()`")),
+        }
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let elements = self.elements().ok_or_else(|| self.unexpected("a list"))?;
+        visitor.visit_seq(Seq(elements.into_iter()))
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let elements = self.elements().ok_or_else(|| self.unexpected("a list of `(key value)` entries"))?;
+        let entries = elements.into_iter()
+            .map(|entry| match Deserializer::new(entry).elements().as_deref() {
+                Some(&[key, value]) => Ok((key, value)),
+                _ => Err(Deserializer::new(entry).unexpected("an entry like `(key value)`")),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        visitor.visit_map(Map { entries: entries.into_iter(), value: None })
+    }
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        let mut elements = self.elements().ok_or_else(|| self.unexpected("a struct like `(Name :field value)`"))?;
+        // the head names the struct, unless it was left out
+        if elements.first().is_some_and(|head| head.is_atom() && !is_keyword(head)) {
+            elements.remove(0);
+        }
+        visitor.visit_map(Map { entries: fields(&elements)?.into_iter(), value: None })
+    }
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        if self.atom().is_some() {
+            return visitor.visit_enum(Enum { variant: self.unprefixed(), content: Vec::new() });
+        }
+        match self.elements() {
+            Some(elements) if elements.first().is_some_and(|head| head.is_atom()) => {
+                visitor.visit_enum(Enum { variant: elements[0], content: elements[1..].to_vec() })
+            }
+            _ => Err(self.unexpected("a variant like `Name` or `(Name value)`")),
+        }
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+    }
+}
+
+/// The elements of a list being read
+struct Seq<'s, 'de>(std::vec::IntoIter<&'s Sexpr<'de>>);
+impl<'s, 'de> de::SeqAccess<'de> for Seq<'s, 'de> {
+    type Error = Error;
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        self.0.next().map(|sexpr| seed.deserialize(Deserializer::new(sexpr))).transpose()
+    }
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+/// The keys and values of a map or struct being read
+struct Map<'s, 'de> {
+    entries: std::vec::IntoIter<(&'s Sexpr<'de>, &'s Sexpr<'de>)>,
+    value: Option<&'s Sexpr<'de>>,
+}
+impl<'s, 'de> de::MapAccess<'de> for Map<'s, 'de> {
+    type Error = Error;
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer::new(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let value = self.value.take().ok_or_else(|| Error("a map value was read before its key".to_string()))?;
+        seed.deserialize(Deserializer::new(value))
+    }
+}
+
+/// An enum variant being read: its name, and the values after it
+struct Enum<'s, 'de> {
+    variant: &'s Sexpr<'de>,
+    content: Vec<&'s Sexpr<'de>>,
+}
+impl<'s, 'de> de::EnumAccess<'de> for Enum<'s, 'de> {
+    type Error = Error;
+    type Variant = Enum<'s, 'de>;
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Enum<'s, 'de>)> {
+        let variant = seed.deserialize(Deserializer::new(self.variant))?;
+        Ok((variant, self))
+    }
+}
+impl<'s, 'de> de::VariantAccess<'de> for Enum<'s, 'de> {
+    type Error = Error;
+    fn unit_variant(self) -> Result<()> {
+        match self.content.first() {
+            None => Ok(()),
+            Some(extra) => Err(Error(format!("expected nothing after a unit variant, found `{}`", extra.to_compact_string()))),
+        }
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        match *self.content {
+            [value] => seed.deserialize(Deserializer::new(value)),
+            _ => Err(Error(format!("expected one value after the variant `{}`", self.variant.to_compact_string()))),
+        }
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Seq(self.content.into_iter()))
+    }
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        visitor.visit_map(Map { entries: fields(&self.content)?.into_iter(), value: None })
+    }
+}
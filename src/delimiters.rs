@@ -0,0 +1,273 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use crate::aliases::AliasGroup;
+use crate::sexpr::{block_comment_len, Sexpr, Span};
+use crate::sexpr::SexprKind::{Compound, Prefixed};
+
+/// The pairs of delimiters, opener first
+const PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+/// A list opened by one kind of delimiter and closed by another, like `(a b]`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// where the list is opened
+    pub open: Span,
+    /// where it is closed, by the wrong delimiter
+    pub close: Span,
+    /// the delimiter which opens the list
+    pub opener: char,
+    /// the delimiter which closes it
+    pub found: char,
+}
+impl Mismatch {
+    /// The delimiter which would close the list, which is the least change that repairs it
+    pub fn expected(&self) -> char {
+        closer_of(self.opener).expect("mismatches are opened by delimiters")
+    }
+}
+
+fn closer_of(opener: char) -> Option<char> {
+    PAIRS.iter().find(|&&(open, _)| open == opener).map(|&(_, close)| close)
+}
+
+/// What scanning the delimiters of a text finds
+#[derive(Default)]
+struct Scan {
+    mismatches: Vec<Mismatch>,
+    stray: Vec<Span>,
+    unclosed: Vec<(Span, char)>,
+    quoted_tabs: Vec<usize>,
+    brackets: Vec<usize>,
+}
+
+/// Go through the delimiters of `text`, skipping strings, `|symbols|`, and comments
+fn scan(text: &str) -> Scan {
+    let mut open: Vec<(usize, char)> = Vec::new();
+    let mut found = Scan::default();
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        match ch {
+            '"' | '|' => {
+                while let Some((i, next)) = chars.next() {
+                    if next == ch {
+                        break;
+                    } else if next == '\\' {
+                        chars.next();
+                    } else if next == '\t' {
+                        found.quoted_tabs.push(i);
+                    }
+                }
+            }
+            ';' => while chars.next_if(|&(_, next)| next != '\n').is_some() {},
+            '#' if text[idx..].starts_with("#|") => {
+                let end = block_comment_len(&text[idx..]).map_or(text.len(), |len| idx + len);
+                while chars.next_if(|&(i, _)| i < end).is_some() {}
+            }
+            _ if closer_of(ch).is_some() => {
+                if ch == '[' {
+                    found.brackets.push(idx);
+                }
+                open.push((idx, ch));
+            }
+            _ if PAIRS.iter().any(|&(_, close)| close == ch) => {
+                if ch == ']' {
+                    found.brackets.push(idx);
+                }
+                let close = Span { start: idx, end: idx + ch.len_utf8() };
+                let (start, opener) = match open.pop() {
+                    Some(opened) => opened,
+                    None => {
+                        found.stray.push(close);
+                        continue;
+                    }
+                };
+                if closer_of(opener) != Some(ch) {
+                    found.mismatches.push(Mismatch { open: Span { start, end: start + opener.len_utf8() }, close, opener, found: ch });
+                }
+            }
+            _ => {}
+        }
+    }
+    found.unclosed = open.into_iter().map(|(start, opener)| (Span { start, end: start + opener.len_utf8() }, opener)).collect();
+    found
+}
+
+/// Every list in `text` which is closed by a different kind of delimiter than it was opened by, in order
+/// strings, `|symbols|`, and comments are skipped, and closers with nothing open are left for the reader to complain about
+/// once a mismatch is found, its list counts as closed, as if it had been repaired
+pub fn mismatches(text: &str) -> Vec<Mismatch> {
+    scan(text).mismatches
+}
+
+/// Where each closing delimiter of `text` with no list open for it to close is, like the last `)` of `(a))`
+pub fn stray_closers(text: &str) -> Vec<Span> {
+    scan(text).stray
+}
+
+/// Where each list of `text` which is still open at its end is opened, outermost first, along with its opener
+pub fn unclosed(text: &str) -> Vec<(Span, char)> {
+    scan(text).unclosed
+}
+
+/// The offsets of the tabs written inside of the strings and `|symbols|` of `text`
+pub fn quoted_tabs(text: &str) -> Vec<usize> {
+    scan(text).quoted_tabs
+}
+
+/// `text` with the lists which are still open at its end closed, on a line of their own
+///
+/// ```
+/// use sexpr_fmt::delimiters::{close_unclosed, unclosed};
+///
+/// let text = "(define (f x) ; no closers\n  [g x";
+/// assert_eq!(close_unclosed(text, &unclosed(text)), "(define (f x) ; no closers\n  [g x\n])");
+/// ```
+pub fn close_unclosed(text: &str, unclosed: &[(Span, char)]) -> String {
+    let mut closed = text.to_string();
+    if unclosed.is_empty() {
+        return closed;
+    }
+    // a line comment at the very end would swallow the closers
+    if !closed.ends_with('\n') {
+        closed.push('\n');
+    }
+    closed.extend(unclosed.iter().rev().map(|&(_, opener)| closer_of(opener).expect("lists are opened by delimiters")));
+    closed
+}
+
+/// `text` with the closer of each of `mismatches` changed to the one its list was opened with
+///
+/// ```
+/// use sexpr_fmt::delimiters::{mismatches, repair};
+///
+/// let text = "(let [x (f y]] x)";
+/// assert_eq!(repair(text, &mismatches(text)), "(let [x (f y)] x)");
+/// ```
+pub fn repair(text: &str, mismatches: &[Mismatch]) -> String {
+    let mut repaired = String::with_capacity(text.len());
+    let mut copied = 0;
+    for mismatch in mismatches {
+        repaired.push_str(&text[copied..mismatch.close.start]);
+        repaired.push(mismatch.expected());
+        copied = mismatch.close.end;
+    }
+    repaired.push_str(&text[copied..]);
+    repaired
+}
+
+/// How the square brackets of the input are written out
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BracketStyle {
+    /// brackets are kept as they are, as part of the atoms they are written next to
+    Preserve,
+    /// every bracket is written as a paren, so that `[x 1]` is read as a list
+    Round,
+    /// like `Round`, but then the clauses of forms like `cond` and the bindings of forms like `let`
+    /// are written in brackets, the way Racket code is
+    Racket,
+}
+impl FromStr for BracketStyle {
+    type Err = String;
+    fn from_str(s: &str) -> Result<BracketStyle, String> {
+        match s {
+            "preserve" => Ok(BracketStyle::Preserve),
+            "round" => Ok(BracketStyle::Round),
+            "racket" => Ok(BracketStyle::Racket),
+            _ => Err(format!("unknown bracket style `{}`, expected one of: round, preserve, racket", s)),
+        }
+    }
+}
+impl fmt::Display for BracketStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BracketStyle::Preserve => write!(f, "preserve"),
+            BracketStyle::Round => write!(f, "round"),
+            BracketStyle::Racket => write!(f, "racket"),
+        }
+    }
+}
+
+/// `text` with each `[` and `]` which opens or closes a list written as a paren instead,
+/// leaving the ones inside of strings, `|symbols|`, and comments alone
+///
+/// ```
+/// use sexpr_fmt::delimiters::round_brackets;
+///
+/// assert_eq!(round_brackets("(let ([x \"[1]\"]) x) ; [sic]"), "(let ((x \"[1]\")) x) ; [sic]");
+/// ```
+pub fn round_brackets(text: &str) -> Cow<'_, str> {
+    let brackets = scan(text).brackets;
+    if brackets.is_empty() {
+        return Cow::Borrowed(text);
+    }
+    let mut rounded = text.as_bytes().to_vec();
+    for idx in brackets {
+        rounded[idx] = if rounded[idx] == b'[' { b'(' } else { b')' };
+    }
+    Cow::Owned(String::from_utf8(rounded).expect("brackets and parens are both one byte"))
+}
+
+/// The forms whose arguments are clauses written in brackets in Racket, like the `[else 0]` of `cond`,
+/// with the number of arguments before the clauses
+const CLAUSE_FORMS: &[(&str, usize)] = &[("cond", 0), ("case", 1), ("match", 1), ("match*", 1), ("syntax-rules", 1), ("syntax-case", 2)];
+
+/// The forms whose bindings are written in brackets in Racket, like the `[x 1]` of `(let ([x 1]) x)`
+const BINDING_FORMS: &[&str] = &[
+    "let", "let*", "letrec", "letrec*", "let-values", "let*-values", "letrec-values", "parameterize", "with-handlers",
+    "for", "for*", "for/list", "for*/list", "for/vector", "for/hash", "for/sum", "for/and", "for/or", "for/first",
+];
+
+/// `text`, which has no square brackets, with the clauses and bindings that Racket code writes in brackets written in them,
+/// like `(cond [(> x 0) 1] [else 0])` and `(let loop ([i 0]) ...)`
+/// if `text` cannot be read, it is kept as it is
+///
+/// ```
+/// use sexpr_fmt::delimiters::racket_brackets;
+///
+/// let text = "(let loop ((i 0)) (cond ((> i 9) i) (else (loop (+ i 1)))))";
+/// assert_eq!(racket_brackets(text, &[]), "(let loop ([i 0]) (cond [(> i 9) i] [else (loop (+ i 1))]))");
+/// ```
+pub fn racket_brackets(text: &str, aliases: &[AliasGroup]) -> String {
+    let forms = match Sexpr::parse_many(text) {
+        Ok(forms) => forms,
+        Err(_) => return text.to_string(),
+    };
+    let mut bracketed = Vec::new();
+    for form in &forms {
+        collect_bracketed(form, aliases, &mut bracketed);
+    }
+    let mut out = text.as_bytes().to_vec();
+    for span in bracketed {
+        out[span.start] = b'[';
+        out[span.end - 1] = b']';
+    }
+    String::from_utf8(out).expect("brackets and parens are both one byte")
+}
+
+/// The spans of the lists inside of `sexpr` which Racket writes in brackets
+fn collect_bracketed(sexpr: &Sexpr<'_>, aliases: &[AliasGroup], bracketed: &mut Vec<Span>) {
+    let is_list = |sexpr: &&Sexpr<'_>| matches!(sexpr.kind, Compound(..)) && !sexpr.is_blank();
+    match sexpr.kind {
+        Compound(ref head, ref args) => {
+            if let Some(&(_, skip)) = CLAUSE_FORMS.iter().find(|&&(name, _)| head.is_named_as(name, aliases)) {
+                bracketed.extend(args.iter().skip(skip).filter(is_list).filter_map(Sexpr::span));
+            } else if BINDING_FORMS.iter().any(|name| head.is_named_as(name, aliases)) {
+                // a named let, like `(let loop ((i 0)) ...)`, has its bindings after its name
+                let bindings = match args.first() {
+                    Some(name) if name.is_atom() => args.get(1),
+                    bindings => bindings,
+                };
+                if let Some(Sexpr { kind: Compound(ref first, ref rest), .. }) = bindings {
+                    bracketed.extend(std::iter::once(&**first).chain(rest).filter(is_list).filter_map(Sexpr::span));
+                }
+            }
+            collect_bracketed(head, aliases, bracketed);
+            for arg in args {
+                collect_bracketed(arg, aliases, bracketed);
+            }
+        }
+        Prefixed(_, ref inner) => collect_bracketed(inner, aliases, bracketed),
+        _ => {}
+    }
+}
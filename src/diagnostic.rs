@@ -0,0 +1,172 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use sexpr_fmt::{delimiters, Sexpr, SyntaxError};
+pub use sexpr_fmt::sexpr::line_column;
+
+/// The exit codes, so that scripts and editors can tell what happened without reading the messages
+pub mod exit {
+    /// `--check` found a file which is not formatted, `diff` found differences, or `lint` found a lint
+    pub const FOUND: i32 = 1;
+    /// some input could not be read as s-expressions
+    pub const PARSE_ERROR: i32 = 2;
+    /// a file could not be read or written
+    pub const IO_ERROR: i32 = 3;
+    /// the command line was wrong, or anything else went wrong
+    pub const OTHER_ERROR: i32 = 4;
+}
+
+/// How diagnostics are written to stderr
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ErrorFormat {
+    /// `file:line:column: message`
+    Human,
+    /// one json object per line, like `{"kind": "parse", "file": "a.smt2", "line": 3, "column": 1, "message": "..."}`
+    Json,
+}
+impl FromStr for ErrorFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ErrorFormat, String> {
+        match s {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("unknown error format `{}`, expected one of: human, json", s)),
+        }
+    }
+}
+
+/// What a diagnostic is about
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Kind {
+    Parse,
+    NeedsReformat,
+    Io,
+    Other,
+}
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Parse => "parse",
+            Kind::NeedsReformat => "needs-reformat",
+            Kind::Io => "io",
+            Kind::Other => "error",
+        }
+    }
+    /// The exit code for ending with this kind of diagnostic
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Kind::Parse => exit::PARSE_ERROR,
+            Kind::NeedsReformat => exit::FOUND,
+            Kind::Io => exit::IO_ERROR,
+            Kind::Other => exit::OTHER_ERROR,
+        }
+    }
+}
+
+/// A problem to report, with where it is when that is known
+/// lines and columns start from 1, and columns count characters
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub kind: Kind,
+    pub file: Option<String>,
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+    /// the line the problem is on, with a `^` under it, which is shown under the message of an error
+    pub snippet: Option<String>,
+}
+impl Diagnostic {
+    /// A problem found at byte `offset` of `text`, the contents of `file`
+    pub fn at(kind: Kind, file: &str, text: &str, offset: usize, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            kind,
+            file: Some(file.to_string()),
+            position: Some(line_column(text, offset)),
+            message: message.into(),
+            snippet: Some(sexpr_fmt::sexpr::snippet(text, offset)),
+        }
+    }
+    /// The diagnostic for `error`, from reading the contents of `file`
+    pub fn syntax(file: &str, error: &SyntaxError) -> Diagnostic {
+        Diagnostic {
+            kind: Kind::Parse,
+            file: Some(file.to_string()),
+            position: Some((error.line, error.column)),
+            message: error.message.to_string(),
+            snippet: Some(error.snippet.clone()),
+        }
+    }
+    /// Give this diagnostic the name of the file it is about, if it does not have one yet
+    pub fn in_file(mut self, file: &str) -> Diagnostic {
+        self.file.get_or_insert_with(|| file.to_string());
+        self
+    }
+    /// Turn any error into a diagnostic, keeping its kind if it is one
+    pub fn from_error(error: &(dyn Error + 'static)) -> Diagnostic {
+        if let Some(diagnostic) = error.downcast_ref::<Diagnostic>() {
+            return diagnostic.clone();
+        }
+        let kind = if error.is::<io::Error>() {
+            Kind::Io
+        } else if error.is::<serde_json::Error>() {
+            Kind::Parse
+        } else {
+            Kind::Other
+        };
+        Diagnostic { kind, file: None, position: None, message: error.to_string(), snippet: None }
+    }
+    /// Write this diagnostic to stderr
+    pub fn report(&self, format: ErrorFormat) {
+        match format {
+            ErrorFormat::Human => {
+                eprintln!("{}", self);
+                for line in self.snippet.iter().flat_map(|snippet| snippet.lines()) {
+                    eprintln!("    {}", line);
+                }
+            }
+            ErrorFormat::Json => {
+                let json = serde_json::json!({
+                    "kind": self.kind.name(),
+                    "file": self.file,
+                    "line": self.position.map(|(line, _)| line),
+                    "column": self.position.map(|(_, column)| column),
+                    "message": self.message,
+                    "snippet": self.snippet,
+                });
+                eprintln!("{}", json);
+            }
+        }
+    }
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.position) {
+            (Some(file), Some((line, column))) => write!(f, "{}:{}:{}: {}", file, line, column, self.message),
+            (Some(file), None) => write!(f, "{}: {}", file, self.message),
+            (None, _) => write!(f, "{}", self.message),
+        }
+    }
+}
+impl Error for Diagnostic {}
+impl From<io::Error> for Diagnostic {
+    fn from(error: io::Error) -> Diagnostic {
+        Diagnostic { kind: Kind::Io, file: None, position: None, message: error.to_string(), snippet: None }
+    }
+}
+
+/// The first list of `text` closed by the wrong kind of delimiter, as an error at its closer
+/// which also says where the list was opened, and which delimiter would close it
+pub fn mismatched_delimiters(file: &str, text: &str) -> Option<Diagnostic> {
+    let mismatch = *delimiters::mismatches(text).first()?;
+    let (line, column) = line_column(text, mismatch.open.start);
+    let message = format!("mismatched delimiters: `{}` closes the `{}` at {}:{}; change it to `{}` (or use --fix-delimiters)",
+        mismatch.found, mismatch.opener, line, column, mismatch.expected());
+    Some(Diagnostic::at(Kind::Parse, file, text, mismatch.close.start, message))
+}
+
+/// Read every top-level form of `text`, the contents of `file`, saying where the first one that can not be read starts
+pub fn parse_forms<'t>(file: &str, text: &'t str) -> Result<Vec<Sexpr<'t>>, Diagnostic> {
+    // a list closed by the wrong delimiter is the likelier cause, and has a fix to suggest
+    Sexpr::parse_many_located(text)
+        .map_err(|error| mismatched_delimiters(file, text).unwrap_or_else(|| Diagnostic::syntax(file, &error)))
+}
@@ -0,0 +1,55 @@
+use std::fmt::Write;
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+
+impl Sexpr<'_> {
+    /// Render this sexpr as a graphviz digraph, with one node per atom and compound expression
+    /// a compound expression is labeled by its head, when the head is an atom
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph sexpr {\n    node [shape=box, fontname=monospace];\n");
+        let mut next_id = 0;
+        self.write_dot(&mut out, &mut next_id);
+        out.push_str("}\n");
+        out
+    }
+    /// Write the node for this sexpr and everything below it, returning the id of this node
+    fn write_dot(&self, out: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        match self.kind {
+            Atom(ref text) => {
+                writeln!(out, "    n{} [label=\"{}\"];", id, escape(text)).unwrap();
+            }
+            Comment(ref text) => {
+                writeln!(out, "    n{} [label=\"{}\", shape=note, fontcolor=gray];", id, escape(text)).unwrap();
+            }
+            Prefixed(prefix, ref sexpr) => {
+                writeln!(out, "    n{} [label=\"{}\", shape=diamond];", id, escape(prefix)).unwrap();
+                let child = sexpr.write_dot(out, next_id);
+                writeln!(out, "    n{} -> n{};", id, child).unwrap();
+            }
+            Compound(ref head, ref args) => {
+                let label = match head.kind {
+                    _ if head.is_blank() && args.is_empty() => "()".to_string(),
+                    Atom(ref text) if !head.is_blank() => format!("({} …)", text),
+                    _ => "( … )".to_string(),
+                };
+                writeln!(out, "    n{} [label=\"{}\", shape=ellipse];", id, escape(&label)).unwrap();
+                if !matches!(head.kind, Atom(_)) {
+                    let child = head.write_dot(out, next_id);
+                    writeln!(out, "    n{} -> n{} [label=\"head\"];", id, child).unwrap();
+                }
+                for arg in args.iter() {
+                    let child = arg.write_dot(out, next_id);
+                    writeln!(out, "    n{} -> n{};", id, child).unwrap();
+                }
+            }
+        }
+        id
+    }
+}
+
+/// Escape `text` for use inside a quoted dot string
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
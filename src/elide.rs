@@ -0,0 +1,47 @@
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+
+/// What an elided compound's arguments are replaced with
+pub const ELLIPSIS: &str = "…";
+
+impl<'a> Sexpr<'a> {
+    /// A summary of this sexpr, keeping `max_depth` levels of compounds
+    /// deeper compounds are printed as `(head …)`, or `(…)` when their head is not an atom
+    /// the compounds along each path of `expand` are kept, and the subtree at its end gets another `max_depth` levels (at least one)
+    pub fn elided(&self, max_depth: usize, expand: &[Vec<usize>]) -> Sexpr<'a> {
+        let paths: Vec<&[usize]> = expand.iter().map(|path| &path[..]).collect();
+        self.elided_helper(max_depth, max_depth, &paths)
+    }
+    /// `depth` is the number of levels left to keep, and `paths` are the rest of the paths to expand through this node
+    fn elided_helper(&self, depth: usize, max_depth: usize, paths: &[&[usize]]) -> Sexpr<'a> {
+        let depth = if paths.iter().any(|path| path.is_empty()) { max_depth.max(1) } else { depth };
+        let into = |index: usize| -> Vec<&[usize]> {
+            paths.iter()
+                .filter_map(|path| path.split_first())
+                .filter(|(&first, _)| first == index)
+                .map(|(_, rest)| rest)
+                .collect()
+        };
+        match self.kind {
+            Atom(_) | Comment(_) => self.clone(),
+            Prefixed(prefix, ref sexpr) => Sexpr::prefixed(prefix, sexpr.elided_helper(depth, max_depth, &into(1))),
+            Compound(ref head, ref args) if depth == 0 && paths.is_empty() && !args.is_empty() => {
+                let ellipsis = Sexpr::atom(ELLIPSIS);
+                if head.is_atom() {
+                    Sexpr::compound((**head).clone(), vec![ellipsis])
+                } else {
+                    Sexpr::compound(ellipsis, Vec::new())
+                }
+            }
+            Compound(ref head, ref args) => {
+                let below = depth.saturating_sub(1);
+                let head = head.elided_helper(below, max_depth, &into(0));
+                let args = args.iter()
+                    .enumerate()
+                    .map(|(i, arg)| arg.elided_helper(below, max_depth, &into(i + 1)))
+                    .collect();
+                Sexpr::compound(head, args)
+            }
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::ops::Range;
+
+/// How Emacs indents the arguments of a form, from the `lisp-indent-function` property of its head
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Method {
+    /// like `defun`: the lines after the head's line are indented as a body
+    Defun,
+    /// the first `n` arguments are distinguished, and the rest are a body, like the 1 of `let` and the 2 of `if`
+    Special(usize),
+}
+
+/// The forms whose `lisp-indent-function` is a number in a plain `emacs -Q`
+const SPECIAL_FORMS: &[(&str, usize)] = &[
+    ("catch", 1),
+    ("condition-case", 2),
+    ("dolist", 1),
+    ("dotimes", 1),
+    ("eval-and-compile", 0),
+    ("eval-when-compile", 0),
+    ("if", 2),
+    ("ignore-errors", 0),
+    ("let", 1),
+    ("let*", 1),
+    ("letrec", 1),
+    ("pcase", 1),
+    ("pcase-let", 1),
+    ("pcase-let*", 1),
+    ("prog1", 1),
+    ("prog2", 2),
+    ("progn", 0),
+    ("save-current-buffer", 0),
+    ("save-excursion", 0),
+    ("save-match-data", 0),
+    ("save-restriction", 0),
+    ("unless", 1),
+    ("unwind-protect", 1),
+    ("when", 1),
+    ("while", 1),
+    ("with-current-buffer", 1),
+    ("with-output-to-string", 0),
+    ("with-temp-buffer", 0),
+];
+
+/// The forms indented like `defun` without starting with `def`
+const DEFUN_FORMS: &[&str] = &["lambda", "closure"];
+
+fn method(head: &str) -> Option<Method> {
+    if let Some(&(_, count)) = SPECIAL_FORMS.iter().find(|&&(name, _)| name == head) {
+        return Some(Method::Special(count));
+    }
+    // Emacs indents every head longer than `def` that starts with it like `defun`
+    if DEFUN_FORMS.contains(&head) || (head.len() > 3 && head.starts_with("def")) {
+        Some(Method::Defun)
+    } else {
+        None
+    }
+}
+
+/// Where an element of a list starts, in the re-indented text
+struct Element {
+    line: usize,
+    column: usize,
+    /// the text of the element, if it is a symbol that could name a form
+    symbol: Option<String>,
+}
+
+/// A list which is open at the start of the line being indented
+struct List {
+    line: usize,
+    column: usize,
+    /// the elements which start before the line being indented
+    elements: Vec<Element>,
+}
+impl List {
+    /// The column Emacs indents a line inside of this list to, following `calculate-lisp-indent`
+    fn indent(&self) -> usize {
+        let (first, last) = match (self.elements.first(), self.elements.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return self.column + 1,
+        };
+        // the first element on the line where the last one starts
+        let under_last_line = self.elements.iter().find(|element| element.line == last.line).map_or(last.column, |element| element.column);
+        let symbol = match first.symbol {
+            Some(ref symbol) => symbol,
+            // lists headed by data, like another list or a keyword, are lined up under their first element
+            None => return if last.line == first.line { first.column } else { under_last_line },
+        };
+        // a function call: line the arguments up with the first one, if it is on the head's line
+        let normal = match self.elements.get(1) {
+            _ if last.line != first.line => under_last_line,
+            Some(second) => second.column,
+            None => first.column,
+        };
+        let body = self.column + 2;
+        match method(symbol) {
+            None => normal,
+            Some(Method::Defun) if last.line == self.line => body,
+            Some(Method::Defun) => normal,
+            Some(Method::Special(count)) => {
+                let before = self.elements.len() - 1;
+                if before < count {
+                    // the first two distinguished arguments are indented twice as far as the body
+                    if before <= 1 { self.column + 4 } else { normal }
+                } else if before == count && (count == 0 || body <= normal) {
+                    body
+                } else {
+                    normal
+                }
+            }
+        }
+    }
+}
+
+/// What the text at the start of a line is inside of
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Within {
+    Code,
+    String,
+    /// a `#| ... |#` comment, nested this many times
+    BlockComment(usize),
+}
+
+/// A line after re-indenting, with the lengths in chars of its old and new indentation
+struct Line<'t> {
+    indent: String,
+    rest: &'t str,
+    old_indent: usize,
+}
+
+/// Re-indent the lines of `text` the way `indent-region` does in a plain `emacs -Q` with `emacs-lisp-mode`,
+/// keeping every line break where it is
+/// only the lines in `lines` (counting from 0) are re-indented; the others are read for the nesting of what comes after
+/// lines starting inside of strings or with `;;;` are left alone, and indentation is written with tabs of `tab_width` columns
+pub fn indent_lines(text: &str, lines: Range<usize>, tab_width: usize) -> String {
+    reindent(text, lines, tab_width).iter()
+        .flat_map(|line| vec![&*line.indent, line.rest])
+        .collect()
+}
+
+/// Re-indent the lines of `text` from the one with point through the one before mark (or the other way around),
+/// like `indent-region` between them, giving the new text and the new positions of point and mark
+/// positions count chars from 1, like Emacs does, and a position inside of a line's indentation moves to its end
+pub fn indent_between(text: &str, point: usize, mark: usize, tab_width: usize) -> (String, usize, usize) {
+    let (start, end) = (point.min(mark).saturating_sub(1), point.max(mark).saturating_sub(1));
+    let mut line_starts = vec![0];
+    line_starts.extend(text.chars().enumerate().filter(|&(_, c)| c == '\n').map(|(i, _)| i + 1));
+    let line_of = |position: usize| line_starts.iter().rposition(|&line_start| line_start <= position).unwrap_or(0);
+    // the region stops before the line it ends at the start of
+    let lines = if start == end { 0..0 } else { line_of(start)..line_of(end - 1) + 1 };
+    let reindented = reindent(text, lines, tab_width);
+    let moved = |position: usize| {
+        let position = position.saturating_sub(1);
+        let line = line_of(position);
+        let new_start: usize = reindented[..line].iter()
+            .map(|line| line.indent.chars().count() + line.rest.chars().count())
+            .sum();
+        let Line { indent, old_indent, .. } = match reindented.get(line) {
+            Some(line) => line,
+            // the end of text ending with a newline
+            None => return new_start + 1,
+        };
+        let within = (position - line_starts[line]).saturating_sub(*old_indent);
+        new_start + indent.chars().count() + within + 1
+    };
+    let text = reindented.iter().flat_map(|line| vec![&*line.indent, line.rest]).collect();
+    (text, moved(point), moved(mark))
+}
+
+/// The point and mark on the first line of `input`, written like `12 40`, and the text after that line
+pub fn split_positions(input: &str) -> Option<(usize, usize, &str)> {
+    let (first, text) = input.split_once('\n')?;
+    let (point, mark) = first.trim_end_matches('\r').split_once(' ')?;
+    Some((point.parse().ok()?, mark.parse().ok()?, text))
+}
+
+fn reindent(text: &str, lines: Range<usize>, tab_width: usize) -> Vec<Line<'_>> {
+    let tab_width = tab_width.max(1);
+    let advance = |column: usize, c: char| if c == '\t' { (column / tab_width + 1) * tab_width } else { column + 1 };
+    let mut stack: Vec<List> = Vec::new();
+    let mut within = Within::Code;
+    let mut out = Vec::new();
+    for (line, full) in text.split_inclusive('\n').enumerate() {
+        let trimmed = full.trim_start_matches([' ', '\t']);
+        let old = &full[..full.len() - trimmed.len()];
+        let old_indent = old.chars().count();
+        let content = trimmed.trim_end_matches(['\n', '\r']);
+        let keep = !lines.contains(&line) || within != Within::Code || content.is_empty() || content.starts_with(";;;");
+        let (indent, rest) = if keep {
+            (old.to_string(), trimmed)
+        } else {
+            let column = stack.last().map_or(0, List::indent);
+            let indent = "\t".repeat(column / tab_width) + &" ".repeat(column % tab_width);
+            (indent, trimmed)
+        };
+        let mut column = indent.chars().fold(0, advance);
+        out.push(Line { indent, rest, old_indent });
+
+        let chars: Vec<char> = rest.chars().collect();
+        let mut prefix = None;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+            let start = column;
+            column = advance(column, c);
+            i += 1;
+            match within {
+                Within::String => {
+                    if c == '\\' && i < chars.len() {
+                        column = advance(column, chars[i]);
+                        i += 1;
+                    } else if c == '"' {
+                        within = Within::Code;
+                    }
+                    continue;
+                }
+                Within::BlockComment(depth) => {
+                    if c == '|' && next == Some('#') {
+                        within = if depth == 1 { Within::Code } else { Within::BlockComment(depth - 1) };
+                    } else if c == '#' && next == Some('|') {
+                        within = Within::BlockComment(depth + 1);
+                    } else {
+                        continue;
+                    }
+                    column += 1;
+                    i += 1;
+                    continue;
+                }
+                Within::Code => {}
+            }
+            let element = |stack: &mut Vec<List>, column: usize, symbol: Option<String>| {
+                if let Some(list) = stack.last_mut() {
+                    list.elements.push(Element { line, column, symbol });
+                }
+            };
+            match c {
+                ' ' | '\t' | '\r' | '\n' => {}
+                ';' => break,
+                '#' if next == Some('|') => {
+                    within = Within::BlockComment(1);
+                    column += 1;
+                    i += 1;
+                }
+                // a prefix like `'` or `#'` belongs to the element after it, which starts at the prefix
+                '\'' | '`' | ',' | '#' if c != '#' || next == Some('\'') => {
+                    prefix.get_or_insert(start);
+                    if matches!((c, next), (',', Some('@')) | ('#', Some('\''))) {
+                        column += 1;
+                        i += 1;
+                    }
+                }
+                '(' | '[' => {
+                    let column = prefix.take().unwrap_or(start);
+                    element(&mut stack, column, None);
+                    stack.push(List { line, column: start, elements: Vec::new() });
+                }
+                ')' | ']' => {
+                    prefix = None;
+                    stack.pop();
+                }
+                '"' => {
+                    element(&mut stack, prefix.take().unwrap_or(start), None);
+                    within = Within::String;
+                }
+                _ => {
+                    let mut text = c.to_string();
+                    if c == '\\' && i < chars.len() {
+                        text.push(chars[i]);
+                        column = advance(column, chars[i]);
+                        i += 1;
+                    }
+                    while let Some(&c) = chars.get(i) {
+                        if c.is_whitespace() || "()[]\";'`,".contains(c) {
+                            break;
+                        }
+                        text.push(c);
+                        column = advance(column, c);
+                        i += 1;
+                        if c == '\\' && i < chars.len() {
+                            text.push(chars[i]);
+                            column = advance(column, chars[i]);
+                            i += 1;
+                        }
+                    }
+                    // a prefixed element or a keyword is data, rather than the name of a form
+                    let symbol = match prefix.take() {
+                        Some(prefixed) => {
+                            element(&mut stack, prefixed, None);
+                            continue;
+                        }
+                        None if text.starts_with(':') => None,
+                        None => Some(text),
+                    };
+                    element(&mut stack, start, symbol);
+                }
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,195 @@
+use regex::Regex;
+use crate::sexpr::SyntaxError;
+
+/// The markers which open a fenced block of s-expressions, when none are given
+pub const DEFAULT_FENCE_STARTS: &[&str] = &["```lisp", "```scheme", "```clojure", "```racket", "```smt2", "```sexpr", "```wat"];
+/// The marker which closes a fenced block, when none is given
+pub const DEFAULT_FENCE_END: &str = "```";
+
+/// A fenced block found in a host file that could not be formatted
+#[derive(Debug)]
+pub struct BlockError {
+    /// why the block could not be read, placed in the host text rather than in the block
+    pub error: SyntaxError,
+}
+
+/// Format only the s-expression blocks of the host text `text`, leaving everything else untouched
+/// a block starts on a line beginning with one of `starts` and continues until a line which is just `end`
+/// whatever comes before the opening marker on its line (such as `/// ` or indentation)
+/// is taken off of every line of the block before formatting, and put back on afterwards
+/// blocks which don't parse are left as they are, and reported in the returned errors
+pub fn format_embedded<F>(text: &str, starts: &[String], end: &str, format: F) -> (String, Vec<BlockError>)
+    where F: Fn(&str) -> Result<String, SyntaxError>
+{
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let mut out = String::new();
+    let mut errors = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        out.push_str(line);
+        i += 1;
+        let prefix = match fence_prefix(line, starts) {
+            Some(prefix) => prefix,
+            None => continue,
+        };
+        // find the end of the block
+        let block_start = i;
+        while i < lines.len() && strip_prefix(lines[i], prefix).map(str::trim) != Some(end) {
+            i += 1;
+        }
+        if i == lines.len() {
+            // an unclosed block: leave the rest of the file as it is
+            i = block_start;
+            continue;
+        }
+        let stripped: Vec<&str> = lines[block_start..i].iter()
+            .map(|line| strip_prefix(line, prefix).unwrap_or_else(|| line.trim_start()))
+            .collect();
+        match format(&stripped.concat()) {
+            Ok(formatted) => {
+                for formatted_line in formatted.lines() {
+                    let prefix = if formatted_line.is_empty() { prefix.trim_end() } else { prefix };
+                    out.push_str(prefix);
+                    out.push_str(formatted_line);
+                    out.push('\n');
+                }
+            }
+            Err(error) => {
+                let start: usize = lines[..block_start].iter().map(|line| line.len()).sum();
+                let offset = start + unstripped_offset(&lines[block_start..i], &stripped, error.offset);
+                errors.push(BlockError { error: SyntaxError::at(text, offset, error.message) });
+                lines[block_start..i].iter().for_each(|line| out.push_str(line));
+            }
+        }
+    }
+    (out, errors)
+}
+
+/// Where byte `offset` of the text of `stripped` is in the text of `lines`, which are the same lines
+/// before something was taken off of the start of each
+fn unstripped_offset(lines: &[&str], stripped: &[&str], offset: usize) -> usize {
+    let (mut from, mut to) = (0, 0);
+    for (line, stripped) in lines.iter().zip(stripped) {
+        if offset < from + stripped.len() {
+            return to + line.len() - stripped.len() + offset - from;
+        }
+        from += stripped.len();
+        to += line.len();
+    }
+    to
+}
+
+/// If `line` opens a fenced block, everything that comes before the marker
+fn fence_prefix<'a>(line: &'a str, starts: &[String]) -> Option<&'a str> {
+    starts.iter()
+        .filter_map(|start| line.find(start.as_str()).map(|idx| (idx, start)))
+        .find(|(idx, start)| {
+            let before = &line[..*idx];
+            let after = &line[idx + start.len()..];
+            // the marker must be the first thing on the line after the host's comment leader,
+            // and nothing but whitespace may follow it
+            !before.chars().any(char::is_alphanumeric) && after.trim().is_empty()
+        })
+        .map(|(idx, _)| &line[..idx])
+}
+
+/// Take `prefix` off of `line`, allowing for a blank line which only has the trimmed prefix
+fn strip_prefix<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    line.strip_prefix(prefix).or_else(|| {
+        let trimmed = prefix.trim_end();
+        line.strip_prefix(trimmed).filter(|rest| rest.trim().is_empty())
+    })
+}
+
+/// Compile the regex given to `--fence`, which must have a group for the s-expressions in the blocks it matches,
+/// since a regex matching just a marker like `r\"\"\"` would format nothing at all
+pub fn fence_regex(pattern: &str) -> Result<Regex, String> {
+    let fence = Regex::new(pattern).map_err(|error| error.to_string())?;
+    if fence.captures_len() < 2 {
+        return Err(format!("the fence `{}` has no group for the s-expressions of a block, like `(.*?)` or `(?P<sexpr>.*?)`", pattern));
+    }
+    Ok(fence)
+}
+
+/// Format only the parts of the host text `text` matched by `fence`, leaving everything else untouched
+/// the group named `sexpr` of each match is formatted, or the first group if there is no such group, or else the whole match,
+/// so that `(?s)#\+begin_src lisp\n(.*?)#\+end_src` formats the insides of org blocks
+/// a block of whole lines keeps the indentation the lines share, and the lines after the first of a block
+/// which starts partway through a line are indented to the column the block starts at
+/// blocks which don't parse are left as they are, and reported in the returned errors
+pub fn format_matches<F>(text: &str, fence: &Regex, format: F) -> (String, Vec<BlockError>)
+    where F: Fn(&str) -> Result<String, SyntaxError>
+{
+    let mut out = String::new();
+    let mut errors = Vec::new();
+    let mut last = 0;
+    for captures in fence.captures_iter(text) {
+        let block = match captures.name("sexpr").or_else(|| captures.get(1)).or_else(|| captures.get(0)) {
+            Some(block) if !block.as_str().trim().is_empty() => block,
+            _ => continue,
+        };
+        out.push_str(&text[last..block.start()]);
+        last = block.end();
+        let line_start = text[..block.start()].rfind('\n').map_or(0, |idx| idx + 1);
+        match format_block(&text[line_start..block.start()], block.as_str(), &format) {
+            Ok(formatted) => out.push_str(&formatted),
+            Err(error) => {
+                errors.push(BlockError { error: SyntaxError::at(text, block.start() + error.offset, error.message) });
+                out.push_str(block.as_str());
+            }
+        }
+    }
+    out.push_str(&text[last..]);
+    (out, errors)
+}
+
+/// Format the matched `block`, which comes after `before` on its line
+/// an error is placed in `block`, rather than in the text given to `format`
+fn format_block<F>(before: &str, block: &str, format: &F) -> Result<String, SyntaxError>
+    where F: Fn(&str) -> Result<String, SyntaxError>
+{
+    let mut out = String::new();
+    if before.is_empty() {
+        // whole lines: take off the indentation they share, and put it back after formatting
+        let indent_len = block.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        let indent = &block.lines().find(|line| !line.trim().is_empty()).unwrap_or("")[..indent_len];
+        let lines: Vec<&str> = block.split_inclusive('\n').collect();
+        let dedented: Vec<&str> = lines.iter()
+            .map(|line| line.strip_prefix(indent).unwrap_or_else(|| line.trim_start()))
+            .collect();
+        let formatted = format(&dedented.concat())
+            .map_err(|error| SyntaxError::at(block, unstripped_offset(&lines, &dedented, error.offset), error.message))?;
+        for line in formatted.lines() {
+            if !line.is_empty() {
+                out.push_str(indent);
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+        if !block.ends_with('\n') {
+            out.pop();
+        }
+    } else {
+        // the indentation of the line, then spaces up to where the block starts
+        let leading = &before[..before.len() - before.trim_start().len()];
+        let indent = format!("{}{}", leading, " ".repeat(before[leading.len()..].chars().count()));
+        let formatted = format(block)?;
+        for (i, line) in formatted.trim_end_matches('\n').lines().enumerate() {
+            if i > 0 {
+                out.push('\n');
+                if !line.is_empty() {
+                    out.push_str(&indent);
+                }
+            }
+            out.push_str(line);
+        }
+        // keep whatever whitespace the block ended with, such as the newline before a closing marker
+        out.push_str(&block[block.trim_end().len()..]);
+    }
+    Ok(out)
+}
@@ -0,0 +1,42 @@
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+
+/// An s-expression found inside of some other text, such as a log
+#[derive(Clone, Debug, PartialEq)]
+pub struct Extracted<'a> {
+    /// the line that the s-expression starts on, counting from 1
+    pub line: usize,
+    pub sexpr: Sexpr<'a>,
+}
+
+/// Find the balanced compound s-expressions in `text` with at least `min_nodes` nodes,
+/// skipping over anything that cannot be read, the way prose and log lines usually cannot
+pub fn extract(text: &str, min_nodes: usize) -> Vec<Extracted<'_>> {
+    let mut found = Vec::new();
+    let mut idx = 0;
+    while let Some(offset) = text[idx..].find('(') {
+        let start = idx + offset;
+        match Sexpr::parse_first(&text[start..]) {
+            Ok((sexpr, _)) if sexpr.node_count() >= min_nodes => {
+                let len = sexpr.span().expect("parsed forms have spans").end;
+                let line = text[..start].matches('\n').count() + 1;
+                found.push(Extracted { line, sexpr });
+                idx = start + len;
+            }
+            // not an s-expression (or too small to be interesting), so try from the next `(`
+            _ => idx = start + 1,
+        }
+    }
+    found
+}
+
+impl Sexpr<'_> {
+    /// The number of atoms, comments, and compounds in this sexpr, including itself
+    pub fn node_count(&self) -> usize {
+        match self.kind {
+            Atom(_) | Comment(_) => 1,
+            Prefixed(_, ref sexpr) => sexpr.node_count(),
+            Compound(ref head, ref args) => 1 + head.node_count() + args.iter().map(Sexpr::node_count).sum::<usize>(),
+        }
+    }
+}
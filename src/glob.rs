@@ -0,0 +1,28 @@
+/// Whether `path` matches the glob `pattern`, where `*` matches anything but a `/`,
+/// `**` matches anything at all, and `?` matches any single character but a `/`
+/// a pattern without a `/` is checked against the file name alone, like in a `.gitignore`
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    let path = if pattern.contains('/') { path } else { path.rsplit('/').next().unwrap_or(path) };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    matches_from(&pattern, &path)
+}
+
+fn matches_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` may also match no directories at all
+            let rest_after_slash = rest.strip_prefix(&['/']).unwrap_or(rest);
+            matches_from(rest_after_slash, path) || (0..=path.len()).any(|skip| matches_from(rest, &path[skip..]))
+        }
+        ['*', rest @ ..] => {
+            let segment = path.iter().position(|&ch| ch == '/').unwrap_or(path.len());
+            (0..=segment).any(|skip| matches_from(rest, &path[skip..]))
+        }
+        ['?', rest @ ..] => matches!(path, [ch, tail @ ..] if *ch != '/' && matches_from(rest, tail)),
+        [ch, rest @ ..] => matches!(path, [first, tail @ ..] if first == ch && matches_from(rest, tail)),
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::Write;
+use sha2::{Digest, Sha256};
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+
+/// A SHA-256 hash of the structure of a sexpr, which does not depend on how it is laid out
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StructuralHash(pub [u8; 32]);
+impl std::fmt::Display for StructuralHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Sexpr<'_> {
+    /// Hash the structure of this sexpr, ignoring its layout and comments
+    /// the hash is stable: it only changes if the way sexprs are serialized for hashing does
+    pub fn structural_hash(&self) -> StructuralHash {
+        let mut hasher = Sha256::new();
+        hasher.update(self.hash_serialization());
+        StructuralHash(hasher.finalize().into())
+    }
+    /// The unambiguous text that is hashed: each atom is prefixed with its length in bytes,
+    /// so that atoms containing spaces or parens cannot be confused with compounds
+    fn hash_serialization(&self) -> String {
+        fn write(sexpr: &Sexpr<'_>, out: &mut String) {
+            match sexpr.kind {
+                Atom(ref text) => {
+                    let _ = write!(out, "{}:{}", text.len(), text);
+                }
+                Comment(_) => {}
+                Prefixed(prefix, ref sexpr) => {
+                    out.push_str(prefix);
+                    write(sexpr, out);
+                }
+                Compound(ref head, ref args) => {
+                    out.push('(');
+                    write(head, out);
+                    for arg in args.iter() {
+                        write(arg, out);
+                    }
+                    out.push(')');
+                }
+            }
+        }
+        let mut out = String::new();
+        write(self, &mut out);
+        out
+    }
+}
+
+/// Hash a sequence of top-level forms, such as a whole file, from the hashes of the forms
+/// comments between the forms are ignored, like the comments inside of them
+pub fn hash_forms(forms: &[Sexpr<'_>]) -> StructuralHash {
+    let mut hasher = Sha256::new();
+    for form in forms.iter().filter(|form| !form.is_comment()) {
+        hasher.update(form.structural_hash().0);
+    }
+    StructuralHash(hasher.finalize().into())
+}
@@ -0,0 +1,101 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use serde_json::Value;
+
+/// The largest body we read, so that one request cannot take all of the memory
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+/// Just what is needed of an http request to answer it
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+/// A json response to an http request
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+impl Response {
+    /// A response saying what was wrong with the request, like `{"error": "..."}`
+    pub fn error(status: u16, message: impl Into<String>) -> Response {
+        Response { status, body: serde_json::json!({ "error": message.into() }) }
+    }
+}
+
+/// Answer http requests on `addr` with `handle`, one after the other, until killed
+/// any page may call us, since the server is for playgrounds which are served from somewhere else
+pub fn serve(addr: &str, handle: impl Fn(&Request) -> Response) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let answered = stream.and_then(|stream| answer(stream, &handle));
+        if let Err(error) = answered {
+            eprintln!("warning: could not answer a request: {}", error);
+        }
+    }
+    Ok(())
+}
+
+fn answer(stream: TcpStream, handle: &impl Fn(&Request) -> Response) -> io::Result<()> {
+    // a client which stops sending would keep everyone else waiting
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").split('?').next().unwrap_or("").to_string();
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let response = if method == "OPTIONS" {
+        // the preflight of a request from another origin
+        None
+    } else if length > MAX_BODY {
+        Some(Response::error(413, format!("the body is longer than the limit of {} bytes", MAX_BODY)))
+    } else {
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        Some(match String::from_utf8(body) {
+            Ok(body) => handle(&Request { method, path, body }),
+            Err(_) => Response::error(400, "the body is not utf-8"),
+        })
+    };
+    write_response(stream, response)
+}
+
+/// Write `response`, or an empty answer to a preflight if there is none
+fn write_response(mut stream: TcpStream, response: Option<Response>) -> io::Result<()> {
+    let (status, body) = match response {
+        Some(response) => (response.status, response.body.to_string()),
+        None => (204, String::new()),
+    };
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    write!(stream, "HTTP/1.1 {} {}\r\n", status, reason)?;
+    write!(stream, "Access-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: POST, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\n")?;
+    if !body.is_empty() {
+        write!(stream, "Content-Type: application/json\r\n")?;
+    }
+    write!(stream, "Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)?;
+    stream.flush()
+}
@@ -0,0 +1,50 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::Comment;
+
+/// The text of a comment asking for the top-level form right after it to be left exactly as it is
+/// written either as the line comment `; sexpr-fmt: ignore` or the block comment `#| sexpr-fmt: ignore |#`
+pub const IGNORE_DIRECTIVE: &str = "sexpr-fmt: ignore";
+
+/// Replace each line comment `; sexpr-fmt: ignore` in `input` with spaces, so that the parser does not read it as a comment to format,
+/// keeping the offsets of everything else the same, and giving the range of each directive that was replaced
+pub fn blank_line_directives(input: &str) -> (Cow<'_, str>, Vec<Range<usize>>) {
+    let mut directives = Vec::new();
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let content = line.trim_start();
+        let is_directive = content.starts_with(';')
+            && content.trim_start_matches(';').trim() == IGNORE_DIRECTIVE;
+        if is_directive {
+            let start = offset + line.len() - content.len();
+            directives.push(start..offset + line.trim_end().len());
+        }
+        offset += line.len();
+    }
+    if directives.is_empty() {
+        return (Cow::Borrowed(input), directives);
+    }
+    let mut blanked = input.to_string();
+    for directive in directives.iter() {
+        blanked.replace_range(directive.clone(), &" ".repeat(directive.len()));
+    }
+    (Cow::Owned(blanked), directives)
+}
+
+/// If the top-level form `forms[i]` comes right after an ignore directive, the offset in `input`
+/// that its original text should be copied from: the start of a line directive, which is copied along with it,
+/// or the start of the form itself, after a block comment directive
+pub fn ignored_from(input: &str, directives: &[Range<usize>], forms: &[Sexpr<'_>], i: usize) -> Option<usize> {
+    let span = forms[i].span()?;
+    let only_space_between = |end: usize| end <= span.start && input[end..span.start].trim().is_empty();
+    if let Some(directive) = directives.iter().find(|directive| only_space_between(directive.end)) {
+        return Some(directive.start);
+    }
+    let previous = forms.get(i.checked_sub(1)?)?;
+    match previous.kind {
+        Comment(ref text) if text.starts_with("#|") && text[2..text.len() - 2].trim() == IGNORE_DIRECTIVE
+            && previous.span().is_some_and(|prev| only_space_between(prev.end)) => Some(span.start),
+        _ => None,
+    }
+}
@@ -0,0 +1,81 @@
+use crate::prelude::*;
+use crate::sexpr::{Sexpr, ParseError, Span};
+
+/// A change to a text which was read: the bytes from `start` to `old_end` were replaced by ones ending at `new_end`
+/// (the offsets are in bytes, like the spans, and `start` is the same in the old and the new text)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+impl Edit {
+    /// How many bytes further along everything after the edit is in the new text
+    fn shift(&self) -> isize {
+        self.new_end as isize - self.old_end as isize
+    }
+}
+
+impl Sexpr<'_> {
+    /// Read the top-level forms of `new_text`, which is the text `old_tree` was read from with `edit` made to it,
+    /// reusing the forms which the edit did not touch instead of reading them again
+    /// only the forms which overlap the edit (or touch it, since typing next to an atom changes it) are read again,
+    /// and the rest are moved to borrow from `new_text`, so an edit costs a walk over the trees rather than a reparse;
+    /// if the changed part cannot be read on its own, such as after opening a block comment, the whole text is read again
+    ///
+    /// ```
+    /// use sexpr_fmt::Sexpr;
+    /// use sexpr_fmt::incremental::Edit;
+    ///
+    /// let old_text = "(define x 1)\n(f x)\n(g y)";
+    /// let old_tree = Sexpr::parse_many(old_text).unwrap();
+    /// let new_text = "(define x 1)\n(f x z)\n(g y)";
+    /// let edit = Edit { start: 17, old_end: 17, new_end: 19 };
+    /// let new_tree = Sexpr::reparse_with_edit(&old_tree, edit, new_text).unwrap();
+    /// assert_eq!(new_tree, Sexpr::parse_many(new_text).unwrap());
+    /// assert_eq!(new_tree[2].span().unwrap().start, 21);
+    /// ```
+    pub fn reparse_with_edit<'n>(old_tree: &[Sexpr<'_>], edit: Edit, new_text: &'n str) -> Result<Vec<Sexpr<'n>>, ParseError> {
+        match reparse_around(old_tree, edit, new_text) {
+            Some(forms) => Ok(forms),
+            None => Sexpr::parse_many(new_text),
+        }
+    }
+}
+
+/// The forms of `new_text`, with only those around `edit` read again, or nothing if that is not enough
+fn reparse_around<'n>(old_tree: &[Sexpr<'_>], edit: Edit, new_text: &'n str) -> Option<Vec<Sexpr<'n>>> {
+    let spans = old_tree.iter().map(Sexpr::span).collect::<Option<Vec<Span>>>()?;
+    // the forms before `first` and from `last` on are kept
+    let first = spans.iter().position(|span| span.end >= edit.start).unwrap_or(spans.len());
+    let mut last = spans.iter().position(|span| span.start > edit.old_end).unwrap_or(spans.len()).max(first);
+    // a comment right after the changed forms may stop trailing them, or start to
+    if old_tree.get(last).is_some_and(Sexpr::is_line_comment) {
+        last += 1;
+    }
+    let read_again = &spans[first..last];
+    let start = read_again.first().map_or(edit.start, |span| span.start.min(edit.start));
+    let old_end = read_again.last().map_or(edit.old_end, |span| span.end.max(edit.old_end));
+    let end = old_end.checked_add_signed(edit.shift())?;
+    // the space after the changed forms is read with them, since the reader keeps it in the spans of line comments
+    let end = end + new_text.get(end..)?.len() - new_text[end..].trim_start().len();
+    let changed = new_text.get(start..end)?;
+
+    let mut forms: Vec<Sexpr<'n>> = old_tree[..first].iter().map(|form| form.rebased(new_text, 0)).collect();
+    let mut remaining = changed.trim_start();
+    while !remaining.is_empty() {
+        let (form, tail) = Sexpr::parse_helper(remaining, new_text).ok()?;
+        if form.is_blank() {
+            return None;
+        }
+        remaining = tail.trim_start();
+        // a line comment cut off by the end of the changed text would swallow the rest of its line
+        let rest_of_line = new_text[end..].split('\n').next().unwrap_or("");
+        if remaining.is_empty() && form.is_line_comment() && !rest_of_line.trim().is_empty() {
+            return None;
+        }
+        forms.push(form);
+    }
+    forms.extend(old_tree[last..].iter().map(|form| form.rebased(new_text, edit.shift())));
+    Some(forms)
+}
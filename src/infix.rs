@@ -0,0 +1,153 @@
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+
+/// How operands at the same precedence as their operator are grouped
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Assoc {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `p => q => r` is `p => (q => r)`
+    Right,
+    /// `a = b = c` would be misread either way, so same-precedence operands always get parens
+    None,
+}
+
+/// Binders, `let`, and `if` reach as far right as they can, so they bind loosest of all
+const BINDER: u8 = 0;
+/// A unary `not` binds tighter than the connectives it negates, but looser than a comparison
+const NOT: u8 = 4;
+/// A unary `-` binds tighter than any binary operator
+const NEGATE: u8 = 8;
+/// Atoms and function applications never need parens
+const ATOMIC: u8 = 9;
+
+/// The binary operators written between their operands, from loosest to tightest
+const OPERATORS: &[(&str, u8, Assoc)] = &[
+    ("=>", 1, Assoc::Right),
+    ("or", 2, Assoc::Left),
+    ("xor", 2, Assoc::Left),
+    ("and", 3, Assoc::Left),
+    ("=", 5, Assoc::None),
+    ("distinct", 5, Assoc::None),
+    ("<", 5, Assoc::None),
+    ("<=", 5, Assoc::None),
+    (">", 5, Assoc::None),
+    (">=", 5, Assoc::None),
+    ("+", 6, Assoc::Left),
+    ("-", 6, Assoc::Left),
+    ("*", 7, Assoc::Left),
+    ("/", 7, Assoc::Left),
+    ("div", 7, Assoc::Left),
+    ("mod", 7, Assoc::Left),
+];
+
+impl Sexpr<'_> {
+    /// Write this sexpr on one line as an infix formula, such as `forall x: Int. x * 2 > x`
+    /// operators from the precedence table go between their operands, other forms are written as applications `f(a, b)`,
+    /// and parens are only added where leaving them out would read differently, unless `full_parens` is set
+    /// comments are left out
+    pub fn to_infix(&self, full_parens: bool) -> String {
+        self.infix(full_parens).0
+    }
+    /// The infix text of this sexpr, and the precedence of its outermost operator
+    fn infix(&self, full_parens: bool) -> (String, u8) {
+        match self.kind {
+            Atom(ref text) | Comment(ref text) => (text.to_string(), ATOMIC),
+            Prefixed(prefix, ref sexpr) => (format!("{}{}", prefix, sexpr.operand(full_parens, ATOMIC, false)), ATOMIC),
+            Compound(ref head, ref args) => {
+                let args: Vec<&Sexpr> = args.iter().filter(|arg| !arg.is_comment()).collect();
+                if head.is_blank() && args.is_empty() {
+                    return ("()".to_string(), ATOMIC);
+                }
+                if let Atom(ref name) = head.kind {
+                    if let Some(formula) = special_form(name, &args, full_parens) {
+                        return formula;
+                    }
+                }
+                let args: Vec<String> = args.iter().map(|arg| arg.to_infix(full_parens)).collect();
+                (format!("{}({})", head.operand(full_parens, ATOMIC, false), args.join(", ")), ATOMIC)
+            }
+        }
+    }
+    /// The infix text of this sexpr as an operand of an operator with precedence `parent`,
+    /// in parens if it binds looser, or just as loose and `tight` says this side cannot share a level
+    fn operand(&self, full_parens: bool, parent: u8, tight: bool) -> String {
+        let (text, precedence) = self.infix(full_parens);
+        if precedence < parent || (precedence == parent && tight) || (full_parens && precedence < ATOMIC) {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+}
+
+/// The infix text of an operator, binder, `let`, or `if` named `name`,
+/// or none for forms that should be written as applications
+fn special_form(name: &str, args: &[&Sexpr<'_>], full_parens: bool) -> Option<(String, u8)> {
+    match (name, args) {
+        ("not", [arg]) => Some((format!("not {}", arg.operand(full_parens, NOT, false)), NOT)),
+        ("-", [arg]) => {
+            let operand = arg.operand(full_parens, NEGATE, true);
+            // `--x` would read as a single atom
+            let operand = if operand.starts_with('-') { format!("({})", operand) } else { operand };
+            Some((format!("-{}", operand), NEGATE))
+        }
+        ("forall", [bindings, body]) | ("exists", [bindings, body]) | ("lambda", [bindings, body]) => {
+            let bindings = bindings_text(bindings, ": ", full_parens)?;
+            Some((format!("{} {}. {}", name, bindings, body.to_infix(full_parens)), BINDER))
+        }
+        ("let", [bindings, body]) | ("let*", [bindings, body]) => {
+            let bindings = bindings_text(bindings, " = ", full_parens)?;
+            Some((format!("{} {} in {}", name, bindings, body.to_infix(full_parens)), BINDER))
+        }
+        ("ite", [cond, then, otherwise]) | ("if", [cond, then, otherwise]) => Some((format!(
+            "if {} then {} else {}",
+            cond.to_infix(full_parens), then.to_infix(full_parens), otherwise.to_infix(full_parens),
+        ), BINDER)),
+        _ => {
+            let &(_, precedence, assoc) = OPERATORS.iter().find(|&&(operator, _, _)| operator == name)?;
+            if args.len() < 2 {
+                return None;
+            }
+            let last = args.len() - 1;
+            let operands: Vec<String> = args.iter().enumerate().map(|(i, arg)| {
+                let tight = match assoc {
+                    Assoc::Left => i > 0,
+                    Assoc::Right => i < last,
+                    Assoc::None => true,
+                };
+                arg.operand(full_parens, precedence, tight)
+            }).collect();
+            Some((operands.join(&format!(" {} ", name)), precedence))
+        }
+    }
+}
+
+/// A binding list like `((x Int) (y Int))` written as `x: Int, y: Int`, with `separator` between each name and its value,
+/// or none if it is not a list of names and pairs
+fn bindings_text(bindings: &Sexpr<'_>, separator: &str, full_parens: bool) -> Option<String> {
+    let (head, args) = match bindings.kind {
+        Compound(ref head, ref args) => (head, args),
+        _ => return None,
+    };
+    let binding = |binding: &Sexpr<'_>| match binding.kind {
+        Atom(ref name) if !name.is_empty() => Some(name.to_string()),
+        Compound(ref name, ref value) => match (&name.kind, value.as_slice()) {
+            (Atom(name), [value]) if !name.is_empty() => Some(format!("{}{}{}", name, separator, value.to_infix(full_parens))),
+            _ => None,
+        },
+        _ => None,
+    };
+    let mut texts = Vec::new();
+    // the first binding of the list is parsed as its head, unless the list is empty
+    if !head.is_blank() || !args.is_empty() {
+        texts.push(binding(head)?);
+    }
+    for arg in args.iter().filter(|arg| !arg.is_comment()) {
+        texts.push(binding(arg)?);
+    }
+    if texts.is_empty() {
+        return None;
+    }
+    Some(texts.join(", "))
+}
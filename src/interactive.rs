@@ -0,0 +1,91 @@
+use std::io::{self, Write};
+use std::path::Path;
+use sexpr_fmt::textdiff::{self, Hunk};
+
+/// The number of unchanged lines shown around each hunk
+const CONTEXT: usize = 3;
+
+/// What the user chose to do with the remaining hunks
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Review {
+    /// keep asking about the next files
+    Continue,
+    /// stop reviewing: nothing else should be applied
+    Quit,
+}
+
+/// Show each hunk of the difference between `old` and `new` one at a time, asking whether to apply it,
+/// like `git add -p`
+/// returns the text with the accepted hunks applied, or None if nothing was accepted
+pub fn review(path: &Path, old: &str, new: &str) -> io::Result<(Option<String>, Review)> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let hunks = textdiff::line_hunks(&old_lines, &new_lines);
+    let mut accept = vec![false; hunks.len()];
+    let mut status = Review::Continue;
+    let mut rest = None; // set once the user decides on all of the remaining hunks in this file
+    if !hunks.is_empty() {
+        println!("--- {}", path.display());
+        println!("+++ {}", path.display());
+    }
+    for (i, hunk) in hunks.iter().enumerate() {
+        if let Some(choice) = rest {
+            accept[i] = choice;
+            continue;
+        }
+        print_hunk(&old_lines, hunk);
+        loop {
+            print!("({}/{}) Apply this hunk [y,n,a,d,q,?]? ", i + 1, hunks.len());
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer)? == 0 {
+                // treat the end of input like `q`
+                answer = "q".to_string();
+            }
+            match answer.trim() {
+                "y" => accept[i] = true,
+                "n" => {}
+                "a" => { accept[i] = true; rest = Some(true); }
+                "d" => rest = Some(false),
+                "q" => { status = Review::Quit; rest = Some(false); }
+                _ => {
+                    println!("y - apply this hunk");
+                    println!("n - do not apply this hunk");
+                    println!("a - apply this hunk and all later hunks in the file");
+                    println!("d - do not apply this hunk or any of the later hunks in the file");
+                    println!("q - quit; do not apply this hunk or any of the remaining ones");
+                    continue;
+                }
+            }
+            break;
+        }
+    }
+    if !accept.iter().any(|&accepted| accepted) {
+        return Ok((None, status));
+    }
+    let mut text = textdiff::apply_hunks(&old_lines, &hunks, &accept).join("\n");
+    text.push('\n');
+    Ok((Some(text), status))
+}
+
+/// Print `hunk` in the unified diff format, with a few lines of context
+fn print_hunk(old_lines: &[&str], hunk: &Hunk<'_>) {
+    let before = hunk.old_start.saturating_sub(CONTEXT);
+    let after = std::cmp::min(old_lines.len(), hunk.old_start + hunk.removed.len() + CONTEXT);
+    let context_lines = (hunk.old_start - before) + (after - hunk.old_start - hunk.removed.len());
+    println!("@@ -{},{} +{},{} @@",
+             before + 1, context_lines + hunk.removed.len(),
+             hunk.new_start - (hunk.old_start - before) + 1, context_lines + hunk.inserted.len());
+    for line in &old_lines[before..hunk.old_start] {
+        println!(" {}", line);
+    }
+    for line in &hunk.removed {
+        println!("-{}", line);
+    }
+    for line in &hunk.inserted {
+        println!("+{}", line);
+    }
+    for line in &old_lines[hunk.old_start + hunk.removed.len()..after] {
+        println!(" {}", line);
+    }
+}
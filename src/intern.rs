@@ -0,0 +1,159 @@
+use crate::prelude::*;
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use core::convert::TryFrom;
+use crate::sexpr::{Sexpr, ParseError, Build};
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+
+/// The text of an atom or comment in a `SymbolTable`, which compares as an integer
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SymbolId(u32);
+
+/// The texts of the atoms and comments interned so far, each kept once however many times it was read,
+/// with the id it was given the first time
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable<'s> {
+    texts: Vec<Cow<'s, str>>,
+    ids: BTreeMap<Cow<'s, str>, SymbolId>,
+}
+impl<'s> SymbolTable<'s> {
+    pub fn new() -> SymbolTable<'s> {
+        SymbolTable::default()
+    }
+    /// The id of `text`, which is given a new one if it was not interned before
+    pub fn intern(&mut self, text: impl Into<Cow<'s, str>>) -> SymbolId {
+        let text = text.into();
+        if let Some(&id) = self.ids.get(&*text) {
+            return id;
+        }
+        let id = SymbolId(u32::try_from(self.texts.len()).expect("fewer than 2^32 distinct symbols"));
+        self.texts.push(text.clone());
+        self.ids.insert(text, id);
+        id
+    }
+    /// The id of `text`, if it was interned
+    pub fn get(&self, text: &str) -> Option<SymbolId> {
+        self.ids.get(text).copied()
+    }
+    /// The text `id` stands for
+    pub fn resolve(&self, id: SymbolId) -> &str {
+        &self.texts[id.0 as usize]
+    }
+    /// The number of distinct texts interned
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+}
+
+/// A sexpr whose atoms and comments are symbols of a `SymbolTable`, for holding huge generated inputs
+/// which repeat the same symbols over and over
+/// it keeps only the structure, without the spans and measurements of a `Sexpr`, so it takes a fraction of the memory,
+/// and `Node::is_named` compares symbols as integers rather than comparing their text like `Sexpr::is_named`
+/// `Sexpr::parse_many_interned` reads these straight from the input, without building any `Sexpr`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Node {
+    Atom(SymbolId),
+    /// a list, with its head first (the head of `()` is the empty atom),
+    /// and whether it was written in square brackets, like `[x 1]`
+    List(Box<[Node]>, bool),
+    /// reader sugar like `'(a b)`, holding the index of its prefix in `sexpr::PREFIXES`
+    Prefixed(u8, Box<Node>),
+    Comment(SymbolId),
+}
+impl Node {
+    /// Whether this is the atom `symbol`, like `is_named` of a `Sexpr`
+    pub fn is_named(&self, symbol: SymbolId) -> bool {
+        matches!(*self, Node::Atom(id) if id == symbol)
+    }
+    /// The head of this list, if it is one
+    pub fn head(&self) -> Option<&Node> {
+        match *self {
+            Node::List(ref elements, _) => elements.first(),
+            _ => None,
+        }
+    }
+    /// The sexpr this stands for, borrowing its text from `table`, for formatting
+    pub fn to_sexpr<'t>(&self, table: &'t SymbolTable<'_>) -> Sexpr<'t> {
+        match *self {
+            Node::Atom(id) => Sexpr::atom(table.resolve(id)),
+            Node::Comment(id) => Sexpr::comment(table.resolve(id)),
+            Node::Prefixed(prefix, ref node) => Sexpr::prefixed(crate::sexpr::PREFIXES[prefix as usize], node.to_sexpr(table)),
+            Node::List(ref elements, brackets) => {
+                let (head, args) = elements.split_first().expect("lists have a head");
+                Sexpr::compound(head.to_sexpr(table), args.iter().map(|arg| arg.to_sexpr(table)).collect()).with_brackets(brackets)
+            }
+        }
+    }
+}
+
+impl<'s> Sexpr<'s> {
+    /// This sexpr with its text interned in `table`
+    pub fn to_interned(&self, table: &mut SymbolTable<'s>) -> Node {
+        match self.kind {
+            Atom(ref text) => Node::Atom(table.intern(text.clone())),
+            Comment(ref text) => Node::Comment(table.intern(text.clone())),
+            Prefixed(prefix, ref sexpr) => {
+                let index = crate::sexpr::PREFIXES.iter().position(|&p| p == prefix).expect("prefixes are one of the reader's");
+                Node::Prefixed(index as u8, Box::new(sexpr.to_interned(table)))
+            }
+            Compound(ref head, ref args) => {
+                let elements = core::iter::once(&**head).chain(args.iter());
+                Node::List(elements.map(|sexpr| sexpr.to_interned(table)).collect(), self.is_bracketed())
+            }
+        }
+    }
+    /// Read every top-level form of `input`, interning its text in `table`
+    /// the reader builds the `Node`s straight away, so no `Sexpr` is ever built for them
+    ///
+    /// ```
+    /// use sexpr_fmt::Sexpr;
+    /// use sexpr_fmt::intern::SymbolTable;
+    ///
+    /// let mut table = SymbolTable::new();
+    /// let forms = Sexpr::parse_many_interned("(assert (> x 0)) (assert (< x 9))", &mut table).unwrap();
+    /// assert_eq!(table.len(), 6);
+    /// let assert = table.get("assert").unwrap();
+    /// assert!(forms.iter().all(|form| form.head().is_some_and(|head| head.is_named(assert))));
+    /// assert!(!forms[0].is_named(assert));
+    /// assert_eq!(forms[1].to_sexpr(&table).to_compact_string(), "(assert (< x 9))");
+    /// let forms = Sexpr::parse_many_interned("(let ([x 1]) x)", &mut table).unwrap();
+    /// assert_eq!(forms[0].to_sexpr(&table).to_compact_string(), "(let ([x 1]) x)");
+    /// ```
+    pub fn parse_many_interned(input: &'s str, table: &mut SymbolTable<'s>) -> Result<Vec<Node>, ParseError> {
+        let mut interner = Interner { table };
+        let mut nodes = Vec::new();
+        let mut remaining = input.trim();
+        while !remaining.is_empty() {
+            let (node, tail) = crate::sexpr::read(&mut interner, remaining, input)?;
+            nodes.push(node.ok_or_else(|| crate::sexpr::trailing_error(remaining))?);
+            remaining = tail.trim();
+        }
+        Ok(nodes)
+    }
+}
+
+/// Builds the `Node`s of the forms the reader reads, interning their text in `table` as it goes
+struct Interner<'t, 's> {
+    table: &'t mut SymbolTable<'s>,
+}
+impl<'s> Build<'s> for Interner<'_, 's> {
+    type Output = Node;
+    fn atom(&mut self, text: &'s str) -> Node {
+        Node::Atom(self.table.intern(text))
+    }
+    fn comment(&mut self, text: &'s str) -> Node {
+        Node::Comment(self.table.intern(text))
+    }
+    fn prefixed(&mut self, prefix: &'static str, node: Node) -> Node {
+        let index = crate::sexpr::PREFIXES.iter().position(|&p| p == prefix).expect("prefixes are one of the reader's");
+        Node::Prefixed(index as u8, Box::new(node))
+    }
+    fn list(&mut self, head: Node, args: Vec<Node>, brackets: bool) -> Node {
+        let mut elements = args;
+        elements.insert(0, head);
+        Node::List(elements.into_boxed_slice(), brackets)
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+use std::str::FromStr;
+use serde_json::{Value, Map};
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+use crate::sexpr::PREFIXES;
+
+/// How the keys of json objects become atoms, since dialects spell them differently
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyStyle {
+    /// bare atoms like `key`, quoted like `"a key"` when they would not be read back as one atom
+    Auto,
+    /// keywords like `:key`, with awkward keys escaped like `:|a key|`
+    Keyword,
+    /// strings like `"key"`
+    String,
+    /// bare symbols like `key`, with awkward keys escaped like `|a key|`
+    Symbol,
+}
+impl KeyStyle {
+    pub const ALL: &'static [KeyStyle] = &[KeyStyle::Auto, KeyStyle::Keyword, KeyStyle::String, KeyStyle::Symbol];
+    /// The atom for the object key `key`
+    pub fn key_atom(self, key: &str) -> String {
+        match self {
+            KeyStyle::Auto => json_string_atom(key),
+            KeyStyle::Keyword => format!(":{}", symbol_atom(key)),
+            KeyStyle::String => Value::String(key.to_string()).to_string(),
+            KeyStyle::Symbol => symbol_atom(key),
+        }
+    }
+}
+impl fmt::Display for KeyStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyStyle::Auto => "auto",
+            KeyStyle::Keyword => "keyword",
+            KeyStyle::String => "string",
+            KeyStyle::Symbol => "symbol",
+        })
+    }
+}
+impl FromStr for KeyStyle {
+    type Err = String;
+    fn from_str(s: &str) -> Result<KeyStyle, String> {
+        KeyStyle::ALL.iter()
+            .copied()
+            .find(|style| style.to_string() == s)
+            .ok_or_else(|| format!("unknown key style `{}`, expected one of: auto, keyword, string, symbol", s))
+    }
+}
+
+impl Sexpr<'_> {
+    /// Convert a json value into a sexpr
+    /// this is the inverse of `to_json`, but also accepts arbitrary json:
+    /// arrays `[a, b, c]` become lists in brackets `[a b c]`, objects become lists in parens `(key value ...)`,
+    /// so that the two can be told apart (even `[]` and `{}`), and null, numbers, booleans, and strings become atoms
+    ///
+    /// ```
+    /// use sexpr_fmt::Sexpr;
+    ///
+    /// let value = serde_json::json!({"a": [1, 2], "b": {}, "c": []});
+    /// assert_eq!(Sexpr::from_json(&value).to_compact_string(), "(a [1 2] b () c [])");
+    /// ```
+    pub fn from_json(value: &Value) -> Sexpr<'static> {
+        Sexpr::from_json_with(value, KeyStyle::Auto)
+    }
+    /// Convert a json value into a sexpr like `from_json`, writing the keys of objects in the given style
+    pub fn from_json_with(value: &Value, keys: KeyStyle) -> Sexpr<'static> {
+        let from_json = |value| Sexpr::from_json_with(value, keys);
+        match value {
+            Value::Null => Sexpr::atom("null"),
+            Value::Bool(b) => Sexpr::atom(b.to_string()),
+            Value::Number(n) => Sexpr::atom(n.to_string()),
+            Value::String(text) => Sexpr::atom(json_string_atom(text)),
+            Value::Array(items) => list(items.iter().map(from_json).collect()).with_brackets(true),
+            Value::Object(map) => {
+                let brackets = map.get("brackets") == Some(&Value::Bool(true));
+                if let (Some(head), Some(Value::Array(args)), true) = (map.get("head"), map.get("args"), map.len() == 2 + brackets as usize) {
+                    // a null head is the blank head of `()`
+                    let head = if head.is_null() { Sexpr::blank() } else { from_json(head) };
+                    return Sexpr::compound(head, args.iter().map(from_json).collect()).with_brackets(brackets);
+                }
+                if let (Some(Value::String(prefix)), Some(expr), 2) = (map.get("prefix"), map.get("expr"), map.len()) {
+                    if let Some(prefix) = PREFIXES.iter().find(|p| *p == prefix) {
+                        return Sexpr::prefixed(prefix, from_json(expr));
+                    }
+                }
+                if let (Some(Value::String(text)), 1) = (map.get("comment"), map.len()) {
+                    return Sexpr::comment(text.clone());
+                }
+                let items = map.iter()
+                    .flat_map(|(key, value)| vec![Sexpr::atom(keys.key_atom(key)), from_json(value)])
+                    .collect();
+                list(items)
+            }
+        }
+    }
+
+    /// Convert this sexpr into a json value
+    /// atoms become strings, and compound expressions become `{"head": ..., "args": [...]}`,
+    /// with `"brackets": true` for those written in square brackets
+    /// the blank atom (such as the head of `()`) becomes `null`,
+    /// reader sugar like `'(a b)` becomes `{"prefix": "'", "expr": ...}`,
+    /// and comments become `{"comment": "#| ... |#"}`
+    pub fn to_json(&self) -> Value {
+        match self.kind {
+            _ if self.is_blank() => Value::Null,
+            Atom(ref text) => Value::String(text.to_string()),
+            Comment(ref text) => {
+                let mut map = Map::new();
+                map.insert("comment".to_string(), Value::String(text.to_string()));
+                Value::Object(map)
+            }
+            Prefixed(prefix, ref sexpr) => {
+                let mut map = Map::new();
+                map.insert("prefix".to_string(), Value::String(prefix.to_string()));
+                map.insert("expr".to_string(), sexpr.to_json());
+                Value::Object(map)
+            }
+            Compound(ref head, ref args) => {
+                let mut map = Map::new();
+                map.insert("head".to_string(), head.to_json());
+                map.insert("args".to_string(), Value::Array(args.iter().map(Sexpr::to_json).collect()));
+                if self.is_bracketed() {
+                    map.insert("brackets".to_string(), Value::Bool(true));
+                }
+                Value::Object(map)
+            }
+        }
+    }
+}
+
+/// Make a compound expression out of `items`, with the first one as the head
+fn list(items: Vec<Sexpr<'static>>) -> Sexpr<'static> {
+    let mut items = items.into_iter();
+    let head = items.next().unwrap_or_else(Sexpr::blank);
+    Sexpr::compound(head, items.collect())
+}
+
+/// The text of an atom holding a json string
+/// strings which would not be read back as a single atom are wrapped in quotes
+fn json_string_atom(text: &str) -> String {
+    if !text.is_empty() && text.chars().all(|ch| ch != '(' && ch != ')' && ch != '"' && ch != '|' && !ch.is_whitespace()) {
+        text.to_string()
+    } else {
+        Value::String(text.to_string()).to_string()
+    }
+}
+
+/// The text of a bare symbol for `text`
+/// symbols which would not be read back as a single atom are wrapped in bars, escaping any bars and backslashes inside
+fn symbol_atom(text: &str) -> String {
+    if !text.is_empty() && text.chars().all(|ch| ch != '(' && ch != ')' && ch != '"' && ch != '|' && !ch.is_whitespace()) {
+        text.to_string()
+    } else {
+        format!("|{}|", text.replace('\\', "\\\\").replace('|', "\\|"))
+    }
+}
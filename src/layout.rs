@@ -0,0 +1,77 @@
+use std::fmt;
+use crate::sexpr::{Sexpr, FormatArgs, SexprWrite};
+
+/// One step of the layout that the pretty printer settles on, for driving renderers other than plain text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LayoutEvent {
+    /// the start of the compound at `path`, just before its `(`
+    /// `multiline` says whether its arguments are broken onto their own lines
+    OpenGroup { path: Vec<usize>, multiline: bool },
+    /// the end of the innermost open group, just after its `)`
+    CloseGroup,
+    /// text to write as it is: atoms, comments, prefixes, and parens
+    /// only reflowed or multiline comments contain newlines
+    Text(String),
+    /// the separator between two elements of a group kept on one line, usually written as a space
+    SoftBreak,
+    /// a line break, with the next line indented by the given number of columns
+    Indent(usize),
+}
+
+/// Collects the events, merging adjacent text
+struct EventCollector {
+    events: Vec<LayoutEvent>,
+}
+impl fmt::Write for EventCollector {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.events.last_mut() {
+            Some(LayoutEvent::Text(text)) => text.push_str(s),
+            _ => self.events.push(LayoutEvent::Text(s.to_string())),
+        }
+        Ok(())
+    }
+}
+impl SexprWrite for EventCollector {
+    fn open_group(&mut self, path: &[usize], multiline: bool) {
+        self.events.push(LayoutEvent::OpenGroup { path: path.to_vec(), multiline });
+    }
+    fn close_group(&mut self) {
+        self.events.push(LayoutEvent::CloseGroup);
+    }
+    fn soft_break(&mut self) -> fmt::Result {
+        self.events.push(LayoutEvent::SoftBreak);
+        Ok(())
+    }
+    fn line_break(&mut self, indent: usize) -> fmt::Result {
+        self.events.push(LayoutEvent::Indent(indent));
+        Ok(())
+    }
+}
+
+impl Sexpr<'_> {
+    /// The layout events of formatting this sexpr with `args`
+    /// rendering `Text` as it is, `SoftBreak` as a space, and `Indent(n)` as a newline and `n` spaces gives `self.format(args)`
+    pub fn layout_events(&self, args: FormatArgs<'_>) -> Vec<LayoutEvent> {
+        let mut collector = EventCollector { events: Vec::new() };
+        self.write_helper(&mut collector, args, &mut Vec::new())
+            .expect("collecting layout events does not fail");
+        collector.events
+    }
+}
+
+/// Renders layout events back into text, the way the pretty printer would
+pub fn render(events: &[LayoutEvent]) -> String {
+    let mut out = String::new();
+    for event in events {
+        match event {
+            LayoutEvent::Text(text) => out.push_str(text),
+            LayoutEvent::SoftBreak => out.push(' '),
+            LayoutEvent::Indent(indent) => {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', *indent));
+            }
+            LayoutEvent::OpenGroup { .. } | LayoutEvent::CloseGroup => {}
+        }
+    }
+    out
+}
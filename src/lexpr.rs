@@ -0,0 +1,127 @@
+use std::convert::TryFrom;
+use ::lexpr::Value;
+use ::lexpr::print::{KeywordSyntax, Options};
+use crate::atom::{unquoted, AtomKind};
+use crate::builder::{SexprBuilder, SexprOwned};
+use crate::sexpr::{Sexpr, ParseError};
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+
+/// The symbols which reader prefixes like `'` stand for, when they are written out as lists
+/// (`,@` comes before `,`, so that it is found first)
+const PREFIX_NAMES: &[(&str, &str)] = &[(",@", "unquote-splicing"), ("'", "quote"), ("`", "quasiquote"), (",", "unquote")];
+
+/// How the values which are not lists are written as atoms, with keywords like `:key` to match `AtomKind::Keyword`
+fn print_options() -> Options {
+    Options::default().with_keyword_syntax(KeywordSyntax::ColonPrefix)
+}
+
+/// The value of an atom, judged from its text like `AtomKind` does
+/// numbers that do not fit a lexpr number (like `1/3`) and anything unknown are kept as symbols
+fn atom_value(text: &str) -> Value {
+    // the reader keeps a prefix before an atom, like `'x`, as part of the atom
+    let prefixed = PREFIX_NAMES.iter().find(|&&(prefix, _)| text.len() > prefix.len() && text.starts_with(prefix));
+    if let Some(&(prefix, name)) = prefixed {
+        return Value::list(vec![Value::symbol(name), atom_value(&text[prefix.len()..])]);
+    }
+    match AtomKind::of(text) {
+        AtomKind::String => match unquoted(text) {
+            Some(string) => Value::string(&*string),
+            None => Value::symbol(text),
+        },
+        AtomKind::Keyword => Value::keyword(&text[1..]),
+        AtomKind::Number => {
+            if let Ok(n) = text.parse::<u64>() {
+                Value::from(n)
+            } else if let Ok(n) = text.parse::<i64>() {
+                Value::from(n)
+            } else {
+                text.parse::<f64>().map_or_else(|_| Value::symbol(text), Value::from)
+            }
+        }
+        AtomKind::Symbol => match text {
+            "#t" | "#true" => Value::Bool(true),
+            "#f" | "#false" => Value::Bool(false),
+            _ => Value::symbol(text),
+        },
+    }
+}
+
+/// Convert to a `lexpr::Value`, without printing and reading the sexpr again
+/// a compound becomes a proper list (or an improper one, if it has a `.` before its last element),
+/// reader sugar like `'x` becomes `(quote x)`, and comments inside of it are left out;
+/// a comment on its own has no value, so it cannot be converted
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use sexpr_fmt::{Sexpr, SexprOwned};
+///
+/// let sexpr = Sexpr::parse("(set 'x \"a b\" :key 1.5 (a . b))").unwrap();
+/// let value = lexpr::Value::try_from(&sexpr).unwrap();
+/// assert_eq!(value, lexpr::sexp!((set (quote x) "a b" #:key 1.5 (a . b))));
+/// assert_eq!(SexprOwned::from(value).to_compact_string(), "(set (quote x) \"a b\" :key 1.5 (a . b))");
+/// ```
+impl TryFrom<&Sexpr<'_>> for Value {
+    type Error = ParseError;
+    fn try_from(sexpr: &Sexpr<'_>) -> Result<Value, ParseError> {
+        match sexpr.kind {
+            Comment(_) => Err("a comment has no value"),
+            _ if sexpr.is_blank() => Ok(Value::Null),
+            Atom(ref text) => Ok(atom_value(text)),
+            Prefixed(prefix, ref sexpr) => {
+                let &(_, name) = PREFIX_NAMES.iter().find(|&&(p, _)| p == prefix).expect("every prefix has a name");
+                Ok(Value::list(vec![Value::symbol(name), Value::try_from(&**sexpr)?]))
+            }
+            Compound(ref head, ref args) => {
+                let elements: Vec<&Sexpr<'_>> = std::iter::once(&**head).chain(args).filter(|element| !element.is_comment()).collect();
+                match elements.as_slice() {
+                    [before @ .., dot, last] if dot.is_named(".") && !before.is_empty() => {
+                        let before = before.iter().map(|&element| Value::try_from(element)).collect::<Result<Vec<_>, _>>()?;
+                        Ok(Value::append(before, Value::try_from(*last)?))
+                    }
+                    _ => Ok(Value::list(elements.into_iter().map(Value::try_from).collect::<Result<Vec<_>, _>>()?)),
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<Sexpr<'_>> for Value {
+    type Error = ParseError;
+    fn try_from(sexpr: Sexpr<'_>) -> Result<Value, ParseError> {
+        Value::try_from(&sexpr)
+    }
+}
+
+/// Convert a `lexpr::Value` to be formatted
+/// lists become compounds, an improper list like `(a . b)` keeps its `.`, the empty list is `()`,
+/// and everything else (including vectors and byte vectors) becomes an atom written the way lexpr prints it
+impl From<&Value> for SexprOwned {
+    fn from(value: &Value) -> SexprOwned {
+        match value {
+            Value::Null => SexprBuilder::list(Vec::new()),
+            Value::Cons(_) => {
+                let mut elements = Vec::new();
+                let mut rest = value;
+                while let Value::Cons(cons) = rest {
+                    elements.push(SexprOwned::from(cons.car()));
+                    rest = cons.cdr();
+                }
+                if !rest.is_null() {
+                    elements.push(SexprBuilder::atom("."));
+                    elements.push(SexprOwned::from(rest));
+                }
+                SexprBuilder::list(elements)
+            }
+            _ => {
+                let text = ::lexpr::print::to_string_custom(value, print_options()).expect("printing to a string does not fail");
+                SexprBuilder::atom(text)
+            }
+        }
+    }
+}
+
+impl From<Value> for SexprOwned {
+    fn from(value: Value) -> SexprOwned {
+        SexprOwned::from(&value)
+    }
+}
@@ -0,0 +1,93 @@
+//! Parsing, pretty printing, and transforming s-expressions
+//! without the `std` feature, only the parser and the printer are built, needing nothing more than `alloc`
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+/// The names from the standard prelude that a `no_std` crate has to bring in from `alloc`
+mod prelude {
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+    pub use alloc::{format, vec};
+}
+
+pub mod sexpr;
+#[cfg(feature = "std")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod annotate;
+pub mod numbers;
+#[cfg(feature = "std")]
+pub mod dot;
+pub mod aliases;
+#[cfg(feature = "std")]
+pub mod pattern;
+#[cfg(feature = "std")]
+pub mod query;
+#[cfg(feature = "std")]
+pub mod textdiff;
+#[cfg(feature = "std")]
+pub mod rewrite;
+#[cfg(feature = "std")]
+pub mod embedded;
+pub mod canonical;
+#[cfg(feature = "std")]
+pub mod treediff;
+pub mod lint;
+pub mod atom;
+#[cfg(feature = "std")]
+pub mod layout;
+#[cfg(feature = "std")]
+pub mod style;
+pub mod compat;
+#[cfg(feature = "std")]
+pub mod extract;
+#[cfg(feature = "std")]
+pub mod glob;
+#[cfg(feature = "std")]
+pub mod ignore;
+#[cfg(feature = "std")]
+pub mod rename;
+#[cfg(feature = "std")]
+pub mod elide;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod hash;
+#[cfg(feature = "std")]
+pub mod share;
+#[cfg(feature = "std")]
+pub mod tokens;
+#[cfg(feature = "std")]
+pub mod infix;
+#[cfg(feature = "std")]
+pub mod emacs;
+pub mod builder;
+pub mod incremental;
+pub mod intern;
+pub mod visit;
+#[cfg(feature = "std")]
+pub mod delimiters;
+#[cfg(feature = "std")]
+pub mod compress;
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "serde")]
+pub mod ser;
+#[cfg(feature = "serde")]
+pub mod de;
+#[cfg(feature = "lexpr")]
+pub mod lexpr;
+#[cfg(feature = "pyo3")]
+mod python;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "tree-sitter")]
+pub mod tree_sitter;
+
+pub use sexpr::{Sexpr, FormatArgs, ParseError, Span, SyntaxError};
+pub use builder::{SexprBuilder, SexprOwned};
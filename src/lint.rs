@@ -0,0 +1,37 @@
+use crate::prelude::*;
+/// A problem found in the source text, and the line it is on (starting from 1)
+#[derive(Debug, PartialEq)]
+pub struct Lint {
+    pub line: usize,
+    pub message: &'static str,
+}
+
+/// Find every line whose leading whitespace mixes tabs and spaces
+/// such lines look different in editors with different tab widths
+pub fn mixed_indentation(text: &str) -> Vec<Lint> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let leading = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+            leading.contains(' ') && leading.contains('\t')
+        })
+        .map(|(i, _)| Lint { line: i + 1, message: "leading whitespace mixes tabs and spaces" })
+        .collect()
+}
+
+/// Replace the tabs in the leading whitespace of each line with spaces, up to the next tab stop
+pub fn expand_leading_tabs(text: &str, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let rest = line.trim_start_matches([' ', '\t']);
+        let mut column = 0;
+        for ch in line[..line.len() - rest.len()].chars() {
+            let spaces = if ch == '\t' { tab_width - column % tab_width } else { 1 };
+            out.extend(core::iter::repeat_n(' ', spaces));
+            column += spaces;
+        }
+        out.push_str(rest);
+    }
+    out
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use serde_json::{json, Value};
+use crate::diagnostic::Diagnostic;
+
+/// Formats the text of a document: its path, its text, and the lines to format (counting from 1, including both ends),
+/// or all of it if there are none
+pub trait Formatter: Fn(&str, &str, Option<(usize, usize)>) -> Result<String, Diagnostic> {}
+impl<F: Fn(&str, &str, Option<(usize, usize)>) -> Result<String, Diagnostic>> Formatter for F {}
+
+/// Speak the language server protocol over stdin and stdout until the client says to exit,
+/// formatting documents (or ranges of them) when asked, and reporting the errors in them as they change
+pub fn serve(format: impl Formatter) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    let mut documents: HashMap<String, String> = HashMap::new();
+    let mut shutdown = false;
+    while let Some(message) = read_message(&mut input)? {
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+        let result = match message["method"].as_str().unwrap_or("") {
+            "initialize" => json!({
+                "capabilities": {
+                    // the client sends the whole text of a document whenever it changes
+                    "textDocumentSync": 1,
+                    "documentFormattingProvider": true,
+                    "documentRangeFormattingProvider": true,
+                },
+                "serverInfo": { "name": "sexpr-fmt", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => {
+                shutdown = true;
+                Value::Null
+            }
+            // like other servers, exit with 1 if the client never asked us to shut down
+            "exit" => std::process::exit(if shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("").to_string();
+                publish_diagnostics(&mut output, &format, &uri, &text)?;
+                documents.insert(uri, text);
+                continue;
+            }
+            "textDocument/didChange" => {
+                if let Some(text) = params["contentChanges"].as_array().and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str()) {
+                    publish_diagnostics(&mut output, &format, &uri, text)?;
+                    documents.insert(uri, text.to_string());
+                }
+                continue;
+            }
+            "textDocument/didClose" => {
+                documents.remove(&uri);
+                write_message(&mut output, &notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] })))?;
+                continue;
+            }
+            "textDocument/formatting" => edits(&format, &uri, documents.get(&uri), None),
+            "textDocument/rangeFormatting" => edits(&format, &uri, documents.get(&uri), Some(lines_of(&params["range"]))),
+            method => {
+                if id.is_some() {
+                    let error = json!({ "code": -32601, "message": format!("unknown method `{}`", method) });
+                    write_message(&mut output, &json!({ "jsonrpc": "2.0", "id": id, "error": error }))?;
+                }
+                continue;
+            }
+        };
+        if id.is_some() {
+            write_message(&mut output, &json!({ "jsonrpc": "2.0", "id": id, "result": result }))?;
+        }
+    }
+    Ok(())
+}
+
+/// The path of the file at `uri`, for matching overrides and for messages
+fn path_of(uri: &str) -> &str {
+    uri.strip_prefix("file://").unwrap_or(uri)
+}
+
+/// The edits which format `text`, the document at `uri`: the whole text replaced, or nothing if it is already formatted
+/// a document which can not be formatted has no edits, since its errors are reported as diagnostics
+fn edits(format: &impl Formatter, uri: &str, text: Option<&String>, lines: Option<(usize, usize)>) -> Value {
+    let text = match text {
+        Some(text) => text,
+        None => return Value::Null,
+    };
+    match format(path_of(uri), text, lines) {
+        Ok(formatted) if formatted == *text => json!([]),
+        Ok(formatted) => json!([{ "range": { "start": position(0, 0), "end": end_of(text) }, "newText": formatted }]),
+        Err(_) => Value::Null,
+    }
+}
+
+/// Tell the client about the error which stops `text` from being formatted, if there is one
+fn publish_diagnostics(output: &mut impl Write, format: &impl Formatter, uri: &str, text: &str) -> io::Result<()> {
+    let diagnostics = match format(path_of(uri), text, None) {
+        Ok(_) => Vec::new(),
+        Err(diagnostic) => {
+            let (line, column) = diagnostic.position.unwrap_or((1, 1));
+            vec![json!({ "range": range_at(text, line, column), "severity": 1, "source": "sexpr-fmt", "message": diagnostic.message })]
+        }
+    };
+    write_message(output, &notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": diagnostics })))
+}
+
+/// The lines of `text` a range of the protocol covers, counting from 1 and including both ends, like `--lines` does
+pub fn lines_of(range: &Value) -> (usize, usize) {
+    let first = range["start"]["line"].as_u64().unwrap_or(0) as usize + 1;
+    let mut last = range["end"]["line"].as_u64().unwrap_or(0) as usize + 1;
+    // a range ending at the very start of a line does not include that line
+    if range["end"]["character"].as_u64() == Some(0) && last > first {
+        last -= 1;
+    }
+    (first, last)
+}
+
+/// The range of the character at `line` and `column` of `text` (counting from 1, like a diagnostic does)
+pub fn range_at(text: &str, line: usize, column: usize) -> Value {
+    // a diagnostic's column counts characters, but the protocol counts utf-16 code units
+    let character: usize = text.lines().nth(line - 1).unwrap_or("")
+        .chars().take(column - 1).map(char::len_utf16).sum();
+    json!({ "start": position(line - 1, character), "end": position(line - 1, character + 1) })
+}
+
+pub fn position(line: usize, character: usize) -> Value {
+    json!({ "line": line, "character": character })
+}
+
+/// The position just after the last character of `text`
+pub fn end_of(text: &str) -> Value {
+    let line = text.matches('\n').count();
+    let last = &text[text.rfind('\n').map_or(0, |i| i + 1)..];
+    position(line, last.chars().map(char::len_utf16).sum())
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Read the next message, which is a header giving its length, a blank line, and then that much json
+/// gives None at the end of the input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
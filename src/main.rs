@@ -1,64 +1,2022 @@
 extern crate structopt;
 use crate::structopt::StructOpt;
+use crate::structopt::clap::{ErrorKind, Shell};
 
-mod sexpr;
-use sexpr::*;
+#[cfg(feature = "dev")]
+mod corpus;
+mod api;
+mod daemon;
+mod diagnostic;
+mod http;
+mod interactive;
+mod lsp;
+mod repl;
+mod watch;
+use sexpr_fmt::*;
+use sexpr_fmt::{annotate, canonical, delimiters, emacs, embedded, extract, glob, hash, ignore, lint, sexpr, stats, style, tokens, treediff};
+use sexpr_fmt::pipeline::Pipeline;
+use sexpr_fmt::numbers::PrecisionRule;
+use sexpr_fmt::aliases::AliasGroup;
+use sexpr_fmt::compat::LayoutVersion;
+use sexpr_fmt::delimiters::BracketStyle;
+use sexpr_fmt::compress::{self, Compression};
+use sexpr_fmt::sexpr::{CommentStyle, ComplexityMetric, SyntaxError};
+use sexpr_fmt::json::KeyStyle;
+use sexpr_fmt::query::Query;
+use sexpr_fmt::rewrite::Rule;
+use regex::Regex;
+use interactive::Review;
+use daemon::Framing;
+use diagnostic::{Diagnostic, ErrorFormat, Kind, exit};
 
-use std::io;
+use std::{fs, io, process};
+use std::borrow::Cow;
+use std::io::{IsTerminal, Read, Write};
 use std::error::Error;
+use std::str::FromStr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::ffi::OsString;
+use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
 
-#[derive(StructOpt)]
+/// The ways that a parsed s-expression can be written out
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Sexpr,
+    Json,
+    Dot,
+}
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "sexpr" => Ok(OutputFormat::Sexpr),
+            "json" => Ok(OutputFormat::Json),
+            "dot" => Ok(OutputFormat::Dot),
+            _ => Err(format!("unknown output format `{}`, expected one of: sexpr, json, dot", s)),
+        }
+    }
+}
+
+/// The ways that an s-expression can be read in
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputFormat {
+    Sexpr,
+    Json,
+}
+impl FromStr for InputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<InputFormat, String> {
+        match s {
+            "sexpr" => Ok(InputFormat::Sexpr),
+            "json" => Ok(InputFormat::Json),
+            _ => Err(format!("unknown input format `{}`, expected one of: sexpr, json", s)),
+        }
+    }
+}
+
+/// What to emit when writing an s-expression
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Emit {
+    Formatted,
+    Annotated,
+    StyleManifest,
+    TokensJson,
+    Infix,
+}
+impl FromStr for Emit {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Emit, String> {
+        match s {
+            "formatted" => Ok(Emit::Formatted),
+            "annotated" => Ok(Emit::Annotated),
+            "style-manifest" => Ok(Emit::StyleManifest),
+            "tokens-json" => Ok(Emit::TokensJson),
+            "infix" => Ok(Emit::Infix),
+            _ => Err(format!("unknown emit mode `{}`, expected one of: formatted, annotated, style-manifest, tokens-json, infix", s)),
+        }
+    }
+}
+
+/// What to do with text after the last form that cannot be read as an s-expression, such as log lines
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TrailingPolicy {
+    Error,
+    Ignore,
+    Keep,
+    Report,
+}
+impl FromStr for TrailingPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<TrailingPolicy, String> {
+        match s {
+            "error" => Ok(TrailingPolicy::Error),
+            "ignore" => Ok(TrailingPolicy::Ignore),
+            "keep" => Ok(TrailingPolicy::Keep),
+            "report" => Ok(TrailingPolicy::Report),
+            _ => Err(format!("unknown trailing text policy `{}`, expected one of: error, ignore, keep, report", s)),
+        }
+    }
+}
+
+/// A range of source lines, written `start:end`, counting from 1 and including both ends
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LineRange {
+    start: usize,
+    end: usize,
+}
+impl FromStr for LineRange {
+    type Err = String;
+    fn from_str(s: &str) -> Result<LineRange, String> {
+        let (start, end) = s.split_once(':')
+            .ok_or_else(|| format!("expected a line range like `3:10`, found `{}`", s))?;
+        let line = |text: &str| text.parse::<usize>().ok().filter(|&line| line > 0)
+            .ok_or_else(|| format!("expected a line number (counting from 1), found `{}`", text));
+        let (start, end) = (line(start)?, line(end)?);
+        if start > end {
+            return Err(format!("the line range `{}` ends before it starts", s));
+        }
+        Ok(LineRange { start, end })
+    }
+}
+impl LineRange {
+    /// Whether any of the lines `first` through `last` are in this range
+    fn intersects(&self, first: usize, last: usize) -> bool {
+        first <= self.end && last >= self.start
+    }
+}
+
+/// A setting that an override can change, written `name=value` after the flag of the same name
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Setting {
+    ComplexityThreshold(u32),
+    ComplexityMetric(ComplexityMetric),
+    ShortQuantifiers(bool),
+    InlineUnquoted(bool),
+    ReflowComments(bool),
+    AlignNumbers(bool),
+    PairKeywords(bool),
+    AlignBindings(bool),
+    /// `max-width=none` takes away a maximum width given before
+    MaxWidth(Option<usize>),
+    HardWrap(bool),
+    CommentWidth(usize),
+    CommentStyle(CommentStyle),
+    KeepBlankLines(usize),
+    TabWidth(usize),
+    Compat(LayoutVersion),
+    NormalizeBrackets(BracketStyle),
+}
+impl Setting {
+    /// The name of each setting, which is also the name of its flag
+    const NAMES: &'static [&'static str] = &[
+        "complexity-threshold", "complexity-metric", "short-quantifiers", "inline-unquoted", "reflow-comments",
+        "align-numbers", "pair-keywords", "align-bindings", "max-width", "hard-wrap", "comment-width", "comment-style",
+        "keep-blank-lines", "tab-width", "compat", "normalize-brackets",
+    ];
+}
+impl FromStr for Setting {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Setting, String> {
+        let (name, value) = s.split_once('=')
+            .ok_or_else(|| format!("expected a setting like `complexity-threshold=2`, found `{}`", s))?;
+        let (name, value) = (name.trim(), value.trim());
+        fn value_of<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value.parse().map_err(|_| format!("invalid value `{}` for the setting `{}`", value, name))
+        }
+        match name {
+            "complexity-threshold" => Ok(Setting::ComplexityThreshold(value_of(name, value)?)),
+            "complexity-metric" => Ok(Setting::ComplexityMetric(value.parse()?)),
+            "short-quantifiers" => Ok(Setting::ShortQuantifiers(value_of(name, value)?)),
+            "inline-unquoted" => Ok(Setting::InlineUnquoted(value_of(name, value)?)),
+            "reflow-comments" => Ok(Setting::ReflowComments(value_of(name, value)?)),
+            "align-numbers" => Ok(Setting::AlignNumbers(value_of(name, value)?)),
+            "pair-keywords" => Ok(Setting::PairKeywords(value_of(name, value)?)),
+            "align-bindings" => Ok(Setting::AlignBindings(value_of(name, value)?)),
+            "max-width" if value == "none" => Ok(Setting::MaxWidth(None)),
+            "max-width" => Ok(Setting::MaxWidth(Some(value_of(name, value)?))),
+            "hard-wrap" => Ok(Setting::HardWrap(value_of(name, value)?)),
+            "comment-width" => Ok(Setting::CommentWidth(value_of(name, value)?)),
+            "comment-style" => Ok(Setting::CommentStyle(value.parse()?)),
+            "keep-blank-lines" => Ok(Setting::KeepBlankLines(value_of(name, value)?)),
+            "tab-width" => Ok(Setting::TabWidth(value_of(name, value)?)),
+            "compat" => Ok(Setting::Compat(value.parse()?)),
+            "normalize-brackets" => Ok(Setting::NormalizeBrackets(value.parse()?)),
+            _ => Err(format!("unknown setting `{}`, expected one of: {}", name, Setting::NAMES.join(", "))),
+        }
+    }
+}
+
+/// Settings for the files matching a glob, written `glob:name=value,name=value`, such as `smt/**:complexity-threshold=2`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Override {
+    glob: String,
+    settings: Vec<Setting>,
+}
+impl FromStr for Override {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Override, String> {
+        let (glob, settings) = s.rsplit_once(':')
+            .ok_or_else(|| format!("expected an override like `*.smt2:complexity-threshold=2`, found `{}`", s))?;
+        let settings = settings.split(',').map(str::parse).collect::<Result<_, _>>()?;
+        Ok(Override { glob: glob.to_string(), settings })
+    }
+}
+
+#[derive(StructOpt, Clone)]
+pub enum Command {
+    // print only the subtrees matching a pattern like `(assert _)` or `(= $x $x)`, or at a path like `.2.1`
+    Query {
+        // the pattern or path to look for
+        query: String,
+        // the file to search, instead of reading from stdin
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
+    // apply rules like `(and $x true) => $x` bottom-up, then format the result
+    Rewrite {
+        // treat `rules` as the text of the rules, rather than the path of a file containing them
+        #[structopt(short, long)]
+        expr: bool,
+        // the file containing the rules
+        rules: String,
+        // the file to rewrite, instead of reading from stdin
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
+    // compare the forms of two files structurally, ignoring whitespace and layout
+    Diff {
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+    },
+    // report lines whose indentation mixes tabs and spaces
+    Lint {
+        // the files to check, instead of reading from stdin
+        #[structopt(parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    // report the size and depth of each top-level form, the most common atoms, and a histogram of the sizes
+    Stats {
+        // how many of the most common atoms to list
+        #[structopt(long, default_value = "10")]
+        top: usize,
+        // the files to measure, instead of reading from stdin
+        #[structopt(parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    // print a hash of the structure of each top-level form, which ignores layout and comments
+    Hash {
+        // print one hash for each file, rather than one for each form
+        #[structopt(long)]
+        whole_file: bool,
+        // the files to hash, instead of reading from stdin
+        #[structopt(parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    // format sample files with a grid of complexity thresholds and maximum widths, report the line count,
+    // average line length, and widest line of each, and recommend the settings which fit the target width in the fewest lines
+    // (the indentation is always 4 spaces, so it is not part of the grid)
+    Tune {
+        // the widest the recommended settings may make a line
+        #[structopt(long, default_value = "100")]
+        target_width: usize,
+        // the sample files
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+    },
+    // commands for working on sexpr-fmt itself, which need it to be built with the `dev` feature
+    Dev(DevCommand),
+    // find and format the s-expressions inside of a log (or any other text), skipping everything else
+    ExtractFromLog {
+        // the fewest atoms, comments, and lists an s-expression can have to be extracted
+        #[structopt(long, default_value = "5")]
+        min_nodes: usize,
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    // print a script completing the flags, subcommands, and their values in a shell, to source from its startup file,
+    // like `sexpr-fmt completions bash > /etc/bash_completion.d/sexpr-fmt`
+    Completions {
+        // the shell to complete in (bash, zsh, fish, powershell, elvish)
+        #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+        shell: Shell,
+    },
+}
+
+#[derive(StructOpt, Clone)]
+pub enum DevCommand {
+    // download the public s-expression files used for benchmarks and golden tests into a local cache
+    FetchCorpus {
+        // the directory to download into (by default, the corpus directory of the user's cache)
+        #[structopt(long, parse(from_os_str))]
+        dir: Option<PathBuf>,
+        // download the files again, even if they are already there
+        #[structopt(long)]
+        refresh: bool,
+    },
+}
+
+#[derive(StructOpt, Clone)]
 pub struct CmdArgs {
     // activate silent mode
     #[structopt(short, long)]
     silent: bool,
-    // expect multiple lines of user input from stdin
+    // does nothing: user input is always read until the parens of the form are balanced (kept for old scripts)
     #[structopt(short, long)]
     multiline: bool,
+    // run a language server over stdin and stdout, for editors to format documents and show their errors with
+    #[structopt(long)]
+    lsp: bool,
+    // answer http requests on this address, like `127.0.0.1:7878`: a POST to /preview with `{"content": "...", "options": [...]}`
+    // formats the content with each set of settings like `{"complexity-threshold": 2}`, for comparing styles side by side
+    #[structopt(long)]
+    serve: Option<String>,
+    // format each request read from stdin and write the response to stdout, for editors which format on every save
+    // without starting a process each time: with `nul`, each message ends with a NUL byte, and with `length`,
+    // each starts with a line giving its length in bytes; each response starts with `ok` or `error`
+    #[structopt(long, possible_values = &["nul", "length"])]
+    daemon: Option<Framing>,
+    // answer json requests read from stdin, one on each line, like `{"apiVersion": 1, "text": "...", "options": {...}}`,
+    // with the edits which format their text, one response on each line, for editor extensions such as one for VS Code
+    #[structopt(long)]
+    api: bool,
+    // print the version of the json schema of --api and of sexpr-fmt, like `{"apiVersion": 1, "version": "0.1.0"}`,
+    // for an editor extension to check that it can talk to us
+    #[structopt(long)]
+    api_version: bool,
+    // read forms one after the other, printing each formatted as soon as its parens are balanced
+    #[structopt(long)]
+    repl: bool,
+    // re-indent stdin the way `indent-region` does in a plain Emacs, keeping every line break where it is;
+    // a first line like `12 40` gives point and mark, limiting it to the lines between them, and their new positions are written back
+    #[structopt(long, alias = "indent-sexp")]
+    emacs: bool,
     // activate debug mode
     #[structopt(short, long)]
     debug: bool,
+    // explain settings with surprising effects, like a --complexity-threshold which lays out the input the same as the default
+    #[structopt(short, long, alias = "explain")]
+    verbose: bool,
+    // start from the settings of a profile: smt, clojure-community, kicad, compact, or one defined in a `.sexpr-fmt` file
+    // in the current directory or one above it, like `(profile team (complexity-threshold 2) (short-quantifiers true))`;
+    // the flags given along with it override the ones of the profile
+    #[structopt(long)]
+    profile: Option<String>,
     // the nesting depth of a s-expression to display on a single line
+    // (or its number of nodes, or its width, with another --complexity-metric)
     #[structopt(short, long, default_value = "1")]
     complexity_threshold: u32,
+    // how the complexity compared with the threshold is measured: `depth`, `nodes` (atoms and lists), or `width` (on one line)
+    #[structopt(long, default_value = "depth", possible_values = &["depth", "nodes", "width"])]
+    complexity_metric: ComplexityMetric,
     // squish the arguments of quantifiers onto the same line
     #[structopt(short = "q", long)]
     short_quantifiers: bool,
+    // keep the first argument of forms with these heads on the head's line, e.g. `lambda,exists!,pi`
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    inline_first_arg_of: Vec<String>,
+    // always break forms with these heads over several lines, however simple they are, e.g. `assert,define-fun`
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    always_break: Vec<String>,
+    // keep forms with these heads on one line, however complex they are, e.g. `check-sat,attributes`
+    // (a form with a line comment inside of it is still broken up)
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    never_break: Vec<String>,
+    // keep the unquoted fragments (`,x` and `,@x`) of quasiquoted templates on one line
+    #[structopt(long)]
+    inline_unquoted: bool,
+    // re-wrap the prose of block comments (`#| ... |#`) to the comment width
+    #[structopt(long)]
+    reflow_comments: bool,
+    // right-align the numbers in the columns of runs of rows like `(at 1.0 2.54)` and `(at 10.5 -3)`,
+    // and of the rows nested in them, like `(pad 1 smd (at 1.0 2.54))` and `(pad 22 smd (at -10.25 3))`
+    #[structopt(long)]
+    align_numbers: bool,
+    // keep keywords like `:key` on the same line as the value after them, when breaking a form over several lines
+    #[structopt(long)]
+    pair_keywords: bool,
+    // line up the values in the binding lists of `let`, `let*`, and `parameterize` forms
+    #[structopt(long)]
+    align_bindings: bool,
+    // the widest a line may be: forms which would be wider are broken over several lines, even if they are simple
+    #[structopt(long)]
+    max_width: Option<usize>,
+    // split atoms which are too long for the maximum width over several lines, ending each with a continuation marker
+    // (these are joined back together when reading the input with the same marker)
+    #[structopt(long)]
+    hard_wrap: bool,
+    // the marker ending each line of a split atom
+    #[structopt(long, default_value = sexpr::DEFAULT_CONTINUATION_MARKER)]
+    continuation_marker: String,
+    // the width to wrap block comments to
+    #[structopt(long, default_value = "80")]
+    comment_width: usize,
+    // where a line comment which trails a form, like `(f x) ; note`, is written:
+    // `preserve` keeps each comment where it was, `attach-trailing` also breaks up the form if the comment would not fit after it,
+    // and `own-line` moves it onto its own line above the form
+    #[structopt(long, default_value = "preserve", possible_values = &["preserve", "attach-trailing", "own-line"])]
+    comment_style: CommentStyle,
+    // the most blank lines to keep between two top-level forms, of the ones between them in the input
+    #[structopt(long, default_value = "1")]
+    keep_blank_lines: usize,
+    // the number of columns a tab stands for, when turning tabs in comments into spaces, and in the indentation written by --emacs
+    #[structopt(long, default_value = "8")]
+    tab_width: usize,
+    // treat a family of heads the same for head-specific rules, e.g. `defun=define=define-fun`
+    #[structopt(long, number_of_values = 1)]
+    alias: Vec<AliasGroup>,
+    // lay out the output like release X.Y did, so that upgrading does not reformat every file
+    #[structopt(long, possible_values = &["0.1", "0.2", "0.3"])]
+    compat: Option<LayoutVersion>,
+    // what to do with text after the last form that is not an s-expression (error, ignore with a warning, keep it as it is,
+    // or report the error but still format the forms before it, keeping the rest as it is, and exit with the parse error code)
+    #[structopt(long, default_value = "error", possible_values = &["error", "ignore", "keep", "report"])]
+    trailing: TrailingPolicy,
+    // change the closing delimiter of each list closed by the wrong one, like the `]` of `(a b]`, to the one it was opened with,
+    // instead of failing with an error saying where the list was opened
+    #[structopt(long)]
+    fix_delimiters: bool,
+    // how to write square brackets: `preserve` keeps them as they are, `round` changes them all to parens,
+    // and `racket` changes them to parens but writes the clauses of `cond` and the bindings of `let` and the like in brackets
+    #[structopt(long, default_value = "preserve", possible_values = &["round", "preserve", "racket"])]
+    normalize_brackets: BracketStyle,
+    // close the lists which are still open at the end of the input, with a warning saying where each was opened,
+    // instead of failing because of a missing `)`
+    #[structopt(long, conflicts_with = "strict")]
+    lenient: bool,
+    // reject closing delimiters with nothing open for them to close, even with --trailing, and tabs inside of strings and `|symbols|`
+    #[structopt(long)]
+    strict: bool,
+    // keep each top-level form which cannot be read as it is, up to where its parens balance, with a warning,
+    // instead of failing on the whole file because of one construct the reader does not know, like a string with a `)`
+    #[structopt(long)]
+    opaque_fallback: bool,
+    // the format to read the input in (sexpr, json)
+    #[structopt(long, default_value = "sexpr", possible_values = &["sexpr", "json"])]
+    input_format: InputFormat,
+    // how the keys of json objects become atoms (auto, keyword like `:key`, string like `"key"`, symbol like `|a key|`)
+    #[structopt(long, default_value = "auto", possible_values = &["auto", "keyword", "string", "symbol"])]
+    json_keys: KeyStyle,
+    // fix the decimal places of decimal arguments to forms with a given head, e.g. `at=4`, or `*=4` for every form
+    #[structopt(long, number_of_values = 1)]
+    fix_precision: Vec<PrecisionRule>,
+    // sort the arguments of these commutative forms, e.g. `and,or,set`
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    sort_args: Vec<String>,
+    // sort each run of consecutive top-level forms with one of these heads, e.g. `declare-fun,declare-const`
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    sort_toplevel: Vec<String>,
+    // rename the variables bound by quantifiers, lambdas, and lets to `x0`, `x1`, ... in the order they are bound
+    #[structopt(long)]
+    rename_bound: bool,
+    // bind the large subterms which appear more than once with a `let`, and refer to them by name, to shrink huge terms
+    #[structopt(long)]
+    share_common_subterms: bool,
+    // with --share-common-subterms, the fewest atoms, comments, and lists a subterm must have to be shared
+    #[structopt(long, default_value = "8")]
+    min_shared_nodes: usize,
+    // print each form on one line, with only the spaces needed to read it back
+    #[structopt(long)]
+    minify: bool,
+    // end each top-level form printed with a comment like `; 1.2k nodes`, to see where the bulk of a huge file is
+    // (only for reading: the comments would not survive being formatted again)
+    #[structopt(long, conflicts_with_all = &["write", "check", "minify", "lsp"])]
+    annotate_sizes: bool,
+    // with --annotate-sizes, also annotate each compound broken over several lines with at least this many nodes
+    #[structopt(long, requires = "annotate-sizes")]
+    annotate_sizes_over: Option<usize>,
+    // refuse to write output with a line longer than this many bytes, which editors and other tools might choke on
+    #[structopt(long, default_value = "1048576")]
+    max_line_bytes: usize,
+    // write output with lines longer than --max-line-bytes anyway, after warning about them
+    #[structopt(long)]
+    force: bool,
+    // report how long reading, parsing, printing, postprocessing, and writing each file took, on stderr
+    #[structopt(long)]
+    time: bool,
+    // map the files into memory instead of reading them, so that formatting a huge file takes little more memory than its size
+    // (the files must not be changed by anything else while they are being formatted)
+    #[structopt(long)]
+    mmap: bool,
+    // print compounds nested deeper than this as `(head …)`, to summarize huge terms
+    #[structopt(long)]
+    max_depth: Option<usize>,
+    // with --max-depth, keep the compounds along this path of every top-level form, like `.2.1`, and expand the subtree at its end
+    #[structopt(long, number_of_values = 1, requires = "max-depth", parse(try_from_str = query::parse_path))]
+    expand: Vec<Vec<usize>>,
+    // move definitions before the top-level forms that refer to them, keeping the other forms in order
+    #[structopt(long)]
+    sort_definitions: bool,
+    // the heads of the forms which define names, for sorting definitions (by default, declare-fun, define-fun, define, defun, ...)
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    definition_forms: Vec<String>,
+    // the format to write the parsed s-expression in (sexpr, json, dot)
+    #[structopt(long, default_value = "sexpr", possible_values = &["sexpr", "json", "dot"])]
+    output_format: OutputFormat,
+    // what to emit for s-expression output (formatted, annotated with node paths, style-manifest for the effective style alone,
+    // or tokens-json for the spans and nesting depths of the tokens of the input as it is,
+    // or infix for a one-line formula like `x * 2 > x`)
+    #[structopt(long, default_value = "formatted", possible_values = &["formatted", "annotated", "style-manifest", "tokens-json", "infix"])]
+    emit: Emit,
+    // with --emit infix, put parens around every operand that is itself an operator, instead of only where they are needed
+    #[structopt(long)]
+    full_parens: bool,
+    // only format the top-level forms touching these lines, like `3:10`, leaving the rest of the file as it is
+    #[structopt(long)]
+    line_range: Option<LineRange>,
+    // record the style in a comment at the end of each formatted file, replacing any recorded before
+    #[structopt(long)]
+    embed_style: bool,
+    // run several stages over each top-level form of the input, e.g. `from-sexpr | fmt -c 2`
+    #[structopt(long)]
+    pipeline: Option<String>,
+    // report the files (or the input) which are not formatted, exiting with 1 if there are any, instead of printing them
+    #[structopt(long)]
+    check: bool,
+    // with --check, read the top-level forms of stdin as they arrive, reporting each form which is not formatted right away
+    #[structopt(long, requires = "check")]
+    stream: bool,
+    // pipe each formatted result through this shell command before writing it, like `pygmentize -l scheme`;
+    // the command gets the name of the file being formatted in $SEXPR_FMT_FILE
+    #[structopt(long)]
+    postprocess: Option<String>,
+    // write the formatted files back in place, instead of to stdout
+    #[structopt(short, long)]
+    write: bool,
+    // compress the output with `gzip` or `zstd`, or write it as it is with `none`;
+    // without it, stdout is written as it is, and a file written back keeps the compression it was read with
+    #[structopt(long, possible_values = &["none", "gzip", "zstd"])]
+    compress: Option<Compression>,
+    // show the changes to each file hunk by hunk, and ask whether to apply each one
+    #[structopt(long)]
+    interactive: bool,
+    // only format the fenced s-expression blocks inside of a host file (such as markdown or rust)
+    #[structopt(long)]
+    embedded: bool,
+    // a marker which opens a fenced block in embedded mode (by default, ```lisp, ```scheme, ```smt2, ...)
+    #[structopt(long, number_of_values = 1)]
+    fence_start: Vec<String>,
+    // the marker which closes a fenced block in embedded mode
+    #[structopt(long, default_value = embedded::DEFAULT_FENCE_END)]
+    fence_end: String,
+    // in embedded mode, a regex matching the blocks to format instead of the markers, like `(?s)#\+begin_src lisp\n(.*?)#\+end_src`
+    // only its group named `sexpr` (or else its first group) is formatted, so it must have one
+    #[structopt(long, requires = "embedded", conflicts_with = "fence-start", parse(try_from_str = embedded::fence_regex))]
+    fence: Option<Regex>,
+    // keep running, and format the files (or the files in the directories) again whenever they change
+    #[structopt(long)]
+    watch: bool,
+    // how often to check the watched files for changes, in milliseconds
+    #[structopt(long, default_value = "500")]
+    watch_interval: u64,
+    // change settings for the files matching a glob, like `smt/**:complexity-threshold=2,short-quantifiers=true`
+    // when several match a file, the later ones win
+    #[structopt(long = "override", number_of_values = 1)]
+    overrides: Vec<Override>,
+    // how many files to format at once
+    #[structopt(short, long, default_value = "1")]
+    jobs: usize,
+    // with several jobs, print each file as soon as it is done, instead of in the order they were given
+    #[structopt(long)]
+    unordered: bool,
+    // how to write errors and other diagnostics to stderr (human, or json with one object per line)
+    #[structopt(long, default_value = "human", possible_values = &["human", "json"])]
+    error_format: ErrorFormat,
+    // the name of the file being piped through stdin, for messages; editors pass this when using us as a filter
+    #[structopt(long)]
+    stdin_filename: Option<String>,
+    // the files to format, instead of reading from stdin
+    #[structopt(parse(from_os_str))]
+    files: Vec<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 impl CmdArgs {
     pub fn noisy(&self) -> bool { !self.silent }
     pub fn multiline(&self) -> bool { self.multiline }
+    pub fn lsp(&self) -> bool { self.lsp }
+    pub fn serve(&self) -> Option<&str> { self.serve.as_deref() }
+    pub fn daemon(&self) -> Option<Framing> { self.daemon }
+    pub fn api(&self) -> bool { self.api }
+    pub fn api_version(&self) -> bool { self.api_version }
+    pub fn repl(&self) -> bool { self.repl }
+    pub fn emacs(&self) -> bool { self.emacs }
     pub fn debug(&self) -> bool { self.debug }
+    pub fn verbose(&self) -> bool { self.verbose }
+    pub fn profile(&self) -> Option<&str> { self.profile.as_deref() }
     pub fn complexity_threshold(&self) -> u32 { self.complexity_threshold }
+    pub fn complexity_metric(&self) -> ComplexityMetric { self.complexity_metric }
     pub fn short_quantifiers(&self) -> bool { self.short_quantifiers }
+    pub fn inline_first_arg_of(&self) -> &[String] { &self.inline_first_arg_of }
+    pub fn always_break(&self) -> &[String] { &self.always_break }
+    pub fn never_break(&self) -> &[String] { &self.never_break }
+    pub fn inline_unquoted(&self) -> bool { self.inline_unquoted }
+    pub fn reflow_comments(&self) -> bool { self.reflow_comments }
+    pub fn align_numbers(&self) -> bool { self.align_numbers }
+    pub fn pair_keywords(&self) -> bool { self.pair_keywords }
+    pub fn align_bindings(&self) -> bool { self.align_bindings }
+    pub fn max_width(&self) -> Option<usize> { self.max_width }
+    pub fn hard_wrap(&self) -> bool { self.hard_wrap }
+    pub fn continuation_marker(&self) -> &str { &self.continuation_marker }
+    pub fn comment_width(&self) -> usize { self.comment_width }
+    pub fn comment_style(&self) -> CommentStyle { self.comment_style }
+    pub fn keep_blank_lines(&self) -> usize { self.keep_blank_lines }
+    pub fn tab_width(&self) -> usize { self.tab_width }
+    pub fn aliases(&self) -> &[AliasGroup] { &self.alias }
+    pub fn compat(&self) -> LayoutVersion { self.compat.unwrap_or(LayoutVersion::CURRENT) }
+    pub fn trailing(&self) -> TrailingPolicy { self.trailing }
+    pub fn fix_delimiters(&self) -> bool { self.fix_delimiters }
+    pub fn normalize_brackets(&self) -> BracketStyle { self.normalize_brackets }
+    pub fn lenient(&self) -> bool { self.lenient }
+    pub fn strict(&self) -> bool { self.strict }
+    pub fn opaque_fallback(&self) -> bool { self.opaque_fallback }
+    pub fn input_format(&self) -> InputFormat { self.input_format }
+    pub fn json_keys(&self) -> KeyStyle { self.json_keys }
+    pub fn fix_precision(&self) -> &[PrecisionRule] { &self.fix_precision }
+    pub fn sort_args(&self) -> &[String] { &self.sort_args }
+    pub fn sort_toplevel(&self) -> &[String] { &self.sort_toplevel }
+    pub fn rename_bound(&self) -> bool { self.rename_bound }
+    pub fn share_common_subterms(&self) -> bool { self.share_common_subterms }
+    pub fn min_shared_nodes(&self) -> usize { self.min_shared_nodes }
+    pub fn minify(&self) -> bool { self.minify }
+    pub fn annotate_sizes(&self) -> bool { self.annotate_sizes }
+    pub fn annotate_sizes_over(&self) -> Option<usize> { self.annotate_sizes_over }
+    pub fn max_line_bytes(&self) -> usize { self.max_line_bytes }
+    pub fn force(&self) -> bool { self.force }
+    pub fn time(&self) -> bool { self.time }
+    pub fn mmap(&self) -> bool { self.mmap }
+    pub fn error_format(&self) -> ErrorFormat { self.error_format }
+    pub fn max_depth(&self) -> Option<usize> { self.max_depth }
+    pub fn expand(&self) -> &[Vec<usize>] { &self.expand }
+    pub fn sort_definitions(&self) -> bool { self.sort_definitions }
+    pub fn definition_forms(&self) -> Vec<String> {
+        if self.definition_forms.is_empty() {
+            canonical::DEFINITION_FORMS.iter().map(|head| head.to_string()).collect()
+        } else {
+            self.definition_forms.clone()
+        }
+    }
+    pub fn output_format(&self) -> OutputFormat { self.output_format }
+    pub fn emit(&self) -> Emit { self.emit }
+    pub fn full_parens(&self) -> bool { self.full_parens }
+    pub fn line_range(&self) -> Option<LineRange> { self.line_range }
+    pub fn embed_style(&self) -> bool { self.embed_style }
+    pub fn pipeline(&self) -> Option<&str> { self.pipeline.as_deref() }
+    pub fn check(&self) -> bool { self.check }
+    pub fn stream(&self) -> bool { self.stream }
+    pub fn postprocess(&self) -> Option<&str> { self.postprocess.as_deref() }
+    pub fn write(&self) -> bool { self.write }
+    pub fn compress(&self) -> Option<Compression> { self.compress }
+    pub fn interactive(&self) -> bool { self.interactive }
+    pub fn embedded(&self) -> bool { self.embedded }
+    pub fn fence_starts(&self) -> Vec<String> {
+        if self.fence_start.is_empty() {
+            embedded::DEFAULT_FENCE_STARTS.iter().map(|start| start.to_string()).collect()
+        } else {
+            self.fence_start.clone()
+        }
+    }
+    pub fn fence_end(&self) -> &str { &self.fence_end }
+    pub fn fence(&self) -> Option<&Regex> { self.fence.as_ref() }
+    pub fn watch(&self) -> bool { self.watch }
+    pub fn watch_interval(&self) -> Duration { Duration::from_millis(self.watch_interval) }
+    pub fn jobs(&self) -> usize { self.jobs }
+    pub fn unordered(&self) -> bool { self.unordered }
+    pub fn stdin_name(&self) -> &str { self.stdin_filename.as_deref().unwrap_or("<stdin>") }
+    pub fn piped(&self) -> bool { self.stdin_filename.is_some() || !io::stdin().is_terminal() }
+    pub fn files(&self) -> &[PathBuf] { &self.files }
+    pub fn command(&self) -> Option<&Command> { self.command.as_ref() }
+    /// These arguments, with the overrides for `file` applied
+    pub fn for_file(&self, file: &Path) -> Cow<'_, CmdArgs> {
+        let path = file.to_string_lossy().replace('\\', "/");
+        let path = compress::strip_extension(&path);
+        let mut matching = self.overrides.iter().filter(|o| glob::matches(&o.glob, path)).peekable();
+        if matching.peek().is_none() {
+            return Cow::Borrowed(self);
+        }
+        let settings: Vec<Setting> = matching.flat_map(|o| o.settings.iter().copied()).collect();
+        Cow::Owned(self.with_settings(&settings))
+    }
+    /// These arguments with `settings` changed, the later ones winning
+    pub fn with_settings(&self, settings: &[Setting]) -> CmdArgs {
+        let mut args = self.clone();
+        for setting in settings {
+            match *setting {
+                Setting::ComplexityThreshold(threshold) => args.complexity_threshold = threshold,
+                Setting::ComplexityMetric(metric) => args.complexity_metric = metric,
+                Setting::ShortQuantifiers(on) => args.short_quantifiers = on,
+                Setting::InlineUnquoted(on) => args.inline_unquoted = on,
+                Setting::ReflowComments(on) => args.reflow_comments = on,
+                Setting::AlignNumbers(on) => args.align_numbers = on,
+                Setting::PairKeywords(on) => args.pair_keywords = on,
+                Setting::AlignBindings(on) => args.align_bindings = on,
+                Setting::MaxWidth(width) => args.max_width = width,
+                Setting::HardWrap(on) => args.hard_wrap = on,
+                Setting::CommentWidth(width) => args.comment_width = width,
+                Setting::CommentStyle(style) => args.comment_style = style,
+                Setting::KeepBlankLines(lines) => args.keep_blank_lines = lines,
+                Setting::TabWidth(width) => args.tab_width = width,
+                Setting::Compat(version) => args.compat = Some(version),
+                Setting::NormalizeBrackets(style) => args.normalize_brackets = style,
+            }
+        }
+        args
+    }
+}
+
+/// The formatting arguments chosen on the command line
+fn format_args(cmd_args: &CmdArgs) -> FormatArgs<'_> {
+    let mut fmt_args = FormatArgs::new();
+    fmt_args.complexity_threshold = cmd_args.complexity_threshold();
+    fmt_args.complexity_metric = cmd_args.complexity_metric();
+    fmt_args.short_quantifiers = cmd_args.short_quantifiers();
+    fmt_args.inline_first_arg_of = cmd_args.inline_first_arg_of();
+    fmt_args.always_break = cmd_args.always_break();
+    fmt_args.never_break = cmd_args.never_break();
+    fmt_args.aliases = cmd_args.aliases();
+    fmt_args.inline_unquoted = cmd_args.inline_unquoted();
+    fmt_args.reflow_comments = cmd_args.reflow_comments();
+    fmt_args.comment_width = cmd_args.comment_width();
+    fmt_args.comment_style = cmd_args.comment_style();
+    fmt_args.tab_width = cmd_args.tab_width();
+    fmt_args.layout_version = cmd_args.compat();
+    fmt_args.align_numbers = cmd_args.align_numbers();
+    fmt_args.pair_keywords = cmd_args.pair_keywords();
+    fmt_args.align_bindings = cmd_args.align_bindings();
+    fmt_args.max_width = cmd_args.max_width();
+    fmt_args.hard_wrap = cmd_args.hard_wrap();
+    fmt_args.continuation_marker = cmd_args.continuation_marker();
+    fmt_args
 }
 
 fn read_input(args: &CmdArgs) -> Result<String, io::Error> {
+    let mut input = String::new();
+    if args.piped() {
+        // another program is giving us the input, so take all of it, without prompting
+        return read_stdin();
+    }
     if args.noisy() {
         println!("Input s-expression to format: ");
     }
-    let mut input = String::new();
-    let mut buf = String::new();
-    if args.multiline() {
-        loop {
-            io::stdin().read_line(&mut buf)?;
-            if buf.trim().is_empty() { break; }
-            input.push_str(&buf);
-            buf.clear();
+    // keep reading lines until the parens are balanced, so pasted forms may have blank lines in them
+    while io::stdin().read_line(&mut input)? != 0 && repl::is_unfinished(&input) {}
+    Ok(input)
+}
+
+/// All of stdin, decompressed if it is compressed
+fn read_stdin() -> io::Result<String> {
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    compress::decode(None, &bytes).map(|(text, _)| text)
+}
+
+/// The name of `file` for messages, or of the input if there is no file
+fn source_name(args: &CmdArgs, file: Option<&Path>) -> String {
+    file.map_or_else(|| args.stdin_name().to_string(), |file| file.display().to_string())
+}
+
+/// Read the contents of `file`, or the user's input if there is no file
+fn read_source(args: &CmdArgs, file: Option<&Path>) -> Result<String, io::Error> {
+    match file {
+        Some(file) => compress::read_to_string(file),
+        None => read_input(args),
+    }
+}
+
+/// Print every subtree of the input that matches `query`, one after the other
+fn run_query(args: &CmdArgs, query: &str, file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let query = Query::parse(query)?;
+    let input = read_source(args, file)?;
+    let mut stdout = io::stdout().lock();
+    for sexpr in diagnostic::parse_forms(&source_name(args, file), &input)?.iter() {
+        for found in query.find(sexpr) {
+            found.write_to(&mut stdout, format_args(args))?;
+            writeln!(stdout)?;
         }
+    }
+    Ok(())
+}
+
+/// Apply the transformations requested on the command line to `sexpr`
+fn transform(args: &CmdArgs, sexpr: &mut Sexpr<'_>) {
+    sexpr.fix_precision(args.fix_precision(), args.aliases());
+    if args.rename_bound() {
+        sexpr.rename_bound(args.aliases());
+    }
+    sexpr.sort_args(args.sort_args(), args.aliases());
+    if args.share_common_subterms() {
+        sexpr.share_common_subterms(args.min_shared_nodes(), args.aliases());
+    }
+    if let Some(max_depth) = args.max_depth() {
+        *sexpr = sexpr.elided(max_depth, args.expand());
+    }
+}
+
+/// The complete effective style, as a manifest that can be compared between runs
+fn style_manifest(args: &CmdArgs) -> Sexpr<'static> {
+    fn strings<T: ToString>(values: &[T]) -> Vec<String> {
+        values.iter().map(ToString::to_string).collect()
+    }
+    style::manifest(vec![
+        ("layout-version", vec![args.compat().to_string()]),
+        ("complexity-threshold", vec![args.complexity_threshold().to_string()]),
+        ("complexity-metric", vec![args.complexity_metric().to_string()]),
+        ("short-quantifiers", vec![args.short_quantifiers().to_string()]),
+        ("inline-first-arg-of", args.inline_first_arg_of().to_vec()),
+        ("always-break", args.always_break().to_vec()),
+        ("never-break", args.never_break().to_vec()),
+        ("inline-unquoted", vec![args.inline_unquoted().to_string()]),
+        ("reflow-comments", vec![args.reflow_comments().to_string()]),
+        ("align-numbers", vec![args.align_numbers().to_string()]),
+        ("pair-keywords", vec![args.pair_keywords().to_string()]),
+        ("align-bindings", vec![args.align_bindings().to_string()]),
+        ("minify", vec![args.minify().to_string()]),
+        ("max-width", args.max_width().iter().map(ToString::to_string).collect()),
+        ("hard-wrap", vec![args.hard_wrap().to_string()]),
+        ("continuation-marker", vec![format!("{:?}", args.continuation_marker())]),
+        ("comment-width", vec![args.comment_width().to_string()]),
+        ("comment-style", vec![args.comment_style().to_string()]),
+        ("keep-blank-lines", vec![args.keep_blank_lines().to_string()]),
+        ("normalize-brackets", vec![args.normalize_brackets().to_string()]),
+        ("tab-width", vec![args.tab_width().to_string()]),
+        ("alias", strings(args.aliases())),
+        ("fix-precision", strings(args.fix_precision())),
+        ("sort-args", strings(args.sort_args())),
+        ("sort-toplevel", strings(args.sort_toplevel())),
+        ("rename-bound", vec![args.rename_bound().to_string()]),
+        ("share-common-subterms", vec![args.share_common_subterms().to_string()]),
+        ("min-shared-nodes", if args.share_common_subterms() { vec![args.min_shared_nodes().to_string()] } else { Vec::new() }),
+        ("sort-definitions", vec![args.sort_definitions().to_string()]),
+        ("definition-forms", if args.sort_definitions() { args.definition_forms() } else { Vec::new() }),
+    ])
+}
+
+/// The number of blank lines to keep between the top-level forms read from `previous` and `next` of `input`,
+/// which should be next to each other there; forms which were not read from it have none kept between them
+fn blank_lines_between(args: &CmdArgs, input: &str, previous: Option<Span>, next: Option<Span>) -> usize {
+    match (previous, next) {
+        (Some(previous), Some(next)) => input.get(previous.end..next.start)
+            .map_or(0, |gap| gap.matches('\n').count().saturating_sub(1).min(args.keep_blank_lines())),
+        _ => 0,
+    }
+}
+
+/// Format each of `forms`, which were read from `input`, each on its own line,
+/// keeping some of the blank lines before each of them, even if it was moved by sorting
+fn format_forms(args: &CmdArgs, input: &str, mut forms: Vec<Sexpr<'_>>) -> String {
+    let mut out = String::new();
+    // the blank lines before each form where it was in the input, by where it starts, which sorting moves along with it,
+    // rather than counting the gap between forms which were not next to each other
+    let mut previous = None;
+    let leading: BTreeMap<usize, usize> = forms.iter().filter_map(|sexpr| {
+        let span = sexpr.span()?;
+        Some((span.start, blank_lines_between(args, input, previous.replace(span), Some(span))))
+    }).collect();
+    canonical::sort_toplevel(&mut forms, args.sort_toplevel(), args.aliases());
+    if args.sort_definitions() {
+        canonical::sort_definitions(&mut forms, &args.definition_forms(), args.aliases());
+    }
+    // where the form written last starts, for moving a comment which trails it above it
+    let mut last_start = 0;
+    for (i, mut sexpr) in forms.into_iter().enumerate() {
+        let blank_lines = match sexpr.span() {
+            Some(span) if i > 0 => leading.get(&span.start).copied().unwrap_or(0),
+            _ => 0,
+        };
+        for _ in 0..blank_lines {
+            out.push('\n');
+        }
+        transform(args, &mut sexpr);
+        let formatted = format_sexpr(args, &sexpr);
+        if sexpr.is_trailing_comment() && !out.is_empty() {
+            if args.comment_style() == CommentStyle::OwnLine {
+                out.insert_str(last_start, &format!("{}\n", formatted));
+                continue;
+            }
+            out.pop();
+            out.push(' ');
+        } else {
+            last_start = out.len();
+        }
+        out.push_str(&formatted);
+        out.push('\n');
+    }
+    out
+}
+
+/// Check that no line of the output `text` for `name` is longer than the maximum,
+/// refusing to give it out unless forced to, in which case it is only warned about
+fn check_line_lengths(args: &CmdArgs, name: &str, text: &str) -> Result<(), Diagnostic> {
+    let longest = text.split('\n').enumerate().max_by_key(|(_, line)| line.len());
+    match longest {
+        Some((i, line)) if line.len() > args.max_line_bytes() => {
+            let problem = format!("line {} of the output is {} bytes long, more than the maximum of {}",
+                i + 1, line.len(), args.max_line_bytes());
+            if !args.force() {
+                let message = format!("{} (use --force to write it anyway, or raise --max-line-bytes)", problem);
+                return Err(Diagnostic { kind: Kind::Other, file: Some(name.to_string()), position: None, message, snippet: None });
+            }
+            eprintln!("{}: warning: {}", name, problem);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Pipe `text`, the output for `name`, through the --postprocess command if there is one, giving what the command writes
+fn postprocess(args: &CmdArgs, name: &str, text: String) -> Result<String, Diagnostic> {
+    let command = match args.postprocess() {
+        Some(command) => command,
+        None => return Ok(text),
+    };
+    let failed = |problem: String| Diagnostic {
+        kind: Kind::Other,
+        file: Some(name.to_string()),
+        position: None,
+        message: format!("the postprocess command `{}` {}", command, problem),
+        snippet: None,
+    };
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut child = process::Command::new(shell)
+        .args([flag, command])
+        .env("SEXPR_FMT_FILE", name)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .map_err(|error| failed(format!("could not be started: {}", error)))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // writing everything before reading anything would block on a command which writes as it reads
+    let writer = thread::spawn(move || match stdin.write_all(text.as_bytes()) {
+        // the command does not have to read all of its input
+        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => written,
+    });
+    let output = child.wait_with_output().map_err(|error| failed(format!("could not be run: {}", error)))?;
+    writer.join().expect("writing to the command does not panic")
+        .map_err(|error| failed(format!("could not be given the output: {}", error)))?;
+    if !output.status.success() {
+        return Err(failed(format!("failed ({})", output.status)));
+    }
+    String::from_utf8(output.stdout).map_err(|_| failed("wrote something which is not utf-8".to_string()))
+}
+
+/// Answer a request to the http server: a POST to `/preview` formats its `content` with each set of its `options`,
+/// giving the formatted text (or the error) for each, in order
+fn answer_http(args: &CmdArgs, request: &http::Request) -> http::Response {
+    if request.path != "/preview" {
+        return http::Response::error(404, format!("there is nothing at `{}`, expected /preview", request.path));
+    }
+    if request.method != "POST" {
+        return http::Response::error(405, format!("expected a POST to /preview, found a {}", request.method));
+    }
+    let body: serde_json::Value = match serde_json::from_str(&request.body) {
+        Ok(body) => body,
+        Err(error) => return http::Response::error(400, format!("the body is not json: {}", error)),
+    };
+    let content = match body.get("content").and_then(serde_json::Value::as_str) {
+        Some(content) => content,
+        None => return http::Response::error(400, "expected the text to format as the string `content`"),
+    };
+    // without any options, the content is formatted with the settings the server was started with
+    let options = match body.get("options") {
+        None => vec![serde_json::json!({})],
+        Some(serde_json::Value::Array(options)) => options.clone(),
+        Some(_) => return http::Response::error(400, "expected `options` to be a list of sets of settings"),
+    };
+    let results: Vec<serde_json::Value> = options.iter().map(|options| {
+        let formatted = preview_settings(options)
+            .and_then(|settings| format_document(&args.with_settings(&settings), content, "preview").map_err(|error| error.to_string()));
+        match formatted {
+            Ok(formatted) => serde_json::json!({ "options": options, "formatted": formatted }),
+            Err(error) => serde_json::json!({ "options": options, "error": error }),
+        }
+    }).collect();
+    http::Response { status: 200, body: serde_json::json!({ "results": results }) }
+}
+
+/// The settings of a set of options to preview, like `{"complexity-threshold": 2, "short-quantifiers": true}`
+fn preview_settings(options: &serde_json::Value) -> Result<Vec<Setting>, String> {
+    let options = options.as_object()
+        .ok_or_else(|| format!("expected a set of settings like {{\"complexity-threshold\": 2}}, found `{}`", options))?;
+    options.iter().map(|(name, value)| {
+        let value = match value {
+            serde_json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        format!("{}={}", name, value).parse()
+    }).collect()
+}
+
+/// Re-indent `input` like Emacs, between the point and mark on its first line if it starts with them
+fn emacs_indent(args: &CmdArgs, input: &str) -> String {
+    match emacs::split_positions(input) {
+        Some((point, mark, text)) => {
+            let (text, point, mark) = emacs::indent_between(text, point, mark, args.tab_width());
+            format!("{} {}\n{}", point, mark, text)
+        }
+        None => emacs::indent_lines(input, 0..usize::MAX, args.tab_width()),
+    }
+}
+
+/// Format a single form, or minify it if asked to
+fn format_sexpr(args: &CmdArgs, sexpr: &Sexpr<'_>) -> String {
+    let formatted = if args.minify() {
+        sexpr.to_minified_string()
+    } else if args.annotate_sizes() {
+        annotate::annotate_sizes(sexpr, format_args(args), args.annotate_sizes_over())
+            .expect("writing to a string does not fail")
     } else {
-        io::stdin().read_line(&mut input)?;
+        sexpr.format(format_args(args))
+    };
+    match args.normalize_brackets() {
+        BracketStyle::Racket => delimiters::racket_brackets(&formatted, args.aliases()),
+        _ => formatted,
+    }
+}
+
+/// `input` with its square brackets written as parens, unless they are to be kept as they are
+fn round_brackets<'t>(args: &CmdArgs, input: &'t str) -> Cow<'t, str> {
+    match args.normalize_brackets() {
+        BracketStyle::Preserve => Cow::Borrowed(input),
+        BracketStyle::Round | BracketStyle::Racket => delimiters::round_brackets(input),
+    }
+}
+
+/// Format every top-level form of `input`, each on its own line
+fn format_source(args: &CmdArgs, input: &str) -> Result<String, SyntaxError> {
+    Ok(format_forms(args, input, Sexpr::parse_many_located(input)?))
+}
+
+/// Format a whole file, warning when it records a different style than ours,
+/// and recording our style at its end if asked to
+fn format_document(args: &CmdArgs, input: &str, name: &str) -> Result<String, Diagnostic> {
+    let joined;
+    let input = if args.hard_wrap() {
+        joined = sexpr::join_continuations(input, args.continuation_marker());
+        &*joined
+    } else {
+        input
+    };
+    let repaired;
+    let input = if args.fix_delimiters() {
+        repaired = fix_delimiters(name, input);
+        &*repaired
+    } else {
+        input
+    };
+    let rounded = round_brackets(args, input);
+    let checked = apply_parse_mode(args, name, &rounded)?;
+    let input = &*checked;
+    // the line directives are not formatted like other comments, so they are hidden from the parser
+    let (blanked, directives) = ignore::blank_line_directives(input);
+    if let Some(range) = args.line_range() {
+        return format_range(args, name, input, &blanked, &directives, range);
+    }
+    let (forms, trailing) = timed(args, name, "parsing", || if args.opaque_fallback() {
+        let (forms, opaque) = Sexpr::parse_many_opaque(&blanked);
+        for (span, error) in opaque {
+            let kept = Diagnostic::at(Kind::Parse, name, &blanked, span.start,
+                format!("warning: keeping bytes {}..{} as they are, since they could not be read ({})", span.start, span.end, error));
+            eprintln!("{}", kept);
+        }
+        (forms, None)
+    } else {
+        Sexpr::parse_many_partial(&blanked)
+    });
+    let trailing = match trailing {
+        Some((text, error)) => handle_trailing(args, name, &blanked, text, error)?,
+        None => "",
+    };
+    let manifest = style_manifest(args);
+    let ours = manifest.to_compact_string();
+    if forms.iter().filter_map(style::recorded_manifest).any(|recorded| recorded != ours) {
+        eprintln!("{}: warning: the file records a different style than the one it is being formatted with", name);
+    }
+    if args.verbose() {
+        if let Some(advice) = threshold_advice(args, &forms) {
+            eprintln!("{}: note: {}", name, advice);
+        }
+    }
+    let ignored: Vec<_> = (0..forms.len()).map(|i| ignore::ignored_from(input, &directives, &forms, i)).collect();
+    let mut forms: Vec<_> = forms.into_iter().zip(ignored).collect();
+    if args.embed_style() {
+        forms.retain(|(form, _)| style::recorded_manifest(form).is_none());
+    }
+    // the forms between the ignored ones are formatted (and sorted) as usual
+    let mut out = String::new();
+    let mut run = Vec::new();
+    // the blank lines to keep before the run, and where the form before it ends
+    let mut run_gap = 0;
+    let mut previous = None;
+    let push_run = |out: &mut String, run: Vec<Sexpr<'_>>, gap: usize| {
+        if !run.is_empty() {
+            out.push_str(&"\n".repeat(gap));
+            out.push_str(&format_forms(args, &blanked, run));
+        }
+    };
+    timed(args, name, "printing", || {
+        for (form, ignored_from) in forms {
+            let span = form.span().expect("parsed forms have spans");
+            match ignored_from {
+                Some(start) => {
+                    push_run(&mut out, std::mem::take(&mut run), run_gap);
+                    out.push_str(&"\n".repeat(blank_lines_between(args, input, previous, Some(Span { start, end: span.end }))));
+                    out.push_str(&input[start..span.end]);
+                    out.push('\n');
+                }
+                None => {
+                    if run.is_empty() {
+                        run_gap = blank_lines_between(args, input, previous, Some(span));
+                    }
+                    run.push(form);
+                }
+            }
+            previous = Some(span);
+        }
+        push_run(&mut out, run, run_gap);
+    });
+    if args.embed_style() {
+        out.push_str(&style::manifest_comment(&manifest));
+        out.push('\n');
+    }
+    out.push_str(trailing);
+    Ok(out)
+}
+
+/// Advice for when the complexity threshold lays out `forms` just like the default one does,
+/// naming the nearest thresholds on either side which would change the layout
+fn threshold_advice(args: &CmdArgs, forms: &[Sexpr<'_>]) -> Option<String> {
+    let default = FormatArgs::new().complexity_threshold;
+    let threshold = args.complexity_threshold();
+    let with_threshold = |threshold| format_forms(&args.with_settings(&[Setting::ComplexityThreshold(threshold)]), "", forms.to_vec());
+    let ours = with_threshold(threshold);
+    if threshold == default || ours != with_threshold(default) {
+        return None;
+    }
+    // every threshold at least as high as the most complex form puts all of the forms on one line
+    let highest = forms.iter().map(Sexpr::complexity).max().unwrap_or(0);
+    let changes = |threshold: &u32| with_threshold(*threshold) != ours;
+    let below = (0..threshold.min(highest + 1)).rev().find(changes);
+    let above = (threshold + 1..=highest).find(changes);
+    let nearest = match (below, above) {
+        (Some(below), Some(above)) => format!("the nearest thresholds which change it are {} and {}", below, above),
+        (Some(nearest), None) | (None, Some(nearest)) => format!("the nearest threshold which changes it is {}", nearest),
+        (None, None) => "no threshold changes it".to_string(),
+    };
+    Some(format!("--complexity-threshold {} lays out the input the same as the default of {}; {}", threshold, default, nearest))
+}
+
+/// `input` with the lists closed by the wrong delimiter closed by the right one, warning about each change
+fn fix_delimiters(name: &str, input: &str) -> String {
+    let mismatches = delimiters::mismatches(input);
+    for mismatch in &mismatches {
+        let (line, column) = diagnostic::line_column(input, mismatch.open.start);
+        let message = format!("warning: changed `{}` to `{}`, to close the `{}` at {}:{}",
+            mismatch.found, mismatch.expected(), mismatch.opener, line, column);
+        eprintln!("{}", Diagnostic::at(Kind::Parse, name, input, mismatch.close.start, message));
+    }
+    delimiters::repair(input, &mismatches)
+}
+
+/// Check `input`, the text of `name`, the way --strict asks for, or close the lists left open at its end for --lenient
+fn apply_parse_mode<'t>(args: &CmdArgs, name: &str, input: &'t str) -> Result<Cow<'t, str>, Diagnostic> {
+    if args.strict() {
+        if let Some(stray) = delimiters::stray_closers(input).first() {
+            let message = format!("`{}` has no list open for it to close (rejected by --strict)", &input[stray.range()]);
+            return Err(Diagnostic::at(Kind::Parse, name, input, stray.start, message));
+        }
+        if let Some(&tab) = delimiters::quoted_tabs(input).first() {
+            let message = "a tab inside of a string or `|symbol|` (rejected by --strict, write it as `\\t` instead)";
+            return Err(Diagnostic::at(Kind::Parse, name, input, tab, message));
+        }
+    }
+    if !args.lenient() {
+        return Ok(Cow::Borrowed(input));
+    }
+    let unclosed = delimiters::unclosed(input);
+    for &(open, opener) in &unclosed {
+        let message = format!("warning: closing the `{}` opened here at the end of the input", opener);
+        eprintln!("{}", Diagnostic::at(Kind::Parse, name, input, open.start, message));
+    }
+    Ok(Cow::Owned(delimiters::close_unclosed(input, &unclosed)))
+}
+
+/// Decide what to do with the `trailing` text of `name`, which could not be read because of `error`,
+/// giving the text to write after the formatted forms
+fn handle_trailing<'t>(args: &CmdArgs, name: &str, input: &str, trailing: &'t str, error: ParseError) -> Result<&'t str, Diagnostic> {
+    let diagnostic = || {
+        let offset = trailing.as_ptr() as usize - input.as_ptr() as usize;
+        diagnostic::mismatched_delimiters(name, input).unwrap_or_else(|| Diagnostic::at(Kind::Parse, name, input, offset, error))
+    };
+    match args.trailing() {
+        TrailingPolicy::Error => Err(diagnostic()),
+        TrailingPolicy::Report => {
+            diagnostic().report(args.error_format());
+            PARTIALLY_READ.store(true, Ordering::SeqCst);
+            Ok(trailing)
+        }
+        TrailingPolicy::Ignore => {
+            eprintln!("{}: warning: ignoring the trailing text which is not an s-expression ({})", name, error);
+            Ok("")
+        }
+        TrailingPolicy::Keep => Ok(trailing),
+    }
+}
+
+/// Format the top-level forms of `input` which touch the lines of `range`, copying everything else as it is
+/// `blanked` is the input with its ignore directives hidden from the parser, and the forms after them are left as they are
+fn format_range(args: &CmdArgs, name: &str, input: &str, blanked: &str, directives: &[Range<usize>], range: LineRange)
+    -> Result<String, Diagnostic> {
+    let line_of = |offset: usize| input[..offset].matches('\n').count() + 1;
+    let mut out = String::new();
+    let mut copied = 0;
+    let forms = diagnostic::parse_forms(name, blanked)?;
+    for (i, sexpr) in forms.iter().enumerate() {
+        let span = sexpr.span().expect("parsed forms have spans");
+        let ignored = ignore::ignored_from(input, directives, &forms, i).is_some();
+        if ignored || !range.intersects(line_of(span.start), line_of(span.end)) {
+            continue;
+        }
+        let mut sexpr = sexpr.clone();
+        out.push_str(&input[copied..span.start]);
+        transform(args, &mut sexpr);
+        out.push_str(&format_sexpr(args, &sexpr));
+        copied = span.end;
+    }
+    out.push_str(&input[copied..]);
+    Ok(out)
+}
+
+/// Format the fenced blocks of s-expressions inside of the host text `input`,
+/// reporting any blocks which could not be formatted
+fn format_host(args: &CmdArgs, input: &str, name: &str) -> Result<String, Diagnostic> {
+    let (formatted, errors) = match args.fence() {
+        Some(fence) => embedded::format_matches(input, fence, |block| format_source(args, block)),
+        None => embedded::format_embedded(input, &args.fence_starts(), args.fence_end(), |block| format_source(args, block)),
+    };
+    let mut errors = errors.iter().map(|error| Diagnostic::syntax(name, &error.error));
+    // every block which could not be read is reported, the last one by failing with it
+    match errors.next_back() {
+        Some(last) => {
+            errors.for_each(|error| error.report(args.error_format()));
+            Err(last)
+        }
+        None => Ok(formatted),
     }
-    Ok(input)
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let cmd_args = CmdArgs::from_args();
-    let input = read_input(&cmd_args)?;
-    let sexpr = Sexpr::parse(&input)?;
+/// Rewrite every top-level form of the input with the given rules, then print them
+fn run_rewrite(args: &CmdArgs, rules: &str, is_expr: bool, file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let rules_text = if is_expr { rules.to_string() } else { fs::read_to_string(rules)? };
+    let rules = Rule::parse_all(&rules_text)?;
+    let input = read_source(args, file)?;
+    let forms = diagnostic::parse_forms(&source_name(args, file), &input)?
+        .into_iter()
+        .map(|sexpr| sexpr.rewrite(&rules))
+        .collect::<Result<Vec<_>, _>>()?;
+    write_stdout(args, &format_forms(args, &input, forms))?;
+    Ok(())
+}
+
+/// Print the structural differences between two files, returning whether there were any
+fn run_diff(old: &Path, new: &Path) -> Result<bool, Box<dyn Error>> {
+    let old_text = compress::read_to_string(old)?;
+    let new_text = compress::read_to_string(new)?;
+    let old_forms = diagnostic::parse_forms(&old.display().to_string(), &old_text)?;
+    let new_forms = diagnostic::parse_forms(&new.display().to_string(), &new_text)?;
+    let changes = treediff::diff_forms(&old_forms, &new_forms);
+    for change in changes.iter() {
+        writeln!(io::stdout(), "{}", change)?;
+    }
+    Ok(!changes.is_empty())
+}
+
+/// Report the lints found in each file (or the input), returning whether there were any
+fn run_lint(args: &CmdArgs, files: &[PathBuf]) -> Result<bool, Box<dyn Error>> {
+    let mut found = false;
+    let mut check = |name: &str, text: &str| -> io::Result<()> {
+        for lint in lint::mixed_indentation(text) {
+            writeln!(io::stdout(), "{}:{}: {}", name, lint.line, lint.message)?;
+            found = true;
+        }
+        Ok(())
+    };
+    if files.is_empty() {
+        check(args.stdin_name(), &read_input(args)?)?;
+    }
+    for file in files {
+        check(&file.display().to_string(), &compress::read_to_string(file)?)?;
+    }
+    Ok(found)
+}
+
+/// Print the statistics of each file (or the input)
+fn run_stats(args: &CmdArgs, files: &[PathBuf], top: usize) -> Result<(), Box<dyn Error>> {
+    let report = |name: &str, text: &str| -> Result<(), Box<dyn Error>> {
+        let forms = diagnostic::parse_forms(name, text)?;
+        let all: Vec<stats::Stats> = forms.iter().map(Sexpr::stats).collect();
+        let nodes: usize = all.iter().map(stats::Stats::nodes).sum();
+        let max_depth = all.iter().map(|stats| stats.max_depth).max().unwrap_or(0);
+        writeln!(io::stdout(), "{}: {} forms, {} nodes, max depth {}", name, forms.len(), nodes, max_depth)?;
+        writeln!(io::stdout(), "{:>6} {:>6} {:>8} {:>6}  head", "form", "line", "nodes", "depth")?;
+        for (i, (form, stats)) in forms.iter().zip(all.iter()).enumerate() {
+            let start = form.span().expect("parsed forms have spans").start;
+            let line = text[..start].matches('\n').count() + 1;
+            let head = form.head_name().unwrap_or("");
+            let row = format!("{:>6} {:>6} {:>8} {:>6}  {}", i + 1, line, stats.nodes(), stats.max_depth, head);
+            writeln!(io::stdout(), "{}", row.trim_end())?;
+        }
+        writeln!(io::stdout(), "most common atoms:")?;
+        for (atom, count) in stats::atom_frequencies(&forms).into_iter().take(top) {
+            writeln!(io::stdout(), "{:>8}  {}", count, atom)?;
+        }
+        writeln!(io::stdout(), "forms by number of nodes:")?;
+        let sizes: Vec<usize> = all.iter().map(stats::Stats::nodes).collect();
+        let histogram = stats::size_histogram(&sizes);
+        let most = histogram.iter().map(|&(_, count)| count).max().unwrap_or(0);
+        for (low, count) in histogram {
+            let range = if low == 1 { "1".to_string() } else { format!("{}..{}", low, 2 * low - 1) };
+            // scale the bars so that the longest is 40 columns
+            let bar = "#".repeat((count * 40).div_ceil(most.max(1)));
+            let row = format!("{:>16} {:>6}  {}", range, count, bar);
+            writeln!(io::stdout(), "{}", row.trim_end())?;
+        }
+        Ok(())
+    };
+    if files.is_empty() {
+        report(args.stdin_name(), &read_input(args)?)?;
+    }
+    for (i, file) in files.iter().enumerate() {
+        if i > 0 {
+            writeln!(io::stdout())?;
+        }
+        report(&file.display().to_string(), &compress::read_to_string(file)?)?;
+    }
+    Ok(())
+}
+
+/// Print the structural hash of each form of each file (or the input), after transforming it,
+/// like `sha256sum` does for whole files
+fn run_hash(args: &CmdArgs, files: &[PathBuf], whole_file: bool) -> Result<(), Box<dyn Error>> {
+    let report = |name: &str, text: &str| -> Result<(), Box<dyn Error>> {
+        // the transforms hash forms like `--rename-bound` makes equal the same
+        let mut forms = diagnostic::parse_forms(name, text)?;
+        for form in forms.iter_mut() {
+            transform(args, form);
+        }
+        if whole_file {
+            writeln!(io::stdout(), "{}  {}", hash::hash_forms(&forms), name)?;
+            return Ok(());
+        }
+        for (i, form) in forms.iter().enumerate().filter(|(_, form)| !form.is_comment()) {
+            writeln!(io::stdout(), "{}  {}:{}", form.structural_hash(), name, i + 1)?;
+        }
+        Ok(())
+    };
+    if files.is_empty() {
+        report(args.stdin_name(), &read_input(args)?)?;
+    }
+    for file in files {
+        report(&file.display().to_string(), &compress::read_to_string(file)?)?;
+    }
+    Ok(())
+}
+
+/// Download the benchmark corpus into `dir`, or the default cache directory, returning whether all of it was
+#[cfg(feature = "dev")]
+fn fetch_corpus(dir: Option<&Path>, refresh: bool) -> Result<bool, Box<dyn Error>> {
+    let dir = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => corpus::default_dir().ok_or("no cache directory: set $XDG_CACHE_HOME or $HOME, or pass --dir")?,
+    };
+    corpus::fetch(&dir, refresh)
+}
+#[cfg(not(feature = "dev"))]
+fn fetch_corpus(_dir: Option<&Path>, _refresh: bool) -> Result<bool, Box<dyn Error>> {
+    Err("fetching the corpus needs sexpr-fmt to be built with `--features dev`".into())
+}
+
+/// The complexity thresholds and maximum widths tried by `tune`, which also tries the target width
+const TUNE_THRESHOLDS: &[u32] = &[0, 1, 2, 3, 4];
+const TUNE_WIDTHS: &[Option<usize>] = &[None, Some(80), Some(100), Some(120)];
+
+/// Format `files` with each setting of the grid, print how the lines come out, and recommend one of them
+fn run_tune(args: &CmdArgs, files: &[PathBuf], target_width: usize) -> Result<(), Box<dyn Error>> {
+    let inputs = files.iter()
+        .map(|file| Ok((file.display().to_string(), compress::read_to_string(file)?)))
+        .collect::<Result<Vec<_>, io::Error>>()?;
+    writeln!(io::stdout(), "{:>9} {:>9} {:>7} {:>7} {:>6}", "threshold", "max-width", "lines", "average", "widest")?;
+    let mut widths = TUNE_WIDTHS.to_vec();
+    if !widths.contains(&Some(target_width)) {
+        widths.push(Some(target_width));
+    }
+    let mut best: Option<(u32, Option<usize>, stats::LineStats)> = None;
+    for &threshold in TUNE_THRESHOLDS {
+        for &max_width in &widths {
+            let mut tried = args.clone();
+            tried.complexity_threshold = threshold;
+            tried.max_width = max_width;
+            let mut lines = stats::LineStats::default();
+            for (name, input) in &inputs {
+                lines.add(stats::LineStats::of(&format_document(&tried, input, name)?));
+            }
+            let width = max_width.map_or_else(|| "none".to_string(), |width| width.to_string());
+            writeln!(io::stdout(), "{:>9} {:>9} {:>7} {:>7.1} {:>6}", threshold, width, lines.lines, lines.average(), lines.widest)?;
+            // the fewest lines within the target width, or else the narrowest, keeping the first (and simplest) of equals
+            let better = best.as_ref().is_none_or(|&(_, _, best)| {
+                match (lines.widest <= target_width, best.widest <= target_width) {
+                    (true, true) => lines.lines < best.lines,
+                    (fits, fitted) if fits != fitted => fits,
+                    _ => lines.widest < best.widest,
+                }
+            });
+            if better {
+                best = Some((threshold, max_width, lines));
+            }
+        }
+    }
+    if let Some((threshold, max_width, lines)) = best {
+        let width = max_width.map_or_else(String::new, |width| format!(" --max-width {}", width));
+        let fit = if lines.widest <= target_width { "fits" } else { "does not fit, but comes closest to fitting" };
+        writeln!(io::stdout(), "recommended: --complexity-threshold {}{} ({} lines, which {} in {} columns)",
+            threshold, width, lines.lines, fit, target_width)?;
+    }
+    Ok(())
+}
+
+/// Print each s-expression found in the log `file`, after a comment saying where it was found
+fn run_extract(args: &CmdArgs, file: &Path, min_nodes: usize) -> Result<(), Box<dyn Error>> {
+    // logs often have a few bytes that are not utf-8, which should not stop us from reading the rest
+    let bytes = fs::read(file)?;
+    let text = String::from_utf8_lossy(&Compression::detect(Some(file), &bytes).decompress(&bytes)?).into_owned();
+    for found in extract::extract(&text, min_nodes) {
+        write_stdout(args, &format!("#| {}:{} |#\n", file.display(), found.line))?;
+        write_stdout(args, &format_forms(args, &text, vec![found.sexpr]))?;
+    }
+    Ok(())
+}
+
+/// Format each of the files given on the command line,
+/// either printing them, writing them back, or asking about each changed hunk
+fn format_files(args: &CmdArgs) -> Result<(), Box<dyn Error>> {
+    if args.jobs() > 1 && !args.interactive() {
+        return format_files_in_parallel(args);
+    }
+    for file in args.files() {
+        if format_file(args, file)? == Review::Quit { break; }
+    }
+    Ok(())
+}
+
+/// Format the files given on the command line on several threads
+/// the output of each file is printed whole, in the order of the files, unless `--unordered` is given
+fn format_files_in_parallel(args: &CmdArgs) -> Result<(), Box<dyn Error>> {
+    let files = args.files();
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..args.jobs().min(files.len()) {
+            let sender = sender.clone();
+            let (next, failed) = (&next, &failed);
+            scope.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                if idx >= files.len() || failed.load(Ordering::SeqCst) { break; }
+                let result = format_file_output(args, &files[idx]);
+                failed.fetch_or(result.is_err(), Ordering::SeqCst);
+                if sender.send((idx, result)).is_err() { break; }
+            });
+        }
+        drop(sender);
+        // outputs which are done, but waiting for the ones before them
+        let mut pending = BTreeMap::new();
+        let mut printed = 0;
+        for (idx, result) in receiver {
+            if args.unordered() {
+                write_stdout(args, &result?)?;
+                continue;
+            }
+            pending.insert(idx, result);
+            while let Some(result) = pending.remove(&printed) {
+                write_stdout(args, &result?)?;
+                printed += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Format `file`, either printing it, writing it back, or asking about each changed hunk
+fn format_file(args: &CmdArgs, file: &Path) -> Result<Review, Box<dyn Error>> {
+    if args.interactive() {
+        let args = &*args.for_file(file);
+        let (input, compression) = compress::read_file(file)?;
+        let formatted = format_file_text(args, file, &input)?;
+        check_line_lengths(args, &file.display().to_string(), &formatted)?;
+        let formatted = postprocess(args, &file.display().to_string(), formatted)?;
+        let (result, review) = interactive::review(file, &input, &formatted)?;
+        if let Some(text) = result {
+            compress::write_file(file, &text, args.compress().unwrap_or(compression))?;
+        }
+        return Ok(review);
+    }
+    write_stdout(args, &format_file_output(args, file)?)?;
+    Ok(Review::Continue)
+}
+
+/// Write `text` to stdout, compressed if asked to with --compress, returning the error instead of panicking like `print!` does,
+/// so that the output being cut off (such as by `| head`) can be told apart from other failures
+fn write_stdout(args: &CmdArgs, text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&args.compress().unwrap_or(Compression::None).compress(text.as_bytes())?)?;
+    stdout.flush()
+}
+
+/// Whether `error` is from writing to a pipe whose reader has gone away, which is not worth reporting
+fn is_broken_pipe(error: &(dyn Error + 'static)) -> bool {
+    error.downcast_ref::<io::Error>().is_some_and(|error| error.kind() == io::ErrorKind::BrokenPipe)
+}
+
+/// Do the `phase` of the work on `name`, reporting how long it took if asked to with --time
+fn timed<T>(args: &CmdArgs, name: &str, phase: &str, work: impl FnOnce() -> T) -> T {
+    if !args.time() {
+        return work();
+    }
+    let start = Instant::now();
+    let result = work();
+    eprintln!("{}: {} took {:.2?}", name, phase, start.elapsed());
+    result
+}
+
+/// The contents of a file to format, either read into memory (and decompressed) or mapped into it
+enum Contents {
+    Read(String, Compression),
+    Mapped(memmap2::Mmap),
+}
+impl Contents {
+    /// Read `file`, or map it with --mmap
+    /// a compressed file is read even with --mmap, since it has to be decompressed anyway
+    fn of(args: &CmdArgs, file: &Path) -> io::Result<Contents> {
+        if !args.mmap() || Compression::of_path(file) != Compression::None {
+            return compress::read_file(file).map(|(text, compression)| Contents::Read(text, compression));
+        }
+        let opened = fs::File::open(file)?;
+        // SAFETY: the file is only read through the map, and --mmap asks that nothing else changes it meanwhile
+        let map = unsafe { memmap2::Mmap::map(&opened)? };
+        match Compression::of_bytes(&map) {
+            Compression::None => Ok(Contents::Mapped(map)),
+            _ => compress::decode(Some(file), &map).map(|(text, compression)| Contents::Read(text, compression)),
+        }
+    }
+    fn text(&self) -> io::Result<&str> {
+        match self {
+            Contents::Read(text, _) => Ok(text),
+            Contents::Mapped(map) => std::str::from_utf8(map)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8")),
+        }
+    }
+    fn compression(&self) -> Compression {
+        match self {
+            Contents::Read(_, compression) => *compression,
+            Contents::Mapped(_) => Compression::None,
+        }
+    }
+}
+
+/// Format `file`, writing it back if asked to, and giving what should be printed for it
+fn format_file_output(args: &CmdArgs, file: &Path) -> Result<String, Diagnostic> {
+    let args = &*args.for_file(file);
+    let name = file.display().to_string();
+    let contents = timed(args, &name, "reading", || Contents::of(args, file)).map_err(|e| Diagnostic::from(e).in_file(&name))?;
+    let input = contents.text().map_err(|e| Diagnostic::from(e).in_file(&name))?;
+    let formatted = format_file_text(args, file, input)?;
+    check_line_lengths(args, &name, &formatted)?;
+    let formatted = timed(args, &name, "postprocessing", || postprocess(args, &name, formatted))?;
+    let compression = args.compress().unwrap_or(contents.compression());
+    let changed = formatted != input || compression != contents.compression();
+    // a mapped file is let go of before it is written to
+    drop(contents);
+    if args.write() {
+        if changed {
+            timed(args, &name, "writing", || compress::write_file(file, &formatted, compression)).map_err(|e| Diagnostic::from(e).in_file(&name))?;
+        }
+        return Ok(String::new());
+    }
+    Ok(formatted)
+}
+
+/// The formatted text of `input`, the contents of `file`
+fn format_file_text(args: &CmdArgs, file: &Path, input: &str) -> Result<String, Diagnostic> {
+    if args.embedded() {
+        format_host(args, input, &file.display().to_string())
+    } else {
+        format_document(args, input, &file.display().to_string())
+    }
+}
+
+/// Format all of stdin to stdout, for editors using us as a filter
+/// like rustfmt, exits with 0 when the input was formatted, and with 1 when it could not be,
+/// in which case nothing is written to stdout, so the editor can keep its buffer as it is
+fn filter_stdin(args: &CmdArgs) -> Result<(), Box<dyn Error>> {
+    let args = &*args.for_file(Path::new(args.stdin_name()));
+    let input = read_stdin()?;
+    let formatted = format_document(args, &input, args.stdin_name())
+        .and_then(|formatted| check_line_lengths(args, args.stdin_name(), &formatted).map(|()| formatted))
+        .and_then(|formatted| postprocess(args, args.stdin_name(), formatted));
+    match formatted {
+        Ok(formatted) => write_stdout(args, &formatted)?,
+        Err(diagnostic) => {
+            diagnostic.report(args.error_format());
+            std::process::exit(diagnostic.kind.exit_code());
+        }
+    }
+    Ok(())
+}
+
+/// Report each of the files given on the command line (or the input) which is not formatted,
+/// returning whether there were any
+fn check_files(args: &CmdArgs) -> Result<bool, Box<dyn Error>> {
+    let mut found = false;
+    let mut check = |args: &CmdArgs, file: &Path, input: &str| -> Result<(), Box<dyn Error>> {
+        let formatted = format_file_text(args, file, input)?;
+        if formatted == input {
+            return Ok(());
+        }
+        found = true;
+        match args.error_format() {
+            ErrorFormat::Human => writeln!(io::stdout(), "{}: not formatted", file.display())?,
+            ErrorFormat::Json => {
+                // point at the start of the first line which would change
+                let same = input.split_inclusive('\n').zip(formatted.split_inclusive('\n'))
+                    .take_while(|(old, new)| old == new)
+                    .map(|(old, _)| old.len())
+                    .sum();
+                Diagnostic::at(Kind::NeedsReformat, &file.display().to_string(), input, same, "not formatted")
+                    .report(args.error_format());
+            }
+        }
+        Ok(())
+    };
+    if args.files().is_empty() {
+        let name = Path::new(args.stdin_name());
+        let input = read_stdin()?;
+        check(&args.for_file(name), name, &input)?;
+    }
+    for file in args.files() {
+        check(&args.for_file(file), file, &compress::read_to_string(file)?)?;
+    }
+    Ok(found)
+}
+
+/// Check each top-level form of stdin as soon as all of it has been read, without waiting for the end of the input,
+/// reporting the forms which are not formatted (or can not be read) along with a count of the forms so far
+/// returns whether there were any
+fn check_stream(args: &CmdArgs) -> Result<bool, Box<dyn Error>> {
+    use std::io::{BufRead, Write};
+    let args = &*args.for_file(Path::new(args.stdin_name()));
+    let name = args.stdin_name();
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let (mut forms, mut violations) = (0, 0);
+    let mut buffer = String::new();
+    let mut line = 1; // the line of the input which the buffer starts on
+    loop {
+        let read = stdin.read_line(&mut buffer)?;
+        loop {
+            if buffer.trim().is_empty() {
+                line += buffer.matches('\n').count();
+                buffer.clear();
+                break;
+            }
+            let (consumed, violation) = match Sexpr::parse_first(&buffer) {
+                Ok((sexpr, _)) if !sexpr.is_blank() => {
+                    let span = sexpr.span().expect("parsed forms have spans");
+                    let start = line + buffer[..span.start].matches('\n').count();
+                    let mut formatted = sexpr.clone();
+                    transform(args, &mut formatted);
+                    forms += 1;
+                    let unformatted = format_sexpr(args, &formatted) != buffer[span.range()];
+                    (span.end, if unformatted { Some((start, "not formatted")) } else { None })
+                }
+                // the rest of the form has not arrived yet
+                Err(e) if read != 0 && e.ends_with("EOI") => break,
+                // skip the line which could not be read, and go on from the next one
+                result => {
+                    let e = result.err().unwrap_or("malformed sexpr: unexpected `)`");
+                    let leading = buffer.len() - buffer.trim_start().len();
+                    let start = line + buffer[..leading].matches('\n').count();
+                    let end = buffer[leading..].find('\n').map_or(buffer.len(), |end| leading + end + 1);
+                    (end, Some((start, e)))
+                }
+            };
+            if let Some((start, message)) = violation {
+                violations += 1;
+                writeln!(stdout, "{}:{}: {} ({} problems in {} forms so far)", name, start, message, violations, forms)?;
+                stdout.flush()?;
+            }
+            line += buffer[..consumed].matches('\n').count();
+            buffer.drain(..consumed);
+        }
+        if read == 0 {
+            break;
+        }
+    }
+    writeln!(stdout, "{}: {} problems in {} forms", name, violations, forms)?;
+    Ok(violations > 0)
+}
+
+/// Print the tokens of each file (or the input) as it is, for editors to highlight
+/// the forms up to the first one that can not be read are tokenized, and the reason it could not be is given
+fn emit_tokens(args: &CmdArgs) -> Result<(), Box<dyn Error>> {
+    let tokens = |name: &str, text: &str| {
+        let (forms, rest) = Sexpr::parse_many_partial(text);
+        let tokens: Vec<serde_json::Value> = tokens::tokens(&forms).iter().map(tokens::Token::to_json).collect();
+        serde_json::json!({ "file": name, "tokens": tokens, "error": rest.map(|(_, e)| e) })
+    };
+    if args.files().is_empty() {
+        let input = read_stdin()?;
+        writeln!(io::stdout(), "{}", serde_json::to_string_pretty(&tokens(args.stdin_name(), &input))?)?;
+    }
+    for file in args.files() {
+        writeln!(io::stdout(), "{}", serde_json::to_string_pretty(&tokens(&file.display().to_string(), &compress::read_to_string(file)?))?)?;
+    }
+    Ok(())
+}
+
+/// Format the files given on the command line every time they change, until interrupted
+/// mistakes in the files are reported, rather than stopping the watch
+fn watch_files(args: &CmdArgs) -> Result<(), Box<dyn Error>> {
+    watch::watch(args.files(), args.watch_interval(), |file| {
+        let formatted = (|| -> Result<Review, Box<dyn Error>> {
+            if !args.write() {
+                writeln!(io::stdout(), ";; {}", file.display())?;
+            }
+            format_file(args, file)
+        })();
+        match formatted {
+            // nothing is reading the output any more, so there is no point in watching
+            Err(e) if is_broken_pipe(&*e) => std::process::exit(0),
+            Err(e) => Diagnostic::from_error(&*e).in_file(&file.display().to_string()).report(args.error_format()),
+            Ok(_) => {}
+        }
+    })?;
+    Ok(())
+}
+
+/// Whether some input was only formatted up to where it could no longer be read, with `--trailing report`,
+/// so that we exit with the parse error code after writing everything out
+static PARTIALLY_READ: AtomicBool = AtomicBool::new(false);
+
+/// The config file defining profiles, which is looked for in the current directory and the ones above it
+const CONFIG_FILE: &str = ".sexpr-fmt";
+
+/// The settings of the profile called `name`, from the config file or else the built-in ones
+fn find_profile(name: &str) -> Result<style::Profile, String> {
+    let mut profiles = style::profiles(style::BUILTIN_PROFILES).expect("the built-in profiles can be read");
+    let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+    if let Some(config) = cwd.ancestors().map(|dir| dir.join(CONFIG_FILE)).find(|config| config.is_file()) {
+        let text = fs::read_to_string(&config).map_err(|e| format!("{}: {}", config.display(), e))?;
+        profiles.extend(style::profiles(&text).map_err(|e| format!("{}: {}", config.display(), e))?);
+    }
+    let names = profiles.iter().map(|profile| profile.name.clone()).collect::<Vec<_>>();
+    // the profiles of the config file come last, so they win over built-in ones of the same name
+    profiles.into_iter().rev().find(|profile| profile.name == name)
+        .ok_or_else(|| format!("unknown profile `{}`, expected one of: {}", name, names.join(", ")))
+}
+
+/// Parse the command line, with the settings of the --profile which were not given as flags put in front of it
+fn parse_args() -> Result<CmdArgs, structopt::clap::Error> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let matches = CmdArgs::clap().get_matches_from_safe(&args)?;
+    let cmd_args = CmdArgs::from_clap(&matches);
+    let profile = match cmd_args.profile() {
+        Some(name) => find_profile(name)
+            .map_err(|e| structopt::clap::Error::with_description(&e, ErrorKind::InvalidValue))?,
+        None => return Ok(cmd_args),
+    };
+    let mut expanded = args[..1].to_vec();
+    for (name, values) in profile.settings.iter().cloned() {
+        // style manifests record the version of their format, and the layout version which is set by --compat
+        let name = match name.as_str() {
+            "version" => continue,
+            "layout-version" => "compat".to_string(),
+            _ => name,
+        };
+        if matches.occurrences_of(&name) > 0 {
+            continue;
+        }
+        match values.as_slice() {
+            [value] if value == "true" => expanded.push(format!("--{}", name).into()),
+            [value] if value == "false" => {}
+            _ => for value in values {
+                expanded.push(format!("--{}", name).into());
+                expanded.push(value.into());
+            },
+        }
+    }
+    expanded.extend_from_slice(&args[1..]);
+    // the flags given were read once already, so a flag which is not known comes from the profile
+    CmdArgs::from_iter_safe(expanded).map_err(|e| match (e.kind, e.info.as_deref()) {
+        (ErrorKind::UnknownArgument, Some([flag, ..])) => structopt::clap::Error::with_description(
+            &format!("the profile `{}` has the setting `{}`, which is not a flag", profile.name, flag.trim_start_matches('-')),
+            ErrorKind::UnknownArgument,
+        ),
+        _ => e,
+    })
+}
+
+fn main() {
+    let cmd_args = match parse_args() {
+        Ok(cmd_args) => cmd_args,
+        // asking for --help or --version is not an error
+        Err(e) if !e.use_stderr() => {
+            println!("{}", e.message);
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{}", e.message);
+            std::process::exit(exit::OTHER_ERROR);
+        }
+    };
+    if let Err(e) = run(&cmd_args) {
+        // the reader of our output has all it wanted, like `head` does
+        if is_broken_pipe(&*e) {
+            std::process::exit(0);
+        }
+        let diagnostic = Diagnostic::from_error(&*e);
+        diagnostic.report(cmd_args.error_format());
+        std::process::exit(diagnostic.kind.exit_code());
+    }
+    if PARTIALLY_READ.load(Ordering::SeqCst) {
+        std::process::exit(exit::PARSE_ERROR);
+    }
+}
+
+fn run(cmd_args: &CmdArgs) -> Result<(), Box<dyn Error>> {
+    match cmd_args.command() {
+        Some(Command::Query { query, file }) => return run_query(cmd_args, query, file.as_deref()),
+        Some(Command::Rewrite { expr, rules, file }) => return run_rewrite(cmd_args, rules, *expr, file.as_deref()),
+        Some(Command::Diff { old, new }) => {
+            // like diff(1), exit with 1 when the files differ
+            if run_diff(old, new)? {
+                std::process::exit(exit::FOUND);
+            }
+            return Ok(());
+        }
+        Some(Command::Lint { files }) => {
+            if run_lint(cmd_args, files)? {
+                std::process::exit(exit::FOUND);
+            }
+            return Ok(());
+        }
+        Some(Command::Stats { top, files }) => return run_stats(cmd_args, files, *top),
+        Some(Command::Hash { whole_file, files }) => return run_hash(cmd_args, files, *whole_file),
+        Some(Command::Tune { target_width, files }) => return run_tune(cmd_args, files, *target_width),
+        Some(Command::Dev(DevCommand::FetchCorpus { dir, refresh })) => {
+            if !fetch_corpus(dir.as_deref(), *refresh)? {
+                std::process::exit(exit::FOUND);
+            }
+            return Ok(());
+        }
+        Some(Command::ExtractFromLog { min_nodes, file }) => return run_extract(cmd_args, file, *min_nodes),
+        Some(Command::Completions { shell }) => {
+            CmdArgs::clap().gen_completions_to(env!("CARGO_BIN_NAME"), *shell, &mut io::stdout());
+            return Ok(());
+        }
+        None => {}
+    }
+    if cmd_args.emit() == Emit::StyleManifest {
+        let manifest = style_manifest(cmd_args);
+        match cmd_args.output_format() {
+            OutputFormat::Json => writeln!(io::stdout(), "{}", serde_json::to_string_pretty(&manifest.to_json())?)?,
+            _ => writeln!(io::stdout(), "{}", manifest.format(format_args(cmd_args)))?,
+        }
+        return Ok(());
+    }
+    if cmd_args.stream() {
+        if check_stream(cmd_args)? {
+            std::process::exit(exit::FOUND);
+        }
+        return Ok(());
+    }
+    if cmd_args.check() {
+        if check_files(cmd_args)? {
+            std::process::exit(exit::FOUND);
+        }
+        return Ok(());
+    }
+    if cmd_args.emit() == Emit::TokensJson {
+        return emit_tokens(cmd_args);
+    }
+    if cmd_args.lsp() {
+        return Ok(lsp::serve(|path: &str, text: &str, lines: Option<(usize, usize)>| {
+            let mut args = cmd_args.for_file(Path::new(path)).into_owned();
+            args.line_range = lines.map(|(start, end)| LineRange { start, end });
+            format_document(&args, text, path)
+        })?);
+    }
+    if let Some(addr) = cmd_args.serve() {
+        return Ok(http::serve(addr, |request| answer_http(cmd_args, request))?);
+    }
+    if cmd_args.api_version() {
+        writeln!(io::stdout(), "{}", api::handshake())?;
+        return Ok(());
+    }
+    if cmd_args.api() {
+        return Ok(api::serve(|request: &api::Request| {
+            let settings = preview_settings(&request.options)
+                .map_err(|message| Diagnostic { kind: Kind::Other, file: None, position: None, message, snippet: None })?;
+            let name = request.path.as_deref().unwrap_or(cmd_args.stdin_name());
+            let mut args = cmd_args.for_file(Path::new(name)).with_settings(&settings);
+            args.line_range = request.lines.map(|(start, end)| LineRange { start, end });
+            format_document(&args, &request.text, name)
+        })?);
+    }
+    if let Some(framing) = cmd_args.daemon() {
+        return Ok(daemon::serve(framing, |text| format_document(cmd_args, text, cmd_args.stdin_name()))?);
+    }
+    if cmd_args.emacs() {
+        let input = read_stdin()?;
+        write_stdout(cmd_args, &emacs_indent(cmd_args, &input))?;
+        return Ok(());
+    }
+    if cmd_args.repl() {
+        return Ok(repl::repl(|input| format_source(cmd_args, input).map_err(|error| error.message.to_string()))?);
+    }
+    if cmd_args.watch() {
+        return watch_files(cmd_args);
+    }
+    if !cmd_args.files().is_empty() {
+        return format_files(cmd_args);
+    }
+    if cmd_args.embedded() {
+        let input = read_stdin()?;
+        write_stdout(cmd_args, &format_host(cmd_args, &input, cmd_args.stdin_name())?)?;
+        return Ok(());
+    }
+    if cmd_args.piped() && cmd_args.pipeline().is_none() && cmd_args.input_format() == InputFormat::Sexpr
+        && cmd_args.output_format() == OutputFormat::Sexpr && cmd_args.emit() == Emit::Formatted && !cmd_args.debug() {
+        return filter_stdin(cmd_args);
+    }
+    let pipeline = cmd_args.pipeline().map(Pipeline::parse).transpose()?;
+    let input = read_input(cmd_args)?;
+    if let Some(pipeline) = pipeline {
+        return pipeline.run(&input).map_err(|error| match error.downcast_ref::<SyntaxError>() {
+            Some(error) => Diagnostic::syntax(cmd_args.stdin_name(), error).into(),
+            None => error,
+        });
+    }
+    let input = if cmd_args.fix_delimiters() && cmd_args.input_format() == InputFormat::Sexpr {
+        fix_delimiters(cmd_args.stdin_name(), &input)
+    } else {
+        input
+    };
+    let input = match cmd_args.input_format() {
+        InputFormat::Sexpr => apply_parse_mode(cmd_args, cmd_args.stdin_name(), &round_brackets(cmd_args, &input))?.into_owned(),
+        InputFormat::Json => input,
+    };
+    let mut trailing = "";
+    let mut sexpr = match cmd_args.input_format() {
+        InputFormat::Sexpr => {
+            let name = cmd_args.stdin_name();
+            let start = input.len() - input.trim_start().len();
+            let (sexpr, tail) = Sexpr::parse_first(&input).map_err(|e| diagnostic::mismatched_delimiters(name, &input)
+                .unwrap_or_else(|| Diagnostic::at(Kind::Parse, name, &input, start, e)))?;
+            if !tail.is_empty() {
+                trailing = handle_trailing(cmd_args, name, &input, tail, sexpr::trailing_error(tail))?;
+            }
+            sexpr
+        }
+        InputFormat::Json => Sexpr::from_json_with(&serde_json::from_str(&input)?, cmd_args.json_keys()),
+    };
+    transform(cmd_args, &mut sexpr);
     if cmd_args.debug() {
-        println!("final result: {:#?}", sexpr);
+        writeln!(io::stdout(), "final result: {:#?}", sexpr)?;
+    }
+    match cmd_args.output_format() {
+        OutputFormat::Sexpr => match cmd_args.emit() {
+            Emit::Formatted => {
+                let formatted = format_sexpr(cmd_args, &sexpr) + "\n";
+                check_line_lengths(cmd_args, cmd_args.stdin_name(), &formatted)?;
+                write_stdout(cmd_args, &postprocess(cmd_args, cmd_args.stdin_name(), formatted)?)?;
+            }
+            Emit::Annotated => write_stdout(cmd_args, &annotate::annotate(&sexpr, format_args(cmd_args))?)?,
+            Emit::Infix => write_stdout(cmd_args, &format!("{}\n", sexpr.to_infix(cmd_args.full_parens())))?,
+            Emit::StyleManifest | Emit::TokensJson => unreachable!("these are emitted before reading any input"),
+        },
+        OutputFormat::Json => write_stdout(cmd_args, &format!("{}\n", serde_json::to_string_pretty(&sexpr.to_json())?))?,
+        OutputFormat::Dot => write_stdout(cmd_args, &sexpr.to_dot())?,
+    }
+    if !trailing.is_empty() {
+        write_stdout(cmd_args, trailing)?;
     }
-    sexpr.pretty_print(&cmd_args)?;
     Ok(())
 }
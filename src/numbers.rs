@@ -0,0 +1,276 @@
+use crate::prelude::*;
+use core::fmt;
+use core::str::FromStr;
+use crate::sexpr::{Sexpr, ComplexityMetric};
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed};
+use crate::aliases::AliasGroup;
+
+/// Fixes the number of decimal places of the decimal arguments of forms with a particular head
+/// written as `head=digits`, such as `at=4`, or `*=4` to apply to every form
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrecisionRule {
+    head: Option<String>,
+    digits: usize,
+}
+impl FromStr for PrecisionRule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<PrecisionRule, String> {
+        let (head, digits) = s.rsplit_once('=')
+            .ok_or_else(|| format!("expected `head=digits`, found `{}`", s))?;
+        let digits = digits.parse()
+            .map_err(|_| format!("expected a number of digits, found `{}`", digits))?;
+        let head = if head == "*" { None } else { Some(head.to_string()) };
+        Ok(PrecisionRule { head, digits })
+    }
+}
+impl fmt::Display for PrecisionRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.head.as_deref().unwrap_or("*"), self.digits)
+    }
+}
+impl PrecisionRule {
+    fn applies_to(&self, head: &Sexpr<'_>, aliases: &[AliasGroup]) -> bool {
+        match self.head {
+            Some(ref name) => head.is_named_as(name, aliases),
+            None => true,
+        }
+    }
+}
+
+impl Sexpr<'_> {
+    /// Rewrite every decimal atom which is an argument of a form covered by `rules`
+    /// to have exactly the specified number of digits after the decimal point
+    /// when several rules apply, the first one wins
+    pub fn fix_precision(&mut self, rules: &[PrecisionRule], aliases: &[AliasGroup]) {
+        if let Prefixed(_, ref mut sexpr) = self.kind {
+            sexpr.fix_precision(rules, aliases);
+        }
+        if let Compound(ref mut head, ref mut args) = self.kind {
+            head.fix_precision(rules, aliases);
+            let rule = rules.iter().find(|rule| rule.applies_to(head, aliases));
+            for arg in args.iter_mut() {
+                match (&mut arg.kind, rule) {
+                    (Atom(ref mut text), Some(rule)) => {
+                        if let Some(rounded) = round_decimal(text, rule.digits) {
+                            *text = rounded.into();
+                            arg.remeasure();
+                        }
+                    }
+                    _ => arg.fix_precision(rules, aliases),
+                }
+            }
+        }
+        self.remeasure();
+    }
+}
+
+/// The kinds of numeric literal that are recognized
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NumberKind {
+    /// like `-12`
+    Integer,
+    /// like `-1.25` or `3.`
+    Decimal,
+    /// like `1/3` or `-22/7`
+    Rational,
+    /// like `0xff`, `#xff`, or `-0x1F`
+    Hex,
+    /// like the smt-lib bit-vector `#b1010`
+    Binary,
+}
+impl NumberKind {
+    /// The kind of number that `text` is, if it is one
+    pub fn of(text: &str) -> Option<NumberKind> {
+        if is_integer(text) {
+            Some(NumberKind::Integer)
+        } else if is_decimal(text) {
+            Some(NumberKind::Decimal)
+        } else if is_rational(text) {
+            Some(NumberKind::Rational)
+        } else if is_hex(text) {
+            Some(NumberKind::Hex)
+        } else if is_binary(text) {
+            Some(NumberKind::Binary)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `text` is an integer, like `-12`
+pub fn is_integer(text: &str) -> bool {
+    let unsigned = text.strip_prefix(|ch| ch == '-' || ch == '+').unwrap_or(text);
+    !unsigned.is_empty() && unsigned.chars().all(|ch| ch.is_ascii_digit())
+}
+
+/// Whether `text` is a decimal number, like `-1.25` or `3.`
+/// exponents are not recognized
+pub fn is_decimal(text: &str) -> bool {
+    let unsigned = text.strip_prefix(|ch| ch == '-' || ch == '+').unwrap_or(text);
+    match unsigned.split_once('.') {
+        Some((int, frac)) => {
+            !(int.is_empty() && frac.is_empty())
+                && int.chars().all(|ch| ch.is_ascii_digit())
+                && frac.chars().all(|ch| ch.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// Whether `text` is a rational number, like `1/3` or `-22/7`
+pub fn is_rational(text: &str) -> bool {
+    match text.split_once('/') {
+        Some((numerator, denominator)) => {
+            is_integer(numerator) && !denominator.is_empty() && denominator.chars().all(|ch| ch.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// Whether `text` is a hexadecimal integer, like `0xff` or the smt-lib style `#xff`
+pub fn is_hex(text: &str) -> bool {
+    let unsigned = text.strip_prefix(|ch| ch == '-' || ch == '+').unwrap_or(text);
+    let digits = ["0x", "0X", "#x"].iter().find_map(|prefix| unsigned.strip_prefix(prefix));
+    digits.is_some_and(|digits| !digits.is_empty() && digits.chars().all(|ch| ch.is_ascii_hexdigit()))
+}
+
+/// Whether `text` is a binary integer, like the smt-lib style `#b1010`
+pub fn is_binary(text: &str) -> bool {
+    text.strip_prefix("#b").is_some_and(|digits| !digits.is_empty() && digits.chars().all(|ch| ch == '0' || ch == '1'))
+}
+
+/// The widths to right-align the numbers of each argument of `args` to, for the arguments which are rows of a table
+/// a table is a run of at least two rows printed on one line with the same shape: the same heads and numbers of
+/// arguments, with only atoms and rows nested in them, like `(at 1.0 2.54)` and `(at 10.5 -3)`,
+/// or `(pad 1 smd (at 1.0 2.54))` and `(pad 22 smd (at -10.25 3))`
+/// the columns of a row are its atoms, nested ones included, in the order they are written, as counted by `row_cells`;
+/// each row gets the width of the widest number in each of its columns, or 0 for columns without numbers
+pub(crate) fn number_columns(args: &[Sexpr<'_>], complexity_threshold: u32, metric: ComplexityMetric) -> Vec<Option<Vec<usize>>> {
+    // the head and number of arguments of each compound in `sexpr`, and None for each atom, in the order they are written
+    fn push_shape<'s>(sexpr: &'s Sexpr<'_>, shape: &mut Vec<Option<(&'s str, usize)>>) -> Option<()> {
+        match sexpr.kind {
+            Atom(_) => shape.push(None),
+            Compound(ref head, ref row) => match head.kind {
+                Atom(ref name) => {
+                    shape.push(Some((name, row.len())));
+                    for arg in row.iter() {
+                        push_shape(arg, shape)?;
+                    }
+                }
+                _ => return None,
+            },
+            _ => return None,
+        }
+        Some(())
+    }
+    // the shape of `sexpr`, if it is a row
+    fn row_shape<'s>(sexpr: &'s Sexpr<'_>, complexity_threshold: u32, metric: ComplexityMetric) -> Option<Vec<Option<(&'s str, usize)>>> {
+        if !matches!(sexpr.kind, Compound(..)) || sexpr.measure(metric) > complexity_threshold {
+            return None;
+        }
+        let mut shape = Vec::new();
+        push_shape(sexpr, &mut shape)?;
+        Some(shape)
+    }
+    // the atoms in the arguments of `sexpr`, in the order they are written
+    fn push_cells<'s>(sexpr: &'s Sexpr<'_>, cells: &mut Vec<&'s str>) {
+        match sexpr.kind {
+            Atom(ref text) => cells.push(text),
+            Compound(_, ref row) => row.iter().for_each(|arg| push_cells(arg, cells)),
+            _ => {}
+        }
+    }
+    let row_shape = |sexpr| row_shape(sexpr, complexity_threshold, metric);
+    let mut columns = vec![None; args.len()];
+    let mut start = 0;
+    while start < args.len() {
+        let shape = match row_shape(&args[start]) {
+            Some(shape) => shape,
+            None => {
+                start += 1;
+                continue;
+            }
+        };
+        let len = args[start..].iter().take_while(|arg| row_shape(arg).as_ref() == Some(&shape)).count();
+        if len >= 2 {
+            let mut widths = vec![0; row_cells(&args[start])];
+            for row in args[start..start + len].iter() {
+                let mut cells = Vec::new();
+                push_cells(row, &mut cells);
+                for (width, text) in widths.iter_mut().zip(cells) {
+                    if NumberKind::of(text).is_some() {
+                        *width = core::cmp::max(*width, text.chars().count());
+                    }
+                }
+            }
+            for column in columns[start..start + len].iter_mut() {
+                *column = Some(widths.clone());
+            }
+        }
+        start += len;
+    }
+    columns
+}
+
+/// The number of columns `sexpr` takes up in a row of a table: one for an atom,
+/// and those of its arguments for a nested row
+pub(crate) fn row_cells(sexpr: &Sexpr<'_>) -> usize {
+    match sexpr.kind {
+        Atom(_) => 1,
+        Compound(_, ref row) => row.iter().map(row_cells).sum(),
+        _ => 0,
+    }
+}
+
+/// Round the decimal number `text` to exactly `digits` places after the decimal point,
+/// rounding halves away from zero
+/// this works on the digits of the text, rather than going through a float, so it is exact
+/// and does not depend on the locale in any way
+/// returns None if `text` is not a decimal
+pub fn round_decimal(text: &str, digits: usize) -> Option<String> {
+    if !is_decimal(text) {
+        return None;
+    }
+    let (sign, unsigned) = match text.chars().next() {
+        Some(ch @ '-') | Some(ch @ '+') => (Some(ch), &text[1..]),
+        _ => (None, text),
+    };
+    let (int, frac) = unsigned.split_once('.')?;
+    let int = if int.is_empty() { "0" } else { int };
+    // all of the digits we keep, with the ones we drop used to decide the rounding
+    let mut kept: Vec<u8> = int.bytes()
+        .chain(frac.bytes().chain(core::iter::repeat(b'0')).take(digits))
+        .map(|b| b - b'0')
+        .collect();
+    let round_up = frac.as_bytes().get(digits).is_some_and(|&b| b >= b'5');
+    if round_up {
+        let mut idx = kept.len();
+        loop {
+            if idx == 0 {
+                kept.insert(0, 1);
+                break;
+            }
+            idx -= 1;
+            if kept[idx] == 9 {
+                kept[idx] = 0;
+            } else {
+                kept[idx] += 1;
+                break;
+            }
+        }
+    }
+    let int_len = kept.len() - digits;
+    let mut result = String::new();
+    let is_zero = kept.iter().all(|&d| d == 0);
+    match sign {
+        Some('-') if !is_zero => result.push('-'),
+        Some('+') => result.push('+'),
+        _ => {}
+    }
+    result.extend(kept[..int_len].iter().map(|&d| (b'0' + d) as char));
+    if digits > 0 {
+        result.push('.');
+        result.extend(kept[int_len..].iter().map(|&d| (b'0' + d) as char));
+    }
+    Some(result)
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use crate::sexpr::{Sexpr, ParseError};
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed};
+
+/// A pattern for destructuring sexprs, such as `(define ($name $params...) _ ...)`
+/// `_` matches anything, `$name` matches any subtree and binds it to `name` (and must match the same subtree everywhere it appears),
+/// and a trailing `...` matches any remaining arguments (a trailing `$name...` does the same, and also binds them to `name`)
+/// anything else matches only itself
+///
+/// ```
+/// use sexpr_fmt::Sexpr;
+/// use sexpr_fmt::pattern::Pattern;
+///
+/// let pattern = Pattern::parse("(define ($name $params...) $body ...)").unwrap();
+/// let sexpr = Sexpr::parse("(define (square x) (* x x))").unwrap();
+/// let found = pattern.bind(&sexpr).unwrap();
+/// assert_eq!(found.get("name").and_then(Sexpr::as_atom), Some("square"));
+/// assert_eq!(found.get_rest("params").unwrap().len(), 1);
+/// assert_eq!(found.get("body").unwrap().to_compact_string(), "(* x x)");
+///
+/// let twice = Pattern::parse("(* $x $x)").unwrap();
+/// assert_eq!(twice.find(&sexpr).len(), 1);
+/// assert!(!twice.matches(&Sexpr::parse("(* x y)").unwrap()));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern<'a> {
+    sexpr: Sexpr<'a>,
+}
+
+/// What a `$name` in a pattern matched
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Binding<'s, 'a> {
+    One(&'s Sexpr<'a>),
+    /// the arguments matched by a trailing `$name...`
+    Rest(&'s [Sexpr<'a>]),
+}
+
+/// What each `$name` of a pattern matched, from `Pattern::bind`
+#[derive(Clone, Debug, Default)]
+pub struct Match<'s, 'a> {
+    bindings: HashMap<&'s str, Binding<'s, 'a>>,
+}
+
+impl<'a> Pattern<'a> {
+    pub fn new(sexpr: Sexpr<'a>) -> Pattern<'a> {
+        Pattern { sexpr }
+    }
+    pub fn parse(text: &'a str) -> Result<Pattern<'a>, ParseError> {
+        Sexpr::parse(text).map(Pattern::new)
+    }
+    pub fn as_sexpr(&self) -> &Sexpr<'a> {
+        &self.sexpr
+    }
+    /// What each `$name` in this pattern matched in `sexpr`, if it matches
+    pub fn bind<'s, 'x>(&'s self, sexpr: &'s Sexpr<'x>) -> Option<Match<'s, 'x>> {
+        let mut found = Match::default();
+        if bind(sexpr, &self.sexpr, &mut found) {
+            Some(found)
+        } else {
+            None
+        }
+    }
+    /// Whether `sexpr` matches this pattern
+    pub fn matches(&self, sexpr: &Sexpr<'_>) -> bool {
+        self.bind(sexpr).is_some()
+    }
+    /// Every subtree of `sexpr` matching this pattern, in pre-order
+    pub fn find<'s, 'x>(&self, sexpr: &'s Sexpr<'x>) -> Vec<&'s Sexpr<'x>> {
+        sexpr.iter().filter(|subtree| self.matches(subtree)).collect()
+    }
+}
+
+impl<'s, 'a> Match<'s, 'a> {
+    /// What `$name` matched
+    pub fn binding(&self, name: &str) -> Option<Binding<'s, 'a>> {
+        self.bindings.get(name).copied()
+    }
+    /// The subtree `$name` matched
+    pub fn get(&self, name: &str) -> Option<&'s Sexpr<'a>> {
+        match self.binding(name)? {
+            Binding::One(sexpr) => Some(sexpr),
+            Binding::Rest(_) => None,
+        }
+    }
+    /// The arguments a trailing `$name...` matched
+    pub fn get_rest(&self, name: &str) -> Option<&'s [Sexpr<'a>]> {
+        match self.binding(name)? {
+            Binding::Rest(args) => Some(args),
+            Binding::One(_) => None,
+        }
+    }
+    /// Record that `$name` matched `binding`, or check that it matches what it matched before
+    fn insert(&mut self, name: &'s str, binding: Binding<'s, 'a>) -> bool {
+        match self.bindings.get(name) {
+            Some(bound) => *bound == binding,
+            None => {
+                self.bindings.insert(name, binding);
+                true
+            }
+        }
+    }
+}
+
+/// If `sexpr` is a rest pattern like `...` or `$name...`, the name it binds (if any)
+pub(crate) fn rest_name<'s>(sexpr: &'s Sexpr<'_>) -> Option<Option<&'s str>> {
+    match sexpr.kind {
+        Atom(ref text) => {
+            let name = text.strip_suffix("...")?;
+            match name.strip_prefix('$') {
+                Some(name) if !name.is_empty() => Some(Some(name)),
+                _ if name.is_empty() => Some(None),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Whether `sexpr` matches `pattern`, recording what each `$name` in the pattern matched
+pub(crate) fn bind<'s, 'a>(sexpr: &'s Sexpr<'a>, pattern: &'s Sexpr<'_>, found: &mut Match<'s, 'a>) -> bool {
+    match (&sexpr.kind, &pattern.kind) {
+        (_, Atom(_)) if pattern.is_named("_") => true,
+        (_, Atom(name)) if name.starts_with('$') && name.len() > 1 => found.insert(&name[1..], Binding::One(sexpr)),
+        (Atom(text), Atom(pattern_text)) => text == pattern_text,
+        (Prefixed(prefix, sexpr), Prefixed(pattern_prefix, pattern)) => prefix == pattern_prefix && bind(sexpr, pattern, found),
+        (Compound(head, args), Compound(pattern_head, pattern_args)) => {
+            if !bind(head, pattern_head, found) {
+                return false;
+            }
+            let (pattern_args, rest) = match pattern_args.split_last() {
+                Some((last, before)) if rest_name(last).is_some() => (before, rest_name(last)),
+                _ => (&pattern_args[..], None),
+            };
+            let lengths_match = match rest {
+                Some(_) => args.len() >= pattern_args.len(),
+                None => args.len() == pattern_args.len(),
+            };
+            if !lengths_match || !args.iter().zip(pattern_args.iter()).all(|(arg, pattern)| bind(arg, pattern, found)) {
+                return false;
+            }
+            match rest {
+                Some(Some(name)) => found.insert(name, Binding::Rest(&args[pattern_args.len()..])),
+                _ => true,
+            }
+        }
+        _ => false,
+    }
+}
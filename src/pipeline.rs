@@ -0,0 +1,188 @@
+use structopt::StructOpt;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use crate::sexpr::{Sexpr, FormatArgs, ComplexityMetric, CommentStyle};
+use crate::compat::LayoutVersion;
+use crate::rewrite::Rule;
+use crate::json::KeyStyle;
+
+/// One step of a `--pipeline`, such as `fmt -c 2` or `to-json`
+/// a pipeline is a source stage, followed by any number of transformations, followed by a sink stage
+#[derive(Debug)]
+pub enum Stage {
+    /// read the input as an s-expression
+    FromSexpr,
+    /// read the input as json, writing the keys of objects in the given style
+    FromJson(KeyStyle),
+    /// apply the rewrite rules in the given text
+    Rewrite(String),
+    /// pretty print the expression as an s-expression
+    Fmt(FormatArgs<'static>),
+    /// write the expression as json
+    ToJson,
+    /// write the expression as a graphviz digraph
+    ToDot,
+}
+
+/// The arguments accepted by the `fmt` stage of a pipeline, which are the layout settings of the command of the same names
+/// (the ones taking lists of heads, like `--always-break`, are left out)
+#[derive(StructOpt)]
+#[structopt(name = "fmt")]
+struct FmtStage {
+    #[structopt(short, long, default_value = "1")]
+    complexity_threshold: u32,
+    #[structopt(long, default_value = "depth", possible_values = &["depth", "nodes", "width"])]
+    complexity_metric: ComplexityMetric,
+    #[structopt(short = "q", long)]
+    short_quantifiers: bool,
+    #[structopt(long)]
+    inline_unquoted: bool,
+    #[structopt(long)]
+    reflow_comments: bool,
+    #[structopt(long)]
+    align_numbers: bool,
+    #[structopt(long)]
+    pair_keywords: bool,
+    #[structopt(long)]
+    align_bindings: bool,
+    #[structopt(long)]
+    max_width: Option<usize>,
+    #[structopt(long, default_value = "80")]
+    comment_width: usize,
+    #[structopt(long, default_value = "preserve", possible_values = &["preserve", "attach-trailing", "own-line"])]
+    comment_style: CommentStyle,
+    #[structopt(long, default_value = "8")]
+    tab_width: usize,
+    #[structopt(long, possible_values = &["0.1", "0.2", "0.3"])]
+    compat: Option<LayoutVersion>,
+}
+
+/// The settings `FmtStage` takes, for saying so when it is given another
+const FMT_SETTINGS: &str = "-c/--complexity-threshold, --complexity-metric, -q/--short-quantifiers, --inline-unquoted, \
+    --reflow-comments, --align-numbers, --pair-keywords, --align-bindings, --max-width, --comment-width, --comment-style, --tab-width, --compat";
+
+impl Stage {
+    /// Parse a single stage, such as `fmt -c 2`
+    fn parse(text: &str) -> Result<Stage, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let stage = match words.first() {
+            Some(&"from-sexpr") => Stage::FromSexpr,
+            Some(&"from-json") => match words[1..] {
+                [] => Stage::FromJson(KeyStyle::Auto),
+                ["--keys", style] => Stage::FromJson(style.parse()?),
+                _ => return Err("usage: from-json [--keys <auto|keyword|string|symbol>]".to_string()),
+            },
+            Some(&"to-json") => Stage::ToJson,
+            Some(&"to-dot") => Stage::ToDot,
+            Some(&"rewrite") => match words[1..] {
+                [file] => {
+                    let rules = fs::read_to_string(file).map_err(|e| format!("could not read `{}`: {}", file, e))?;
+                    // check the rules now, so that mistakes are reported before reading any input
+                    Rule::parse_all(&rules)?;
+                    Stage::Rewrite(rules)
+                }
+                _ => return Err("usage: rewrite <rules-file>".to_string()),
+            },
+            Some(&"fmt") => {
+                let fmt = FmtStage::from_iter_safe(&words).map_err(|e| {
+                    let problem = e.message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string();
+                    format!("{}; the fmt stage takes only the settings {}", problem, FMT_SETTINGS)
+                })?;
+                let mut fmt_args = FormatArgs::new();
+                fmt_args.complexity_threshold = fmt.complexity_threshold;
+                fmt_args.complexity_metric = fmt.complexity_metric;
+                fmt_args.short_quantifiers = fmt.short_quantifiers;
+                fmt_args.inline_unquoted = fmt.inline_unquoted;
+                fmt_args.reflow_comments = fmt.reflow_comments;
+                fmt_args.align_numbers = fmt.align_numbers;
+                fmt_args.pair_keywords = fmt.pair_keywords;
+                fmt_args.align_bindings = fmt.align_bindings;
+                fmt_args.max_width = fmt.max_width;
+                fmt_args.comment_width = fmt.comment_width;
+                fmt_args.comment_style = fmt.comment_style;
+                fmt_args.tab_width = fmt.tab_width;
+                fmt_args.layout_version = fmt.compat.unwrap_or(LayoutVersion::CURRENT);
+                Stage::Fmt(fmt_args)
+            }
+            Some(name) => return Err(format!("unknown pipeline stage `{}`", name)),
+            None => return Err("empty pipeline stage".to_string()),
+        };
+        Ok(stage)
+    }
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::FromSexpr => "from-sexpr",
+            Stage::FromJson(_) => "from-json",
+            Stage::Rewrite(_) => "rewrite",
+            Stage::Fmt(_) => "fmt",
+            Stage::ToJson => "to-json",
+            Stage::ToDot => "to-dot",
+        }
+    }
+    fn is_source(&self) -> bool {
+        matches!(self, Stage::FromSexpr | Stage::FromJson(_))
+    }
+    fn is_sink(&self) -> bool {
+        matches!(self, Stage::Fmt(_) | Stage::ToJson | Stage::ToDot)
+    }
+}
+
+/// A sequence of stages, separated by `|`, which are all run over each top-level form of the input
+#[derive(Debug)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Parse a pipeline such as `from-sexpr | fmt -c 2`
+    /// the source stage may be omitted, in which case the input is read as an s-expression
+    pub fn parse(text: &str) -> Result<Pipeline, String> {
+        let mut stages = text.split('|')
+            .map(Stage::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if !stages[0].is_source() {
+            stages.insert(0, Stage::FromSexpr);
+        }
+        match stages.last() {
+            Some(stage) if stage.is_sink() => {},
+            _ => return Err("a pipeline must end with an output stage (fmt, to-json, to-dot)".to_string()),
+        }
+        if let Some(stage) = stages[1..].iter().find(|stage| stage.is_source()) {
+            return Err(format!("`{}` may only appear at the start of a pipeline", stage.name()));
+        }
+        if let Some(stage) = stages[..stages.len() - 1].iter().find(|stage| stage.is_sink()) {
+            return Err(format!("`{}` may only appear at the end of a pipeline", stage.name()));
+        }
+        Ok(Pipeline { stages })
+    }
+    /// Run every stage of this pipeline over each top-level form of `input`, writing the results to stdout one after the other
+    /// input which cannot be read fails with a `SyntaxError` saying where
+    pub fn run(&self, input: &str) -> Result<(), Box<dyn Error>> {
+        let mut forms = Vec::new();
+        for stage in self.stages.iter() {
+            match stage {
+                Stage::FromSexpr => forms = Sexpr::parse_many_located(input)?,
+                Stage::FromJson(keys) => forms = vec![Sexpr::from_json_with(&serde_json::from_str(input)?, *keys)],
+                Stage::Rewrite(rules) => {
+                    let rules = Rule::parse_all(rules)?;
+                    forms = forms.into_iter().map(|sexpr| sexpr.rewrite(&rules)).collect::<Result<_, _>>()?;
+                }
+                Stage::Fmt(fmt_args) => {
+                    let mut stdout = io::stdout().lock();
+                    for sexpr in forms.iter() {
+                        sexpr.write_to(&mut stdout, *fmt_args)?;
+                        writeln!(stdout)?;
+                    }
+                }
+                Stage::ToJson => for sexpr in forms.iter() {
+                    writeln!(io::stdout(), "{}", serde_json::to_string_pretty(&sexpr.to_json())?)?;
+                },
+                Stage::ToDot => for sexpr in forms.iter() {
+                    write!(io::stdout(), "{}", sexpr.to_dot())?;
+                },
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use crate::builder::SexprOwned;
+use crate::compat::LayoutVersion;
+use crate::sexpr::{Sexpr, FormatArgs};
+
+/// The layout options, named like the command line flags, such as `Options(complexity_threshold=2)`
+#[pyclass(name = "Options", from_py_object)]
+#[derive(Clone)]
+pub struct Options {
+    #[pyo3(get, set)]
+    complexity_threshold: u32,
+    #[pyo3(get, set)]
+    short_quantifiers: bool,
+    #[pyo3(get, set)]
+    inline_unquoted: bool,
+    #[pyo3(get, set)]
+    reflow_comments: bool,
+    #[pyo3(get, set)]
+    comment_width: usize,
+    #[pyo3(get, set)]
+    tab_width: usize,
+    #[pyo3(get, set)]
+    align_numbers: bool,
+    #[pyo3(get, set)]
+    pair_keywords: bool,
+    #[pyo3(get, set)]
+    align_bindings: bool,
+    #[pyo3(get, set)]
+    max_width: Option<usize>,
+    /// the layout version, like `"0.1"`
+    #[pyo3(get, set)]
+    compat: Option<String>,
+}
+#[pymethods]
+impl Options {
+    #[new]
+    #[pyo3(signature = (
+        complexity_threshold = 1, short_quantifiers = false, inline_unquoted = false, reflow_comments = false,
+        comment_width = 80, tab_width = 8, align_numbers = false, pair_keywords = false, align_bindings = false,
+        max_width = None, compat = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        complexity_threshold: u32, short_quantifiers: bool, inline_unquoted: bool, reflow_comments: bool,
+        comment_width: usize, tab_width: usize, align_numbers: bool, pair_keywords: bool, align_bindings: bool,
+        max_width: Option<usize>, compat: Option<String>,
+    ) -> Options {
+        Options {
+            complexity_threshold, short_quantifiers, inline_unquoted, reflow_comments, comment_width, tab_width,
+            align_numbers, pair_keywords, align_bindings, max_width, compat,
+        }
+    }
+}
+impl Options {
+    fn format_args(&self) -> PyResult<FormatArgs<'_>> {
+        let mut args = FormatArgs::new();
+        args.complexity_threshold = self.complexity_threshold;
+        args.short_quantifiers = self.short_quantifiers;
+        args.inline_unquoted = self.inline_unquoted;
+        args.reflow_comments = self.reflow_comments;
+        args.comment_width = self.comment_width;
+        args.tab_width = self.tab_width;
+        args.align_numbers = self.align_numbers;
+        args.pair_keywords = self.pair_keywords;
+        args.align_bindings = self.align_bindings;
+        args.max_width = self.max_width;
+        if let Some(ref version) = self.compat {
+            args.layout_version = version.parse::<LayoutVersion>().map_err(PyValueError::new_err)?;
+        }
+        Ok(args)
+    }
+}
+
+/// The layout of `options`, or the default one
+fn format_args(options: Option<&Options>) -> PyResult<FormatArgs<'_>> {
+    options.map_or_else(|| Ok(FormatArgs::new()), Options::format_args)
+}
+
+/// A parsed s-expression, which `str()` writes on one line
+#[pyclass(name = "Sexpr", frozen)]
+pub struct PySexpr(SexprOwned);
+#[pymethods]
+impl PySexpr {
+    /// Pretty print this s-expression with `options`
+    #[pyo3(signature = (options = None))]
+    fn format(&self, options: Option<Options>) -> PyResult<String> {
+        Ok(self.0.format(format_args(options.as_ref())?))
+    }
+    /// The nesting depth of this s-expression, which decides whether it fits on one line
+    fn complexity(&self) -> u32 {
+        self.0.complexity()
+    }
+    fn __str__(&self) -> String {
+        self.0.to_compact_string()
+    }
+    fn __repr__(&self) -> String {
+        format!("Sexpr({:?})", self.0.to_compact_string())
+    }
+}
+
+/// Read every top-level form of `text`, raising `ValueError` if one cannot be read
+#[pyfunction]
+fn parse(text: &str) -> PyResult<Vec<PySexpr>> {
+    let forms = Sexpr::parse_many(text).map_err(PyValueError::new_err)?;
+    Ok(forms.into_iter().map(|form| PySexpr(form.into_owned())).collect())
+}
+
+/// Format every top-level form of `text`, each on its own line, with `options`
+#[pyfunction]
+#[pyo3(signature = (text, options = None))]
+fn format(text: &str, options: Option<Options>) -> PyResult<String> {
+    let args = format_args(options.as_ref())?;
+    let mut out = String::new();
+    for form in Sexpr::parse_many(text).map_err(PyValueError::new_err)? {
+        out.push_str(&form.format(args));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Parsing and pretty printing s-expressions
+#[pymodule]
+fn sexpr_fmt(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Options>()?;
+    module.add_class::<PySexpr>()?;
+    module.add_function(wrap_pyfunction!(parse, module)?)?;
+    module.add_function(wrap_pyfunction!(format, module)?)?;
+    Ok(())
+}
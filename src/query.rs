@@ -0,0 +1,63 @@
+use crate::sexpr::{Sexpr, ParseError};
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+use crate::pattern::Pattern;
+
+/// A way of selecting subtrees of an s-expression
+/// either a path such as `.2.1`, or a pattern such as `(assert _)` or `(declare-fun $name ...)`
+#[derive(Debug)]
+pub enum Query<'a> {
+    Path(Vec<usize>),
+    Pattern(Pattern<'a>),
+}
+
+impl Query<'_> {
+    /// Parse a query: text starting with a `.` is a path, anything else is a pattern
+    pub fn parse(text: &str) -> Result<Query<'_>, ParseError> {
+        let text = text.trim();
+        if text.starts_with('.') {
+            parse_path(text).map(Query::Path)
+        } else {
+            Pattern::parse(text).map(Query::Pattern)
+        }
+    }
+    /// Every subtree of `sexpr` selected by this query, in the order they appear
+    pub fn find<'s, 'x>(&self, sexpr: &'s Sexpr<'x>) -> Vec<&'s Sexpr<'x>> {
+        match self {
+            Query::Path(path) => sexpr.get_path(path).into_iter().collect(),
+            Query::Pattern(pattern) => pattern.find(sexpr),
+        }
+    }
+}
+
+/// Parse a path in the form `.2.1.0`, with `.` on its own being the root
+pub fn parse_path(text: &str) -> Result<Vec<usize>, ParseError> {
+    if text == "." {
+        return Ok(Vec::new());
+    }
+    text.strip_prefix('.')
+        .ok_or("malformed path: expected `.`")?
+        .split('.')
+        .map(|index| index.parse().map_err(|_| "malformed path: expected an index"))
+        .collect()
+}
+
+impl<'x> Sexpr<'x> {
+    /// The subtree at `path`, where the head of a compound is index 0 and its arguments start at 1
+    /// the form under a prefix like `'` is at index 1, as if it were `(quote form)`
+    pub fn get_path(&self, path: &[usize]) -> Option<&Sexpr<'x>> {
+        let (&index, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return Some(self),
+        };
+        match self.kind {
+            Compound(ref head, _) if index == 0 => head.get_path(rest),
+            Compound(_, ref args) => args.get(index - 1)?.get_path(rest),
+            Prefixed(_, ref sexpr) if index == 1 => sexpr.get_path(rest),
+            Prefixed(..) | Atom(_) | Comment(_) => None,
+        }
+    }
+    /// Whether this sexpr matches `pattern`, read as a `Pattern`
+    pub fn matches(&self, pattern: &Sexpr<'_>) -> bool {
+        crate::pattern::bind(self, pattern, &mut Default::default())
+    }
+}
@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+use crate::aliases::AliasGroup;
+
+/// Binders like `(forall ((x Int) (y Int)) body)`, whose variables are bound in the forms after the list
+/// `lambda` may also bind a plain list of names, like `(lambda (x y) body)`
+pub const QUANTIFIER_BINDERS: &[&str] = &["forall", "exists", "lambda"];
+
+/// Binders like `(let ((x 1) (y 2)) body)`, whose values are outside of the scope of the names they bind
+pub const LET_BINDERS: &[&str] = &["let"];
+
+/// Binders like `(let* ((x 1) (y x)) body)`, where each value is in the scope of the names bound before it
+pub const SEQUENTIAL_LET_BINDERS: &[&str] = &["let*"];
+
+impl Sexpr<'_> {
+    /// Rename every bound variable to `x0`, `x1`, ... in the order that they are bound,
+    /// so that formulas which are the same up to the names of their bound variables become equal
+    /// names which already appear in the sexpr are skipped, so no free variable is captured
+    pub fn rename_bound(&mut self, aliases: &[AliasGroup]) {
+        let mut taken = HashSet::new();
+        self.collect_names(&mut taken);
+        let mut renamer = Renamer { scope: Vec::new(), next: 0, taken, aliases };
+        renamer.rename(self);
+        self.remeasure_all();
+    }
+    pub(crate) fn collect_names(&self, names: &mut HashSet<String>) {
+        match self.kind {
+            Atom(ref text) => { names.insert(text.to_string()); }
+            Prefixed(_, ref sexpr) => sexpr.collect_names(names),
+            Compound(ref head, ref args) => {
+                head.collect_names(names);
+                for arg in args.iter() {
+                    arg.collect_names(names);
+                }
+            }
+            Comment(_) => {}
+        }
+    }
+}
+
+struct Renamer<'r> {
+    scope: Vec<(String, String)>, // each bound name and what it is renamed to, innermost last
+    next: usize,
+    taken: HashSet<String>,
+    aliases: &'r [AliasGroup],
+}
+impl Renamer<'_> {
+    fn fresh(&mut self) -> String {
+        loop {
+            let name = format!("x{}", self.next);
+            self.next += 1;
+            if !self.taken.contains(&name) {
+                return name;
+            }
+        }
+    }
+    /// Rename the uses of bound variables in `sexpr`, and the variables of any binders in it
+    fn rename(&mut self, sexpr: &mut Sexpr<'_>) {
+        match sexpr.kind {
+            Atom(ref mut text) => {
+                if let Some((_, new)) = self.scope.iter().rev().find(|(old, _)| old == text) {
+                    *text = Cow::Owned(new.clone());
+                }
+            }
+            Prefixed(_, ref mut sexpr) => self.rename(sexpr),
+            Comment(_) => {}
+            Compound(ref mut head, ref mut args) => {
+                let is = |names: &[&str]| names.iter().any(|name| head.is_named_as(name, self.aliases));
+                let (quantifier, parallel, sequential) = (is(QUANTIFIER_BINDERS), is(LET_BINDERS), is(SEQUENTIAL_LET_BINDERS));
+                let bindings = match args.first_mut() {
+                    Some(list) if (quantifier || parallel || sequential) && is_binding_list(list) => list,
+                    _ => {
+                        self.rename(head);
+                        for arg in args.iter_mut() {
+                            self.rename(arg);
+                        }
+                        return;
+                    }
+                };
+                let outer = self.scope.len();
+                let mut bound = Vec::new();
+                for binding in binding_list_mut(bindings) {
+                    let (name, rest) = match binding.kind {
+                        Atom(ref mut name) => (name, &mut [][..]),
+                        Compound(ref mut name, ref mut rest) => match name.kind {
+                            Atom(ref mut name) => (name, &mut rest[..]),
+                            _ => continue,
+                        },
+                        _ => continue,
+                    };
+                    // the sorts of a quantifier, and the values of a `let`, are outside of the new scope
+                    // (apart from the earlier names of a `let*`)
+                    if !quantifier {
+                        for value in rest.iter_mut() {
+                            self.rename(value);
+                        }
+                    }
+                    let new = self.fresh();
+                    bound.push((name.to_string(), new.clone()));
+                    if sequential {
+                        self.scope.push((name.to_string(), new.clone()));
+                    }
+                    *name = Cow::Owned(new);
+                }
+                if !sequential {
+                    self.scope.extend(bound);
+                }
+                for body in args[1..].iter_mut() {
+                    self.rename(body);
+                }
+                self.scope.truncate(outer);
+            }
+        }
+    }
+}
+
+/// Whether `list` looks like a list of bindings: names, or compounds starting with names
+fn is_binding_list(list: &Sexpr<'_>) -> bool {
+    let is_binding = |binding: &Sexpr<'_>| match binding.kind {
+        Atom(_) => !binding.is_blank(),
+        Compound(ref name, _) => name.is_atom() && !name.is_blank(),
+        _ => false,
+    };
+    match list.kind {
+        Compound(ref head, ref rest) => is_binding(head) && rest.iter().all(is_binding),
+        _ => false,
+    }
+}
+
+/// The bindings of a binding list, the first of which is its head
+fn binding_list_mut<'s, 'a>(list: &'s mut Sexpr<'a>) -> Vec<&'s mut Sexpr<'a>> {
+    match list.kind {
+        Compound(ref mut head, ref mut rest) => std::iter::once(&mut **head).chain(rest.iter_mut()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether a compound with this head and these arguments binds names, like `(forall ((x Int)) body)`
+pub(crate) fn is_binder(head: &Sexpr<'_>, args: &[Sexpr<'_>], aliases: &[AliasGroup]) -> bool {
+    let binds = [QUANTIFIER_BINDERS, LET_BINDERS, SEQUENTIAL_LET_BINDERS].iter()
+        .any(|names| names.iter().any(|name| head.is_named_as(name, aliases)));
+    binds && args.first().is_some_and(is_binding_list)
+}
+
+/// The names bound by a binding list
+pub(crate) fn bound_names<'s>(list: &'s Sexpr<'_>) -> Vec<&'s str> {
+    let name = |binding: &'s Sexpr<'_>| match binding.kind {
+        Atom(ref name) => Some(&**name),
+        Compound(ref name, _) => match name.kind {
+            Atom(ref name) => Some(&**name),
+            _ => None,
+        },
+        _ => None,
+    };
+    match list.kind {
+        Compound(ref head, ref rest) => std::iter::once(&**head).chain(rest.iter()).filter_map(name).collect(),
+        _ => Vec::new(),
+    }
+}
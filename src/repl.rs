@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use sexpr_fmt::Sexpr;
+
+/// The file the history of the repl is kept in, between sessions
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".sexpr_fmt_history"))
+}
+
+/// Whether `text` is the start of some forms, which would be complete with more lines
+pub fn is_unfinished(text: &str) -> bool {
+    matches!(Sexpr::parse_many(text), Err(e) if e.ends_with("EOI"))
+}
+
+/// Read forms one at a time, printing what `format` makes of each as soon as it is complete,
+/// until the end of the input (ctrl-d)
+/// while the parens of a form are unbalanced, the lines after it are read as more of it
+pub fn repl(format: impl Fn(&str) -> Result<String, String>) -> rustyline::Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let history = history_file();
+    if let Some(history) = history.as_ref() {
+        // there is no history the first time
+        let _ = editor.load_history(history);
+    }
+    let mut input = String::new();
+    let mut done = false;
+    while !done {
+        let prompt = if input.is_empty() { "> " } else { ". " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+                if is_unfinished(&input) {
+                    continue;
+                }
+            }
+            // ctrl-c throws away the form being entered
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            // an unfinished form is still reported at the end
+            Err(ReadlineError::Eof) => done = true,
+            Err(e) => return Err(e),
+        }
+        if !input.trim().is_empty() {
+            editor.add_history_entry(input.trim_end())?;
+            match format(&input) {
+                Ok(formatted) => print!("{}", formatted),
+                Err(e) => eprintln!("error: {}", e),
+            }
+        }
+        input.clear();
+    }
+    if let Some(history) = history.as_ref() {
+        editor.save_history(history)?;
+    }
+    Ok(())
+}
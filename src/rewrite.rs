@@ -0,0 +1,100 @@
+use crate::sexpr::{Sexpr, ParseError};
+use crate::pattern::{Pattern, Match, rest_name};
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+
+/// The most rewrites that may be done before giving up, in case the rules never stop applying
+const MAX_REWRITES: usize = 100_000;
+/// The most times that rules may apply to the results of other rules, one inside the other
+const MAX_NESTED_REWRITES: usize = 500;
+
+/// A rule `pattern => template`, such as `(and $x true) => $x`, whose pattern is a `Pattern`
+/// in the template, `$name` is replaced by the subtree that it matched,
+/// and `$name...` is replaced by all of the arguments that it matched
+#[derive(Debug)]
+pub struct Rule<'a> {
+    pattern: Pattern<'a>,
+    template: Sexpr<'a>,
+}
+
+impl Rule<'_> {
+    /// Read every rule in `input`, which is a sequence of `pattern => template`
+    pub fn parse_all(input: &str) -> Result<Vec<Rule<'_>>, ParseError> {
+        let forms = Sexpr::parse_many(input)?;
+        if forms.len() % 3 != 0 {
+            return Err("malformed rules: expected `pattern => template`");
+        }
+        let mut rules = Vec::new();
+        let mut forms = forms.into_iter();
+        while let (Some(pattern), Some(arrow), Some(template)) = (forms.next(), forms.next(), forms.next()) {
+            if !arrow.is_named("=>") {
+                return Err("malformed rules: expected `=>` between a pattern and a template");
+            }
+            rules.push(Rule { pattern: Pattern::new(pattern), template });
+        }
+        Ok(rules)
+    }
+}
+
+impl<'a> Sexpr<'a> {
+    /// Apply `rules` to this sexpr bottom-up, until none of them match anywhere
+    /// upon a match, the first matching rule is used
+    pub fn rewrite(self, rules: &[Rule<'a>]) -> Result<Sexpr<'a>, String> {
+        let mut budget = MAX_REWRITES;
+        self.rewrite_helper(rules, &mut budget, 0)
+    }
+    /// `nesting` is the number of rule applications whose results we are inside of
+    fn rewrite_helper(self, rules: &[Rule<'a>], budget: &mut usize, nesting: usize) -> Result<Sexpr<'a>, String> {
+        // first rewrite everything below us
+        let sexpr = match self.kind {
+            Atom(_) | Comment(_) => self,
+            Compound(head, args) => {
+                let head = head.rewrite_helper(rules, budget, nesting)?;
+                let args = args.into_iter()
+                    .map(|arg| arg.rewrite_helper(rules, budget, nesting))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Sexpr::compound(head, args)
+            }
+            Prefixed(prefix, sexpr) => Sexpr::prefixed(prefix, sexpr.rewrite_helper(rules, budget, nesting)?),
+        };
+        // then rewrite ourselves, and whatever the template made of us
+        for rule in rules.iter() {
+            if let Some(found) = rule.pattern.bind(&sexpr) {
+                if *budget == 0 {
+                    return Err(format!("rewriting did not finish after {} steps", MAX_REWRITES));
+                }
+                if nesting == MAX_NESTED_REWRITES {
+                    return Err(format!("rewriting did not finish: rules applied to their own results {} times over", MAX_NESTED_REWRITES));
+                }
+                *budget -= 1;
+                let result = rule.template.instantiate(&found);
+                return result.rewrite_helper(rules, budget, nesting + 1);
+            }
+        }
+        Ok(sexpr)
+    }
+    /// Replace each `$name` in this template with what it was bound to
+    fn instantiate(&self, found: &Match<'_, 'a>) -> Sexpr<'a> {
+        match self.kind {
+            Atom(ref name) if name.starts_with('$') => match found.get(&name[1..]) {
+                Some(bound) => bound.clone(),
+                _ => self.clone(),
+            },
+            Atom(_) | Comment(_) => self.clone(),
+            Prefixed(prefix, ref sexpr) => Sexpr::prefixed(prefix, sexpr.instantiate(found)),
+            Compound(ref head, ref args) => {
+                let mut new_args = Vec::new();
+                for arg in args.iter() {
+                    // splice in the arguments bound by `$name...`
+                    if let Some(Some(name)) = rest_name(arg) {
+                        if let Some(bound) = found.get_rest(name) {
+                            new_args.extend(bound.iter().cloned());
+                            continue;
+                        }
+                    }
+                    new_args.push(arg.instantiate(found));
+                }
+                Sexpr::compound(head.instantiate(found), new_args)
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::fmt;
+use serde::ser::{self, Serialize};
+use crate::builder::{SexprBuilder, SexprOwned};
+use crate::sexpr::{Sexpr, FormatArgs};
+
+/// Why a value could not be written as an s-expression, or read from one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error(pub(crate) String);
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+impl std::error::Error for Error {}
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error(msg.to_string())
+    }
+}
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error(msg.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Write `value` as an s-expression
+/// sequences, tuples, and maps become lists, with each entry of a map a list `(key value)`,
+/// a struct `Point { x: 1, y: 2 }` becomes `(Point :x 1 :y 2)`, an enum variant is its name or a list headed by its name,
+/// strings are quoted, `None` is `nil`, and `()` is `()`
+pub fn to_sexpr<T: Serialize + ?Sized>(value: &T) -> Result<SexprOwned> {
+    value.serialize(Serializer)
+}
+
+/// Write `value` as an s-expression, pretty printed with `args`
+pub fn to_string_with<T: Serialize + ?Sized>(value: &T, args: FormatArgs<'_>) -> Result<String> {
+    Ok(to_sexpr(value)?.format(args))
+}
+
+/// Write `value` as an s-expression, pretty printed with the default layout,
+/// keeping the fields of structs on the same line as their names
+///
+/// ```
+/// use serde::{Serialize, Deserialize};
+///
+/// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// struct Pad { name: String, at: (f64, f64), shape: Shape }
+/// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// enum Shape { Circle, Rect { w: f64, h: f64 } }
+///
+/// let pad = Pad { name: "GND 1".to_string(), at: (1.5, -2.0), shape: Shape::Rect { w: 1.0, h: 0.5 } };
+/// let text = sexpr_fmt::ser::to_string(&pad).unwrap();
+/// assert_eq!(sexpr_fmt::de::from_str::<Pad>(&text).unwrap(), pad);
+/// ```
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    let mut args = FormatArgs::new();
+    args.pair_keywords = true;
+    to_string_with(value, args)
+}
+
+/// Quote `text` as a string, escaping its quotes and backslashes
+pub(crate) fn quoted(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for ch in text.chars() {
+        if ch == '"' || ch == '\\' {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out.push('"');
+    out
+}
+
+/// The keyword naming a field, like `:x`
+fn keyword(field: &str) -> SexprOwned {
+    SexprBuilder::atom(format!(":{}", field))
+}
+
+/// Builds the s-expression of a value
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = SexprOwned;
+    type Error = Error;
+    type SerializeSeq = Elements;
+    type SerializeTuple = Elements;
+    type SerializeTupleStruct = Elements;
+    type SerializeTupleVariant = Elements;
+    type SerializeMap = Entries;
+    type SerializeStruct = Elements;
+    type SerializeStructVariant = Elements;
+
+    fn serialize_bool(self, v: bool) -> Result<SexprOwned> {
+        Ok(SexprBuilder::atom(if v { "true" } else { "false" }))
+    }
+    fn serialize_i8(self, v: i8) -> Result<SexprOwned> { self.serialize_i64(v.into()) }
+    fn serialize_i16(self, v: i16) -> Result<SexprOwned> { self.serialize_i64(v.into()) }
+    fn serialize_i32(self, v: i32) -> Result<SexprOwned> { self.serialize_i64(v.into()) }
+    fn serialize_i64(self, v: i64) -> Result<SexprOwned> { Ok(SexprBuilder::atom(v.to_string())) }
+    fn serialize_u8(self, v: u8) -> Result<SexprOwned> { self.serialize_u64(v.into()) }
+    fn serialize_u16(self, v: u16) -> Result<SexprOwned> { self.serialize_u64(v.into()) }
+    fn serialize_u32(self, v: u32) -> Result<SexprOwned> { self.serialize_u64(v.into()) }
+    fn serialize_u64(self, v: u64) -> Result<SexprOwned> { Ok(SexprBuilder::atom(v.to_string())) }
+    fn serialize_f32(self, v: f32) -> Result<SexprOwned> { self.serialize_f64(v.into()) }
+    fn serialize_f64(self, v: f64) -> Result<SexprOwned> {
+        if !v.is_finite() {
+            return Err(Error(format!("{} has no s-expression", v)));
+        }
+        Ok(SexprBuilder::atom(v.to_string()))
+    }
+    fn serialize_char(self, v: char) -> Result<SexprOwned> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+    fn serialize_str(self, v: &str) -> Result<SexprOwned> {
+        Ok(SexprBuilder::atom(quoted(v)))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<SexprOwned> {
+        Ok(SexprBuilder::list(v.iter().map(|byte| SexprBuilder::atom(byte.to_string()))))
+    }
+    fn serialize_none(self) -> Result<SexprOwned> {
+        Ok(SexprBuilder::atom("nil"))
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<SexprOwned> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<SexprOwned> {
+        Ok(SexprBuilder::list(Vec::new()))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<SexprOwned> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<SexprOwned> {
+        Ok(SexprBuilder::atom(variant))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<SexprOwned> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, variant: &'static str, value: &T)
+        -> Result<SexprOwned>
+    {
+        Ok(SexprBuilder::list(vec![SexprBuilder::atom(variant), value.serialize(self)?]))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<Elements> {
+        Ok(Elements(Vec::with_capacity(len.unwrap_or(0))))
+    }
+    fn serialize_tuple(self, len: usize) -> Result<Elements> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Elements> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Elements> {
+        let mut elements = Vec::with_capacity(len + 1);
+        elements.push(SexprBuilder::atom(variant));
+        Ok(Elements(elements))
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<Entries> {
+        Ok(Entries { entries: Vec::with_capacity(len.unwrap_or(0)), key: None })
+    }
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Elements> {
+        let mut elements = Vec::with_capacity(2 * len + 1);
+        elements.push(SexprBuilder::atom(name));
+        Ok(Elements(elements))
+    }
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Elements> {
+        self.serialize_struct(variant, len)
+    }
+}
+
+/// The elements of a list being written, including its head
+pub struct Elements(Vec<SexprOwned>);
+impl Elements {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.0.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.0.push(keyword(key));
+        self.push(value)
+    }
+    fn finish(self) -> Result<SexprOwned> {
+        Ok(SexprBuilder::list(self.0))
+    }
+}
+impl ser::SerializeSeq for Elements {
+    type Ok = SexprOwned;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> { self.push(value) }
+    fn end(self) -> Result<SexprOwned> { self.finish() }
+}
+impl ser::SerializeTuple for Elements {
+    type Ok = SexprOwned;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> { self.push(value) }
+    fn end(self) -> Result<SexprOwned> { self.finish() }
+}
+impl ser::SerializeTupleStruct for Elements {
+    type Ok = SexprOwned;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> { self.push(value) }
+    fn end(self) -> Result<SexprOwned> { self.finish() }
+}
+impl ser::SerializeTupleVariant for Elements {
+    type Ok = SexprOwned;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> { self.push(value) }
+    fn end(self) -> Result<SexprOwned> { self.finish() }
+}
+impl ser::SerializeStruct for Elements {
+    type Ok = SexprOwned;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> { self.field(key, value) }
+    fn end(self) -> Result<SexprOwned> { self.finish() }
+}
+impl ser::SerializeStructVariant for Elements {
+    type Ok = SexprOwned;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> { self.field(key, value) }
+    fn end(self) -> Result<SexprOwned> { self.finish() }
+}
+
+/// The `(key value)` entries of a map being written
+pub struct Entries {
+    entries: Vec<SexprOwned>,
+    key: Option<SexprOwned>,
+}
+impl ser::SerializeMap for Entries {
+    type Ok = SexprOwned;
+    type Error = Error;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(key.serialize(Serializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().ok_or_else(|| Error("a map value was written before its key".to_string()))?;
+        self.entries.push(Sexpr::compound(key, vec![value.serialize(Serializer)?]));
+        Ok(())
+    }
+    fn end(self) -> Result<SexprOwned> {
+        Ok(SexprBuilder::list(self.entries))
+    }
+}
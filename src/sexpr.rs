@@ -1,199 +1,1384 @@
-use std::{fmt, io};
-use crate::sexpr::SexprKind::{Compound, Atom};
-use std::iter::repeat;
-use std::fmt::Formatter;
-use crate::CmdArgs;
+use crate::prelude::*;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+use alloc::borrow::Cow;
+use core::ops::Range;
+use core::str::FromStr;
+use crate::sexpr::SexprKind::{Compound, Atom, Prefixed, Comment};
+use core::fmt::Formatter;
+use crate::aliases::AliasGroup;
+use crate::atom::{AtomPrinter, AtomKind, AtomContext};
+use crate::compat::LayoutVersion;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Sexpr<'a> {
-    kind: SexprKind<'a>,
+    pub(crate) kind: SexprKind<'a>,
     complexity: u32,
+    nodes: u32, // the number of atoms and compounds in this, which `ComplexityMetric::Nodes` measures
+    width: u32, // the width of this when written on one line, which `ComplexityMetric::Width` measures
+    span: Option<Span>, // where this was read from, if it was parsed
+    trailing: bool, // whether this is a line comment which was read on the same line as the form before it
+    brackets: bool, // whether this is a list written in square brackets, like the `[x 1]` of `(let ([x 1]) x)`
 }
-#[derive(Debug)]
-enum SexprKind<'a> {
-    Atom(&'a str),
+/// Sexprs are equal when they have the same structure, wherever they were read from
+impl PartialEq for Sexpr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.complexity == other.complexity && self.brackets == other.brackets
+    }
+}
+/// The byte range of the input that a parsed sexpr was read from
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+impl Span {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SexprKind<'a> {
+    Atom(Cow<'a, str>),
     Compound(Box<Sexpr<'a>>, Vec<Sexpr<'a>>),
+    /// reader sugar like `'(a b)`, `` `(a ,b) ``, `,(f x)`, or `,@xs`, holding the prefix and the form it applies to
+    Prefixed(&'static str, Box<Sexpr<'a>>),
+    /// a block comment like `#| ... |#`, including its markers,
+    /// or a line comment like `; note`, up to the end of its line
+    Comment(Cow<'a, str>),
 }
 pub type ParseError = &'static str;
 
+/// Why some input could not be read, and where
+/// lines and columns start from 1, and columns count characters
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub message: ParseError,
+    /// the byte offset of the input where the form which could not be read starts, or the text after the last form
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    /// the line of the input with the offset in it, and a line with a `^` under the offset
+    pub snippet: String,
+}
+impl SyntaxError {
+    /// The error `message` at byte `offset` of `input`
+    pub fn at(input: &str, offset: usize, message: ParseError) -> SyntaxError {
+        let (line, column) = line_column(input, offset);
+        SyntaxError { message, offset, line, column, snippet: snippet(input, offset) }
+    }
+}
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, col {}", self.message, self.line, self.column)
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for SyntaxError {}
+
+/// The line and column of byte `offset` of `text`, counting from 1
+pub fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before[before.rfind('\n').map_or(0, |i| i + 1)..].chars().count() + 1;
+    (line, column)
+}
+
+/// The line of `text` with byte `offset` in it, and a line under it with a `^` under the offset
+/// the tabs before the offset are kept under it, so that the `^` lines up however wide they are shown
+pub fn snippet(text: &str, offset: usize) -> String {
+    let start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+    let line = text[start..end].trim_end_matches('\r');
+    let pad: String = text[start..offset].chars().map(|ch| if ch == '\t' { '\t' } else { ' ' }).collect();
+    format!("{}\n{}^", line, pad)
+}
+
+/// Why `tail`, the text after a form which should have been the last one, cannot be read
+pub fn trailing_error(tail: &str) -> ParseError {
+    if tail.starts_with(')') {
+        "unexpected `)`"
+    } else if tail.starts_with(']') {
+        "unexpected `]`"
+    } else {
+        "trailing characters after final form"
+    }
+}
+
+/// The node count and one-line width of a sexpr of `kind`, from those of its children
+fn measures(kind: &SexprKind<'_>) -> (u32, u32) {
+    let columns = |text: &str| text.chars().count() as u32;
+    match *kind {
+        Atom(ref text) => (1, columns(text)),
+        Comment(ref text) => (0, columns(text)),
+        Prefixed(prefix, ref sexpr) => (sexpr.nodes, columns(prefix).saturating_add(sexpr.width)),
+        Compound(ref head, ref args) => args.iter().fold((head.nodes + 1, head.width.saturating_add(2)), |(nodes, width), arg| {
+            (nodes.saturating_add(arg.nodes), width.saturating_add(arg.width + 1))
+        }),
+    }
+}
+
+/// The most lists (and prefixes) the reader reads inside one another; anything deeper is a parse error,
+/// since reading it, printing it, and even dropping it recurse once for each level
+///
+/// ```
+/// use sexpr_fmt::Sexpr;
+/// use sexpr_fmt::sexpr::MAX_DEPTH;
+///
+/// let deep = |depth: usize| format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+/// assert!(Sexpr::parse(&deep(MAX_DEPTH)).is_ok());
+/// assert_eq!(Sexpr::parse(&deep(MAX_DEPTH + 1)), Err("malformed sexpr: nested more than 1000 deep"));
+/// assert!(Sexpr::parse(&deep(200_000)).is_err());
+/// ```
+pub const MAX_DEPTH: usize = 1_000;
+
+/// The start of a byte vector literal like `#u8(1 2 255)`, which is read as one atom
+pub const BYTE_VECTOR_PREFIX: &str = "#u8(";
+
+/// The reader prefixes which apply to the form right after them,
+/// longest first so that `,@` is not read as `,`
+pub const PREFIXES: &[&str] = &[",@", "'", "`", ","];
+
+impl<'a> Sexpr<'a> {
+    /// A sexpr of `kind`, with its measures other than the complexity worked out from those of its children
+    fn measured(kind: SexprKind<'a>, complexity: u32) -> Sexpr<'a> {
+        let (nodes, width) = measures(&kind);
+        Sexpr { kind, complexity, nodes, width, span: None, trailing: false, brackets: false }
+    }
+    /// Create an atom with the given text
+    pub fn atom(text: impl Into<Cow<'a, str>>) -> Sexpr<'a> {
+        let kind = Atom(text.into());
+        let complexity = 0; // the complexity of an atom is zero
+        Sexpr::measured(kind, complexity)
+    }
+    /// Create the compound expression `(head args...)`
+    pub fn compound(head: Sexpr<'a>, args: Vec<Sexpr<'a>>) -> Sexpr<'a> {
+        let complexity = args.iter()
+            .map(|sexpr| sexpr.complexity)
+            .fold(head.complexity, core::cmp::max) + 1;
+        let kind = Compound(Box::new(head), args);
+        Sexpr::measured(kind, complexity)
+    }
+    /// This list written in square brackets, like `[x 1]`, if `brackets` is true, or else in parens
+    pub fn with_brackets(mut self, brackets: bool) -> Sexpr<'a> {
+        self.brackets = brackets && matches!(self.kind, Compound(..));
+        self
+    }
+    /// Create a comment with the given text, which includes its markers
+    pub fn comment(text: impl Into<Cow<'a, str>>) -> Sexpr<'a> {
+        let kind = Comment(text.into());
+        let complexity = 0; // comments are printed as they are, so they don't add any complexity
+        Sexpr::measured(kind, complexity)
+    }
+    /// Copy the text this borrows from the input it was read from, so that it can outlive the input
+    pub fn into_owned(self) -> Sexpr<'static> {
+        let kind = match self.kind {
+            Atom(text) => Atom(Cow::Owned(text.into_owned())),
+            Comment(text) => Comment(Cow::Owned(text.into_owned())),
+            Prefixed(prefix, sexpr) => Prefixed(prefix, Box::new(sexpr.into_owned())),
+            Compound(head, args) => Compound(Box::new(head.into_owned()), args.into_iter().map(Sexpr::into_owned).collect()),
+        };
+        Sexpr { kind, complexity: self.complexity, nodes: self.nodes, width: self.width, span: self.span, trailing: self.trailing, brackets: self.brackets }
+    }
+    /// A copy of this sexpr which borrows its text from this one, rather than copying it
+    pub fn borrowed(&self) -> Sexpr<'_> {
+        let kind = match self.kind {
+            Atom(ref text) => Atom(Cow::Borrowed(text)),
+            Comment(ref text) => Comment(Cow::Borrowed(text)),
+            Prefixed(prefix, ref sexpr) => Prefixed(prefix, Box::new(sexpr.borrowed())),
+            Compound(ref head, ref args) => Compound(Box::new(head.borrowed()), args.iter().map(Sexpr::borrowed).collect()),
+        };
+        Sexpr { kind, complexity: self.complexity, nodes: self.nodes, width: self.width, span: self.span, trailing: self.trailing, brackets: self.brackets }
+    }
+    /// A copy of this sexpr, read from some text, which borrows from `text` instead,
+    /// where everything it was read from is the same but moved `shift` bytes along
+    /// the text of a node is copied if it is not found where it should be in `text`, such as when it was not read at all
+    pub(crate) fn rebased<'n>(&self, text: &'n str, shift: isize) -> Sexpr<'n> {
+        let span = self.span.map(|span| Span {
+            start: span.start.wrapping_add_signed(shift),
+            end: span.end.wrapping_add_signed(shift),
+        });
+        let moved = |old: &Cow<'_, str>| -> Cow<'n, str> {
+            let found = span.and_then(|span| text.get(span.start..span.start + old.len())).filter(|new| *new == &**old);
+            match found {
+                Some(new) => Cow::Borrowed(new),
+                None => Cow::Owned(old.to_string()),
+            }
+        };
+        let kind = match self.kind {
+            Atom(ref old) => Atom(moved(old)),
+            Comment(ref old) => Comment(moved(old)),
+            Prefixed(prefix, ref sexpr) => Prefixed(prefix, Box::new(sexpr.rebased(text, shift))),
+            Compound(ref head, ref args) => Compound(Box::new(head.rebased(text, shift)), args.iter().map(|arg| arg.rebased(text, shift)).collect()),
+        };
+        Sexpr { kind, complexity: self.complexity, nodes: self.nodes, width: self.width, span, trailing: self.trailing, brackets: self.brackets }
+    }
+    /// Create the sugared form `prefix` `sexpr`, such as `'(a b)`
+    /// the prefix must be one of `PREFIXES`
+    pub fn prefixed(prefix: &'static str, sexpr: Sexpr<'a>) -> Sexpr<'a> {
+        let complexity = sexpr.complexity; // the prefix does not add any nesting
+        let kind = Prefixed(prefix, Box::new(sexpr));
+        Sexpr::measured(kind, complexity)
+    }
+}
+
 impl Sexpr<'_> {
     /// Attempt to create an s expression from the given input
     pub fn parse(input: &str) -> Result<Sexpr<'_>, ParseError> {
-        let (sexpr, tail) = Sexpr::parse_helper(input)?;
+        let (sexpr, tail) = Sexpr::parse_first(input)?;
         if !tail.is_empty() {
-            return Err("unclosed sexpr");
+            return Err(trailing_error(tail));
         }
         Ok(sexpr)
     }
-    fn parse_helper(input: &str) -> Result<(Sexpr<'_>, &'_ str), ParseError> {
-        let input = input.trim();
-        if input.is_empty() {
-            return Ok((Sexpr::blank(), ""))
-        }
-        let (head, remaining) = input.split_at(1);
-        let (kind, complexity, remaining) = if head == "(" {
-            // a compound expression
-            // get the first expr, which is at the same depth as us
-            let (first, mut remaining) = Sexpr::parse_helper(remaining)?;
-            // get the remaining exprs, which are one level below
-            let mut args = Vec::new(); // collect args here
-            let mut complexity = first.complexity; // find maximum complexity
-            while !remaining.is_empty() {
-       //         println!("in loop, remaining = \"{}\"", remaining);
-                let (sexpr, tail) = Sexpr::parse_helper(remaining)?;
-                if sexpr.is_blank() { break; }
-                complexity = std::cmp::max(complexity, sexpr.complexity);
-                remaining = tail;
-                args.push(sexpr);
-            }
-            // println!("finished reading args, remaining = `{}`", remaining);
-            remaining = remaining.trim();
-            if remaining.is_empty() {
-                return Err("malformed sexpr: expected `)`, found EOI");
-            }
-            let (end_paren, remaining) = remaining.split_at(1);
-            if end_paren != ")" {
-                return Err("malformed sexpr: expected `)`, found something else");
-            }
-            // println!("finished compound, sloughed off `{}`, remaining = `{}`", end_paren, remaining);
-            (Compound(Box::new(first), args), complexity + 1, remaining)
-        } else if head.is_empty() {
-            return Err("unexpected end of input");
-        } else {
-            // parse an atomic expression by going through the input
-            // until we hit a whitespace
-            let mut idx= 0;
-            while idx < input.len() && is_ident(&input[idx..idx+1]) {
-                idx += 1;
-            }
-            let (item, remaining) = input.split_at(idx);
-            let complexity = 0; // the complexity of an atom is zero
-            (Atom(item), complexity, remaining)
-        };
-        let sexpr = Sexpr { kind, complexity };
-        // println!("parsed: {:?}, remaining: \"{}\"", sexpr, remaining);
-        Ok((sexpr, remaining))
+    /// Read the one s expression of the given input, like `parse`, but saying where reading went wrong
+    ///
+    /// ```
+    /// use sexpr_fmt::Sexpr;
+    ///
+    /// let error = Sexpr::parse_located("(a b)\n  (c d))").unwrap_err();
+    /// assert_eq!(error.to_string(), "trailing characters after final form at line 2, col 3");
+    /// assert_eq!(error.snippet, "  (c d))\n  ^");
+    /// ```
+    pub fn parse_located(input: &str) -> Result<Sexpr<'_>, SyntaxError> {
+        let start = input.len() - input.trim_start().len();
+        let (sexpr, tail) = Sexpr::parse_first(input).map_err(|error| SyntaxError::at(input, start, error))?;
+        if !tail.is_empty() {
+            let offset = tail.as_ptr() as usize - input.as_ptr() as usize;
+            return Err(SyntaxError::at(input, offset, trailing_error(tail)));
+        }
+        Ok(sexpr)
+    }
+    /// Attempt to read the first s expression in the given input, returning it along with the rest of the input
+    pub fn parse_first(input: &str) -> Result<(Sexpr<'_>, &str), ParseError> {
+        let (sexpr, tail) = Sexpr::parse_helper(input, input)?;
+        Ok((sexpr, tail.trim()))
+    }
+    /// Attempt to read every top-level s expression in the given input
+    pub fn parse_many(input: &str) -> Result<Vec<Sexpr<'_>>, ParseError> {
+        match Sexpr::parse_many_partial(input) {
+            (sexprs, None) => Ok(sexprs),
+            (_, Some((_, error))) => Err(error),
+        }
+    }
+    /// Read every top-level s expression of the given input, like `parse_many`,
+    /// but saying where the first one which cannot be read starts
+    ///
+    /// ```
+    /// use sexpr_fmt::Sexpr;
+    ///
+    /// let error = Sexpr::parse_many_located("(a b)\n(c d))").unwrap_err();
+    /// assert_eq!(error.to_string(), "unexpected `)` at line 2, col 6");
+    /// assert_eq!((error.offset, error.snippet.as_str()), (11, "(c d))\n     ^"));
+    /// ```
+    pub fn parse_many_located(input: &str) -> Result<Vec<Sexpr<'_>>, SyntaxError> {
+        match Sexpr::parse_many_partial(input) {
+            (sexprs, None) => Ok(sexprs),
+            (_, Some((rest, error))) => Err(SyntaxError::at(input, input.len() - rest.len(), error)),
+        }
+    }
+    /// Attempt to read every top-level s expression in the given bytes, which must be utf-8
+    pub fn parse_bytes(input: &[u8]) -> Result<Vec<Sexpr<'_>>, ParseError> {
+        let input = core::str::from_utf8(input).map_err(|_| "input is not valid utf-8")?;
+        Sexpr::parse_many(input)
+    }
+    /// Read the top-level s expressions at the start of the input, stopping at the first one which cannot be read
+    /// gives the rest of the input from there, and why it could not be read, if any of the input is left
+    pub fn parse_many_partial(input: &str) -> (Vec<Sexpr<'_>>, Option<(&str, ParseError)>) {
+        let mut sexprs = Vec::new();
+        let mut remaining = input.trim();
+        // the rest of the input is given as it is, up to its very end
+        let rest = |remaining: &str| &input[remaining.as_ptr() as usize - input.as_ptr() as usize..];
+        while !remaining.is_empty() {
+            let (sexpr, tail) = match Sexpr::parse_helper(remaining, input) {
+                Ok((sexpr, _)) if sexpr.is_blank() => return (sexprs, Some((rest(remaining), trailing_error(remaining)))),
+                Ok(parsed) => parsed,
+                Err(error) => return (sexprs, Some((rest(remaining), error))),
+            };
+            sexprs.push(sexpr);
+            remaining = tail.trim();
+        }
+        (sexprs, None)
+    }
+    /// Read every top-level s expression in the given input, like `parse_many_partial`,
+    /// but keep each form which cannot be read as an opaque atom, holding its text up to where its parens balance
+    /// gives the spans of the opaque atoms too, and why they could not be read, for warning about
+    pub fn parse_many_opaque(input: &str) -> (Vec<Sexpr<'_>>, Vec<(Span, ParseError)>) {
+        let mut sexprs = Vec::new();
+        let mut opaque = Vec::new();
+        let mut remaining = input.trim();
+        while !remaining.is_empty() {
+            let error = match Sexpr::parse_helper(remaining, input) {
+                Ok((sexpr, _)) if sexpr.is_blank() => trailing_error(remaining),
+                // the reader stopped somewhere else than where the parens balance, so it misread something like a string with a `)`
+                Ok((_, tail)) if remaining.len() - tail.len() != balanced_len(remaining) => "unknown reader syntax",
+                Ok((sexpr, tail)) => {
+                    sexprs.push(sexpr);
+                    remaining = tail.trim();
+                    continue;
+                }
+                Err(error) => error,
+            };
+            let (text, tail) = remaining.split_at(balanced_len(remaining));
+            let start = remaining.as_ptr() as usize - input.as_ptr() as usize;
+            let span = Span { start, end: start + text.len() };
+            let mut atom = Sexpr::atom(text);
+            atom.span = Some(span);
+            sexprs.push(atom);
+            opaque.push((span, error));
+            remaining = tail.trim();
+        }
+        (sexprs, opaque)
+    }
+    /// Parse the sexpr at the start of `input`, which is a slice of `base`, recording where in `base` it was read from
+    pub(crate) fn parse_helper<'s>(input: &'s str, base: &str) -> Result<(Sexpr<'s>, &'s str), ParseError> {
+        let (sexpr, remaining) = read(&mut Sexprs, input, base)?;
+        Ok((sexpr.unwrap_or_else(|| Sexpr::atom("")), remaining))
+    }
+    /// Record that this was read from `span` of `base`
+    pub(crate) fn set_span(&mut self, base: &str, span: Span) {
+        self.span = Some(span);
+        if self.is_line_comment() {
+            // the comment trails the form before it if nothing but spaces come between them
+            let before = &base[..span.start];
+            let code = before.trim_end();
+            self.trailing = !code.is_empty() && !before[code.len()..].contains('\n');
+        }
     }
     pub fn blank() -> Sexpr<'static> {
-        let kind = Atom("");
+        let kind = Atom("".into());
         let complexity = 0;
-        Sexpr{ kind, complexity }
+        Sexpr::measured(kind, complexity)
+    }
+    /// Where this was read from, if it was parsed rather than built or rewritten
+    pub fn span(&self) -> Option<Span> {
+        self.span
     }
     pub fn is_named(&self, text: &str) -> bool {
+        matches!(self.kind, Atom(ref name) if name == text)
+    }
+    /// The nesting depth of this sexpr, which decides whether it fits on one line
+    pub fn complexity(&self) -> u32 {
+        self.complexity
+    }
+    /// The number of atoms and compounds in this sexpr, counting itself
+    pub fn nodes(&self) -> u32 {
+        self.nodes
+    }
+    /// The width of this sexpr when it is written on one line
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    /// The complexity of this sexpr by `metric`, which is compared with the complexity threshold
+    pub fn measure(&self, metric: ComplexityMetric) -> u32 {
+        match metric {
+            ComplexityMetric::Depth => self.complexity,
+            ComplexityMetric::Nodes => self.nodes,
+            ComplexityMetric::Width => self.width,
+        }
+    }
+    /// Work out the node count and width of this sexpr and everything inside of it again, after some of their text was changed
+    #[cfg(feature = "std")]
+    pub(crate) fn remeasure_all(&mut self) {
         match self.kind {
-            Atom(name) if name == text => true,
-            _ => false
+            Atom(_) | Comment(_) => {}
+            Prefixed(_, ref mut sexpr) => sexpr.remeasure_all(),
+            Compound(ref mut head, ref mut args) => {
+                head.remeasure_all();
+                args.iter_mut().for_each(Sexpr::remeasure_all);
+            }
+        }
+        self.remeasure();
+    }
+    /// Work out the measures of this sexpr again from those of its children, after it or they were changed
+    pub(crate) fn remeasure(&mut self) {
+        self.complexity = match self.kind {
+            Atom(_) | Comment(_) => 0,
+            Prefixed(_, ref sexpr) => sexpr.complexity,
+            Compound(ref head, ref args) => args.iter().map(|sexpr| sexpr.complexity).fold(head.complexity, core::cmp::max) + 1,
+        };
+        let (nodes, width) = measures(&self.kind);
+        self.nodes = nodes;
+        self.width = width;
+    }
+    pub fn is_atom(&self) -> bool {
+        matches!(self.kind, Atom(_))
+    }
+    pub fn is_comment(&self) -> bool {
+        matches!(self.kind, Comment(_))
+    }
+    /// Whether this is a comment like `; note`, which runs to the end of its line
+    pub fn is_line_comment(&self) -> bool {
+        matches!(self.kind, Comment(ref text) if text.starts_with(';'))
+    }
+    /// Whether this is a line comment which was read after another form on the same line, like `(f x) ; note`
+    pub fn is_trailing_comment(&self) -> bool {
+        self.trailing
+    }
+    /// Whether this is a list written in square brackets, like `[x 1]`, rather than in parens
+    pub fn is_bracketed(&self) -> bool {
+        self.brackets
+    }
+    /// Whether a line comment is somewhere inside of this, which forces it to be broken over several lines
+    pub fn has_line_comment(&self) -> bool {
+        match self.kind {
+            Atom(_) => false,
+            Comment(_) => self.is_line_comment(),
+            Prefixed(_, ref sexpr) => sexpr.has_line_comment(),
+            Compound(ref head, ref args) => head.has_line_comment() || args.iter().any(Sexpr::has_line_comment),
         }
     }
     pub fn is_blank(&self) -> bool {
-        if let Atom(text) = self.kind {
+        if let Atom(ref text) = self.kind {
             text.is_empty()
         } else {
             false
         }
     }
-    pub fn pretty_print(&self, cmd_args: &CmdArgs) -> fmt::Result {
-        let fmt_args = FormatArgs::from(cmd_args);
-        let mut f = ToWriteFmt(io::stdout());
-        self.write_helper(&mut f, fmt_args)
+    /// Writes this sexpr to stdout, using the specified FormatArgs
+    #[cfg(feature = "std")]
+    pub fn pretty_print(&self, fmt_args: FormatArgs<'_>) -> fmt::Result {
+        self.write_to(io::stdout().lock(), fmt_args).map_err(|_| fmt::Error)
+    }
+    /// Writes this sexpr to `writer`, using the specified FormatArgs
+    /// unlike `pretty_print`, a failure to write comes back as the `io::Error` it was, such as a broken pipe
+    ///
+    /// ```
+    /// use sexpr_fmt::{Sexpr, FormatArgs};
+    ///
+    /// let sexpr = Sexpr::parse("(f x)").unwrap();
+    /// let mut out = Vec::new();
+    /// sexpr.write_to(&mut out, FormatArgs::new()).unwrap();
+    /// assert_eq!(out, b"(f x)");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn write_to<W: io::Write>(&self, writer: W, fmt_args: FormatArgs<'_>) -> io::Result<()> {
+        let mut f = ToWriteFmt { writer: io::BufWriter::new(writer), error: None };
+        if self.write_helper(&mut f, fmt_args, &mut Vec::new()).is_err() {
+            return Err(f.error.unwrap_or_else(|| io::Error::other("the sexpr could not be formatted")));
+        }
+        f.writer.flush()
+    }
+    /// Writes this sexpr to `sink`, using the specified FormatArgs
+    /// this is all the printer needs, so it works without `std`, for example to write into a fixed buffer
+    pub fn format_to<W: fmt::Write>(&self, sink: &mut W, fmt_args: FormatArgs<'_>) -> fmt::Result {
+        self.write_helper(&mut Sink(sink), fmt_args, &mut Vec::new())
+    }
+    /// This sexpr as something to `Display`, using the specified FormatArgs instead of the defaults,
+    /// so that it can be written with `format!` or a logger without a string of its own
+    ///
+    /// ```
+    /// use sexpr_fmt::{Sexpr, FormatArgs};
+    ///
+    /// let sexpr = Sexpr::parse("(assert (> x 0))").unwrap();
+    /// let mut args = FormatArgs::new();
+    /// args.complexity_threshold = 2;
+    /// assert_eq!(format!("checking {}", sexpr.display_with(args)), "checking (assert (> x 0))");
+    /// ```
+    pub fn display_with<'f>(&self, fmt_args: FormatArgs<'f>) -> DisplayWith<'_, 'f> {
+        DisplayWith { sexpr: self, fmt_args }
+    }
+    /// Formats this sexpr into a string, using the specified FormatArgs
+    pub fn format(&self, fmt_args: FormatArgs<'_>) -> String {
+        let mut out = String::new();
+        self.write_helper(&mut out, fmt_args, &mut Vec::new())
+            .expect("writing to a string does not fail");
+        out
     }
     /// Writes this sexpr to `f`, using the specified FormatArgs
     /// prints the head of this sexpr immediately, but each subsequent newline
     /// has `depth` spaces preceding any text
-    fn write_helper<W>(&self, f: &mut W, args: FormatArgs) -> fmt::Result
-        where W: fmt::Write
+    /// `path` holds the indices leading from the root to this sexpr, where the head of a compound is index 0
+    pub(crate) fn write_helper<W>(&self, f: &mut W, args: FormatArgs<'_>, path: &mut Vec<usize>) -> fmt::Result
+        where W: SexprWrite
     {
-        let tab = args.tab();
+        f.node_start(path);
         match self.kind {
-            Atom(text) => write!(f, "{}", text)?,
+            Atom(ref text) => {
+                let text = match args.atom_printer {
+                    Some(printer) => {
+                        let context = AtomContext { path, depth: args.depth };
+                        printer.print_atom(text, AtomKind::of(text), &context)
+                    }
+                    None => Cow::Borrowed(&**text),
+                };
+                match args.max_width {
+                    Some(width) if args.hard_wrap => write_hard_wrapped(f, &text, args, width)?,
+                    _ => f.write_str(&text)?,
+                }
+            }
+            Comment(ref text) if args.reflow_comments && text.starts_with("#|") => {
+                write!(f, "{}", reflow_block_comment(text, &args.tab(), args.comment_width))?;
+            }
+            // the text is kept as it is, except for tabs in the indentation, which editors show differently
+            Comment(ref text) if text.contains('\t') => write!(f, "{}", crate::lint::expand_leading_tabs(text, args.tab_width))?,
+            Comment(ref text) => write!(f, "{}", text)?,
+            Prefixed(prefix, ref sexpr) => {
+                write!(f, "{}", prefix)?;
+                let mut args = args;
+                if prefix == "`" {
+                    args.quasiquote_depth += 1;
+                } else if prefix.starts_with(',') && args.quasiquote_depth > 0 {
+                    args.quasiquote_depth -= 1;
+                    if args.inline_unquoted {
+                        // keep an unquoted fragment of a template on one line
+                        args.complexity_threshold = u32::MAX;
+                    }
+                }
+                args.column += prefix.len();
+                path.push(1);
+                sexpr.write_helper(f, args, path)?;
+                path.pop();
+            }
             Compound(ref head, ref subformulas) => {
-                let (new_depth, sep, line_prefix) =
-                    if self.complexity <= args.complexity_threshold {
-                        // inlined: do print any tabs on subsequent lines and separate with ' ', followed by no spaces
-                        (0, " ", "")
-                    } else {
-                        // multiline: increment the depth,
-                        //     and separate with a newline and a tab, (this indents them relative to us)
-                        //     followed by the proper number of spaces (this preserves our indentation relative to our caller)
-                        (args.depth + 4, "\n    ", tab.as_str())
-                    };
-                write!(f, "({}", head)?;
-                let mut subformula_iter = subformulas.iter();
-                if args.short_quantifiers && head.is_named("forall") || head.is_named("exists") {
-                    if let Some(sexpr) = subformula_iter.next() {
-                        // if the command line option is set, and our head is an atom `forall` or `exists`,
+                // the widths are for our own arguments, not for anything nested inside of them
+                let columns = args.column_widths;
+                let (binder_width, is_binding_list) = (args.binder_width, args.is_binding_list);
+                let trailing_width = args.trailing_width;
+                let args = FormatArgs { column_widths: &[], binder_width: 0, is_binding_list: false, trailing_width: 0, ..args };
+                // with a maximum width, a form which would not fit on the rest of the line is broken up too
+                let too_wide = args.max_width
+                    .is_some_and(|width| args.column + self.width as usize + trailing_width > width);
+                // a binding whose value fits on one line is kept on one line with its name
+                let short_binding = binder_width > 0 && subformulas.len() == 1 && subformulas[0].measure(args.complexity_metric) <= args.complexity_threshold;
+                let named_in = |names: &[String]| names.iter().any(|name| head.is_named_as(name, args.aliases));
+                let never_break = named_in(args.never_break);
+                // nothing can follow a line comment on its line, so a form with one inside of it is always broken up
+                let multiline = if self.has_line_comment() {
+                    true
+                } else if never_break {
+                    false
+                } else {
+                    named_in(args.always_break) || (self.measure(args.complexity_metric) > args.complexity_threshold && !short_binding) || too_wide
+                };
+                let tables = if multiline && args.align_numbers {
+                    crate::numbers::number_columns(subformulas, args.complexity_threshold, args.complexity_metric)
+                } else {
+                    Vec::new()
+                };
+                // inlined: do not indent subsequent lines and separate with ' '
+                // multiline: increment the depth, and separate with a newline indented relative to us
+                let new_depth = if multiline { args.depth + 4 } else { 0 };
+                f.open_group(path, multiline);
+                f.write_str(if self.brackets { "[" } else { "(" })?;
+                // the head stays on our line, so it is indented like we are
+                // everything inside of a form that fits on one line fits too, so the width need not be checked again
+                let inline_args = match (multiline, never_break) {
+                    (true, _) => args,
+                    // the forms chosen to never be broken up are kept on one line with everything inside of them
+                    (false, true) => FormatArgs { max_width: None, complexity_threshold: u32::MAX, always_break: &[], ..args },
+                    (false, false) => FormatArgs { max_width: None, ..args },
+                };
+                // the values of a binding list broken over several lines are lined up, by padding the names
+                let bindings_width = if is_binding_list && multiline { binder_names_width(head, subformulas) } else { 0 };
+                // with `CommentStyle::AttachTrailing`, a comment trailing a form has to fit on the line after it
+                let trailing_width_of = |next: Option<&Sexpr<'_>>| match next {
+                    Some(next) if next.trailing && args.comment_style == CommentStyle::AttachTrailing => 1 + next.to_compact_string().chars().count(),
+                    _ => 0,
+                };
+                let own_line = args.comment_style == CommentStyle::OwnLine;
+                path.push(0);
+                let head_args = FormatArgs { column: args.column + 1, binder_width: bindings_width, trailing_width: trailing_width_of(subformulas.first()), ..inline_args };
+                head.write_helper(f, head_args, path)?;
+                path.pop();
+                // the column after the keyword or binder name written just before, when it is paired with the next argument
+                let mut paired_column = None;
+                if binder_width > 0 && !subformulas.is_empty() {
+                    for _ in head.to_compact_string().chars().count()..binder_width {
+                        f.write_char(' ')?;
+                    }
+                    paired_column = Some(args.column + 2 + binder_width);
+                }
+                let binds = args.align_bindings && BINDING_FORMS.iter().any(|name| head.is_named_as(name, args.aliases));
+                // the attributes of an annotation like `(! term :named t :pattern ((f x)))` are paired with their keywords
+                // under the term, with the values lined up after the widest keyword
+                let annotates = args.layout_version >= LayoutVersion::V0_3 && head.is_named_as(ANNOTATION_HEAD, args.aliases);
+                let keywords_width = if annotates && multiline {
+                    subformulas.iter().skip(1).filter(|sexpr| is_keyword(sexpr)).map(|sexpr| sexpr.to_compact_string().chars().count()).max().unwrap_or(0)
+                } else {
+                    0
+                };
+                let mut subformula_iter = subformulas.iter().enumerate();
+                let inline_first_arg = match args.layout_version {
+                    // this grouping is a mistake, but kept so that files formatted by early versions stay the same
+                    LayoutVersion::V0_1 => args.short_quantifiers && head.is_named_as("forall", args.aliases)
+                        || head.is_named_as("exists", args.aliases),
+                    _ => args.short_quantifiers && QUANTIFIERS.iter().any(|name| head.is_named_as(name, args.aliases)),
+                } || annotates || args.inline_first_arg_of.iter().any(|name| head.is_named_as(name, args.aliases));
+                if inline_first_arg {
+                    if let Some((i, sexpr)) = subformula_iter.next() {
+                        // if our head is one of the forms chosen on the command line, like `forall` or `lambda`,
                         // then the first subformula is written on the same line
-                        write!(f, " ")?;
-                        sexpr.write_helper(f, args)?;
+                        f.soft_break()?;
+                        let column = args.column + 2 + head.to_compact_string().chars().count();
+                        // an annotated term is indented like the attributes after it, so that its end is not mistaken for ours
+                        let depth = if annotates { new_depth } else { args.depth };
+                        path.push(i + 1);
+                        sexpr.write_helper(f, FormatArgs { column, depth, ..inline_args }, path)?;
+                        path.pop();
                     }
                 }
-                for sexpr in subformula_iter {
-                    write!(f, "{}{}", sep, line_prefix)?;
-                    sexpr.write_helper(f, args.with_depth(new_depth))?;
+                // the trailing comment which was already written above the form it follows
+                let mut moved = None;
+                for (i, sexpr) in subformula_iter {
+                    if moved == Some(i) {
+                        continue;
+                    }
+                    let next = subformulas.get(i + 1);
+                    // a trailing comment stays on the line of the form it follows, unless it is moved above it
+                    let stays_trailing = sexpr.trailing && !own_line;
+                    if multiline && paired_column.is_none() && !stays_trailing {
+                        f.line_break(new_depth)?;
+                    } else {
+                        f.soft_break()?;
+                    }
+                    if own_line && !sexpr.is_comment() && next.is_some_and(|next| next.trailing) {
+                        path.push(i + 2);
+                        next.expect("the next argument is a comment").write_helper(f, inline_args.with_depth(new_depth), path)?;
+                        path.pop();
+                        f.line_break(new_depth)?;
+                        moved = Some(i + 1);
+                    }
+                    // the first of our columns which belongs to this argument, when we are a row of a table
+                    let cell: usize = if columns.is_empty() { 0 } else { subformulas[..i].iter().map(crate::numbers::row_cells).sum() };
+                    match (&sexpr.kind, columns.get(cell)) {
+                        (Atom(ref text), Some(&width)) if crate::numbers::NumberKind::of(text).is_some() => {
+                            let len = text.chars().count();
+                            for _ in len..width {
+                                f.write_char(' ')?;
+                            }
+                        }
+                        _ => {}
+                    }
+                    let mut child_args = inline_args.with_depth(new_depth);
+                    if let Some(Some(widths)) = tables.get(i) {
+                        child_args.column_widths = widths;
+                    } else if let Compound(..) = sexpr.kind {
+                        // a row nested in a row of a table is lined up with the same rows in the rows beside it
+                        child_args.column_widths = columns.get(cell..cell + crate::numbers::row_cells(sexpr)).unwrap_or(&[]);
+                    }
+                    if let Some(column) = paired_column.take() {
+                        child_args.column = column;
+                    }
+                    child_args.binder_width = bindings_width;
+                    child_args.is_binding_list = binds && i == 0;
+                    child_args.trailing_width = trailing_width_of(next);
+                    // the value of an attribute, like the list of terms after `:pattern`, is kept on one line
+                    if annotates && i > 0 && is_keyword(&subformulas[i - 1]) {
+                        child_args.complexity_threshold = u32::MAX;
+                        child_args.always_break = &[];
+                    }
+                    path.push(i + 1);
+                    sexpr.write_helper(f, child_args, path)?;
+                    path.pop();
+                    // keep a keyword like `:key` on the same line as the value after it
+                    if multiline && (args.pair_keywords || annotates) && is_keyword(sexpr) && next.is_some_and(|next| !next.is_comment() && !is_keyword(next)) {
+                        let len = sexpr.to_compact_string().chars().count();
+                        for _ in len..keywords_width {
+                            f.write_char(' ')?;
+                        }
+                        paired_column = Some(new_depth + 1 + len.max(keywords_width));
+                    }
                 }
                 // we put the closing `)` on a new line only if we're in multiline mode
-                if self.complexity > args.complexity_threshold {
-                    write!(f, "\n{}", tab)?;
+                if multiline {
+                    f.line_break(args.depth)?;
                 }
-                write!(f, ")")?; // finish with the closing paren
+                f.write_str(if self.brackets { "]" } else { ")" })?; // finish with the closing paren
+                f.close_group();
             }
         }
         Ok(())
     }
 }
+/// How the complexity of a sexpr, which decides whether it fits on one line, is measured
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComplexityMetric {
+    /// the nesting depth, so that a flat list is simple however long it is
+    Depth,
+    /// the number of atoms and compounds
+    Nodes,
+    /// the width when written on one line
+    Width,
+}
+impl FromStr for ComplexityMetric {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ComplexityMetric, String> {
+        match s {
+            "depth" => Ok(ComplexityMetric::Depth),
+            "nodes" => Ok(ComplexityMetric::Nodes),
+            "width" => Ok(ComplexityMetric::Width),
+            _ => Err(format!("unknown complexity metric `{}`, expected one of: depth, nodes, width", s)),
+        }
+    }
+}
+impl fmt::Display for ComplexityMetric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ComplexityMetric::Depth => write!(f, "depth"),
+            ComplexityMetric::Nodes => write!(f, "nodes"),
+            ComplexityMetric::Width => write!(f, "width"),
+        }
+    }
+}
+
+/// Where a line comment which trails a form, like `(f x) ; note`, is written
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// trailing comments stay trailing, and comments on their own line stay on their own line
+    Preserve,
+    /// like `Preserve`, but a trailing comment counts toward the width of the line of the form it follows,
+    /// so that the form is broken up if the comment would not fit after it
+    AttachTrailing,
+    /// every line comment is written on its own line, and a trailing comment is moved above the form it follows
+    OwnLine,
+}
+impl FromStr for CommentStyle {
+    type Err = String;
+    fn from_str(s: &str) -> Result<CommentStyle, String> {
+        match s {
+            "preserve" => Ok(CommentStyle::Preserve),
+            "attach-trailing" => Ok(CommentStyle::AttachTrailing),
+            "own-line" => Ok(CommentStyle::OwnLine),
+            _ => Err(format!("unknown comment style `{}`, expected one of: preserve, attach-trailing, own-line", s)),
+        }
+    }
+}
+impl fmt::Display for CommentStyle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CommentStyle::Preserve => write!(f, "preserve"),
+            CommentStyle::AttachTrailing => write!(f, "attach-trailing"),
+            CommentStyle::OwnLine => write!(f, "own-line"),
+        }
+    }
+}
+
 /// Contains all of the arguments needed in the calculations of `Sexpr::write_helper`
-#[derive(Copy, Clone, Debug)]
-struct FormatArgs {
+/// start from `FormatArgs::new()` and set the public fields to change the layout
+#[derive(Copy, Clone)]
+pub struct FormatArgs<'a> {
     depth: usize, // the current nesting depth of the printing
-    complexity_threshold: u32, // the maximum complexity to print a sexpr on a single line
-    short_quantifiers: bool,
+    pub complexity_threshold: u32, // the maximum complexity to print a sexpr on a single line
+    pub complexity_metric: ComplexityMetric, // how the complexity compared with the threshold is measured
+    pub short_quantifiers: bool, // keep the first argument of `forall` and `exists` on the head's line
+    pub inline_first_arg_of: &'a [String], // keep the first argument on the head's line for these heads too
+    pub always_break: &'a [String], // break forms with these heads over several lines, however simple they are
+    pub never_break: &'a [String], // keep forms with these heads on one line, however complex they are
+    pub aliases: &'a [AliasGroup], // heads which are treated the same by the head-specific rules
+    quasiquote_depth: usize, // how many quasiquotes we are inside of, less the unquotes
+    pub inline_unquoted: bool, // print the unquoted fragments of quasiquoted templates on one line
+    pub reflow_comments: bool, // re-wrap the prose of block comments
+    pub comment_width: usize, // the width to wrap block comments to
+    pub tab_width: usize, // the number of columns a tab in the input stands for
+    pub atom_printer: Option<&'a dyn AtomPrinter>, // renders each atom, instead of writing its text
+    pub layout_version: LayoutVersion, // the version of the layout algorithm to follow
+    pub align_numbers: bool, // right-align the numbers in the columns of tables of rows like `(at 1.0 2.54)`
+    column_widths: &'a [usize], // the widths to right-align the numbers of the compound being written to, nested rows included
+    pub max_width: Option<usize>, // the widest a line may be, breaking up forms that would be wider
+    pub hard_wrap: bool, // split atoms which do not fit the maximum width over several lines
+    pub continuation_marker: &'a str, // ends each line of an atom split by `hard_wrap`
+    column: usize, // the column that the sexpr being written starts at
+    pub pair_keywords: bool, // keep keywords like `:key` on the same line as the value after them
+    pub align_bindings: bool, // line up the values of the binding lists of forms like `let`
+    is_binding_list: bool, // whether the sexpr being written is the binding list of a form like `let`
+    binder_width: usize, // the width to pad the name of the binding being written to, or 0
+    pub comment_style: CommentStyle, // where the line comments which trail a form are written
+    trailing_width: usize, // the width of the trailing comment written after the sexpr being written, if it counts
+}
+impl fmt::Debug for FormatArgs<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FormatArgs")
+            .field("depth", &self.depth)
+            .field("complexity_threshold", &self.complexity_threshold)
+            .field("complexity_metric", &self.complexity_metric)
+            .field("short_quantifiers", &self.short_quantifiers)
+            .field("inline_first_arg_of", &self.inline_first_arg_of)
+            .field("always_break", &self.always_break)
+            .field("never_break", &self.never_break)
+            .field("aliases", &self.aliases)
+            .field("quasiquote_depth", &self.quasiquote_depth)
+            .field("inline_unquoted", &self.inline_unquoted)
+            .field("reflow_comments", &self.reflow_comments)
+            .field("comment_width", &self.comment_width)
+            .field("tab_width", &self.tab_width)
+            .field("atom_printer", &self.atom_printer.map(|_| ".."))
+            .field("layout_version", &self.layout_version)
+            .field("align_numbers", &self.align_numbers)
+            .field("column_widths", &self.column_widths)
+            .field("max_width", &self.max_width)
+            .field("hard_wrap", &self.hard_wrap)
+            .field("continuation_marker", &self.continuation_marker)
+            .field("column", &self.column)
+            .field("pair_keywords", &self.pair_keywords)
+            .field("align_bindings", &self.align_bindings)
+            .field("is_binding_list", &self.is_binding_list)
+            .field("binder_width", &self.binder_width)
+            .field("comment_style", &self.comment_style)
+            .field("trailing_width", &self.trailing_width)
+            .finish()
+    }
 }
-impl FormatArgs {
+impl Default for FormatArgs<'static> {
+    fn default() -> FormatArgs<'static> {
+        FormatArgs::new()
+    }
+}
+impl<'a> FormatArgs<'a> {
     /// create the default formatting arguments
-    fn new() -> FormatArgs {
+    pub fn new() -> FormatArgs<'static> {
         FormatArgs {
             depth: 0,
             complexity_threshold: 1,
+            complexity_metric: ComplexityMetric::Depth,
             short_quantifiers: false,
+            inline_first_arg_of: &[],
+            always_break: &[],
+            never_break: &[],
+            aliases: &[],
+            quasiquote_depth: 0,
+            inline_unquoted: false,
+            reflow_comments: false,
+            comment_width: 80,
+            tab_width: 8,
+            atom_printer: None,
+            layout_version: LayoutVersion::CURRENT,
+            align_numbers: false,
+            column_widths: &[],
+            max_width: None,
+            hard_wrap: false,
+            continuation_marker: DEFAULT_CONTINUATION_MARKER,
+            column: 0,
+            pair_keywords: false,
+            align_bindings: false,
+            is_binding_list: false,
+            binder_width: 0,
+            comment_style: CommentStyle::Preserve,
+            trailing_width: 0,
         }
     }
-    fn from(cmd_args: &CmdArgs) -> FormatArgs {
-        FormatArgs {
-            depth: 0,
-            complexity_threshold: cmd_args.complexity_threshold(),
-            short_quantifiers: cmd_args.short_quantifiers(),
+    fn with_depth(&self, new_depth: usize) -> FormatArgs<'a> {
+        // a sexpr at a new depth starts its own line
+        FormatArgs { depth: new_depth, column: new_depth, ..*self }
+    }
+    /// The spaces which indent the current depth
+    /// this borrows from a shared buffer instead of allocating, unless the nesting is very deep
+    fn tab(&self) -> Cow<'static, str> {
+        match SPACES.get(..self.depth) {
+            Some(spaces) => Cow::Borrowed(spaces),
+            None => Cow::Owned(" ".repeat(self.depth)),
         }
     }
-    fn with_depth(&self, new_depth: usize) -> FormatArgs {
-        FormatArgs {
-            depth: new_depth,
-            complexity_threshold: self.complexity_threshold,
-            short_quantifiers: self.short_quantifiers,
+}
+
+/// Enough spaces for the indentation of all but the most deeply nested expressions
+const SPACES: &str = concat!(
+    "                                                                                                                                ",
+    "                                                                                                                                ",
+);
+
+/// What the reader builds out of the forms it reads, like `Sexpr`s, or the `Node`s of a `SymbolTable`
+pub(crate) trait Build<'s> {
+    type Output;
+    fn atom(&mut self, text: &'s str) -> Self::Output;
+    fn comment(&mut self, text: &'s str) -> Self::Output;
+    fn prefixed(&mut self, prefix: &'static str, form: Self::Output) -> Self::Output;
+    fn list(&mut self, head: Self::Output, args: Vec<Self::Output>, brackets: bool) -> Self::Output;
+    /// Record that `form` was read from `span` of `base`
+    fn located(&mut self, _form: &mut Self::Output, _base: &str, _span: Span) {}
+}
+
+/// Builds the `Sexpr`s the reader reads, borrowing their text from the input
+struct Sexprs;
+impl<'s> Build<'s> for Sexprs {
+    type Output = Sexpr<'s>;
+    fn atom(&mut self, text: &'s str) -> Sexpr<'s> {
+        Sexpr::atom(text)
+    }
+    fn comment(&mut self, text: &'s str) -> Sexpr<'s> {
+        Sexpr::comment(text)
+    }
+    fn prefixed(&mut self, prefix: &'static str, form: Sexpr<'s>) -> Sexpr<'s> {
+        Sexpr::prefixed(prefix, form)
+    }
+    fn list(&mut self, head: Sexpr<'s>, args: Vec<Sexpr<'s>>, brackets: bool) -> Sexpr<'s> {
+        Sexpr::compound(head, args).with_brackets(brackets)
+    }
+    fn located(&mut self, form: &mut Sexpr<'s>, base: &str, span: Span) {
+        form.set_span(base, span);
+    }
+}
+
+/// Read the form at the start of `input`, which is a slice of `base`, with `builder`, giving the rest of the input after it
+/// gives None if nothing is left of the input, or if it starts with the end of a list
+pub(crate) fn read<'s, B: Build<'s>>(builder: &mut B, input: &'s str, base: &str) -> Result<(Option<B::Output>, &'s str), ParseError> {
+    read_nested(builder, input, base, 0)
+}
+/// Like `read`, for a form inside of `depth` lists or prefixes
+fn read_nested<'s, B: Build<'s>>(builder: &mut B, input: &'s str, base: &str, depth: usize) -> Result<(Option<B::Output>, &'s str), ParseError> {
+    let input = input.trim_start();
+    let (form, remaining) = read_node(builder, input, base, depth)?;
+    let offset = |slice: &str| slice.as_ptr() as usize - base.as_ptr() as usize;
+    let span = Span { start: offset(input), end: offset(remaining) };
+    Ok((form.map(|mut form| { builder.located(&mut form, base, span); form }), remaining))
+}
+/// Read the form at the very start of `input`, like `read`
+fn read_node<'s, B: Build<'s>>(builder: &mut B, input: &'s str, base: &str, depth: usize) -> Result<(Option<B::Output>, &'s str), ParseError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok((None, input))
+    }
+    if input.starts_with("#|") {
+        let len = block_comment_len(input).ok_or("unclosed block comment: expected `|#`, found EOI")?;
+        let (text, remaining) = input.split_at(len);
+        return Ok((Some(builder.comment(text)), remaining));
+    }
+    if input.starts_with(';') {
+        let (text, remaining) = input.split_at(input.find('\n').unwrap_or(input.len()));
+        return Ok((Some(builder.comment(text.trim_end())), remaining));
+    }
+    // a prefix like `'` or `,@` applies to the compound right after it
+    // (a prefix before an atom, like `'foo`, is just part of the atom)
+    let prefix = PREFIXES.iter().find(|prefix| {
+        input.strip_prefix(**prefix).is_some_and(|rest| rest.starts_with(['(', '[']) || PREFIXES.iter().any(|p| rest.starts_with(p)))
+    });
+    let brackets = input.starts_with('[');
+    let nested = prefix.is_some() || input.starts_with('(') || brackets;
+    if nested && depth == MAX_DEPTH {
+        return Err("malformed sexpr: nested more than 1000 deep");
+    }
+    if let Some(prefix) = prefix {
+        let (form, remaining) = read_nested(builder, &input[prefix.len()..], base, depth + 1)?;
+        let form = form.unwrap_or_else(|| builder.atom(""));
+        return Ok((Some(builder.prefixed(prefix, form)), remaining));
+    }
+    if let Some(remaining) = input.strip_prefix(['(', '[']) {
+        // a compound expression, in parens or in square brackets
+        // get the first expr, which is at the same depth as us
+        let (first, mut remaining) = read_nested(builder, remaining, base, depth + 1)?;
+        // the head of `()` is the empty atom, read from right before the `)`
+        let first = match first {
+            Some(first) => first,
+            None => {
+                let mut blank = builder.atom("");
+                let at = remaining.as_ptr() as usize - base.as_ptr() as usize;
+                builder.located(&mut blank, base, Span { start: at, end: at });
+                blank
+            }
+        };
+        // get the remaining exprs, which are one level below
+        let mut args = Vec::new(); // collect args here
+        while !remaining.is_empty() {
+   //         println!("in loop, remaining = \"{}\"", remaining);
+            let (form, tail) = read_nested(builder, remaining, base, depth + 1)?;
+            let form = match form { Some(form) => form, None => break };
+            remaining = tail;
+            args.push(form);
+        }
+        // println!("finished reading args, remaining = `{}`", remaining);
+        remaining = remaining.trim();
+        let remaining = match (brackets, remaining.chars().next()) {
+            (false, Some(')')) | (true, Some(']')) => &remaining[1..],
+            (false, None) => return Err("malformed sexpr: expected `)`, found EOI"),
+            (true, None) => return Err("malformed sexpr: expected `]`, found EOI"),
+            (false, Some(_)) => return Err("malformed sexpr: expected `)`, found something else"),
+            (true, Some(_)) => return Err("malformed sexpr: expected `]`, found something else"),
+        };
+        Ok((Some(builder.list(first, args, brackets)), remaining))
+    } else {
+        // parse an atomic expression by going through the input until we hit a whitespace or a paren
+        // a string like `"a (b) c"` is read up to its closing quote, spaces and parens and all,
+        // and a byte vector like `#u8(1 2 255)` up to its closing paren, since it is a literal rather than a list
+        let start = if input.starts_with('"') {
+            string_len(input)?
+        } else if input.starts_with(BYTE_VECTOR_PREFIX) {
+            input.find(')').ok_or("unclosed byte vector: expected `)`, found EOI")? + 1
+        } else {
+            0
+        };
+        let mut idx = start;
+        // a `|` quotes the text up to the next one, spaces and parens and all, like the `|a key|` of SMT-LIB,
+        // or the `:|a key|` of a keyword
+        loop {
+            let len = ident_len(&input[idx..]);
+            match memchr::memchr(b'|', &input.as_bytes()[idx..idx + len]) {
+                Some(bar) => idx += bar + quoted_symbol_len(&input[idx + bar..])?,
+                None => break idx += len,
+            }
+        }
+        let (item, remaining) = input.split_at(idx);
+        // nothing is read at the `)` or `]` which ends a list
+        Ok((if item.is_empty() { None } else { Some(builder.atom(item)) }, remaining))
+    }
+}
+
+/// The length of the block comment at the start of `input`, up to and including its closing `|#`
+/// block comments nest, so `#| a #| b |# c |#` is a single comment
+pub(crate) fn block_comment_len(input: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut idx = 0;
+    while idx < input.len() {
+        if input[idx..].starts_with("#|") {
+            depth += 1;
+            idx += 2;
+        } else if input[idx..].starts_with("|#") {
+            depth -= 1;
+            idx += 2;
+            if depth == 0 {
+                return Some(idx);
+            }
+        } else {
+            idx += input[idx..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    None
+}
+
+/// The length of the string literal at the start of `input`, up to and including its closing quote
+/// a backslash escapes the character after it, so `"a \" b"` is a single string
+fn string_len(input: &str) -> Result<usize, ParseError> {
+    let bytes = input.as_bytes();
+    let mut idx = 1;
+    // the quotes and backslashes are found in bulk, since they are ascii and so never part of another character
+    while let Some(found) = memchr::memchr2(b'"', b'\\', &bytes[idx..]) {
+        idx += found;
+        if bytes[idx] == b'"' {
+            return Ok(idx + 1);
+        }
+        // skip the backslash and the character it escapes, which may be several bytes long
+        idx += 1 + input[idx + 1..].chars().next().map_or(0, char::len_utf8);
+        if idx >= bytes.len() {
+            break;
+        }
+    }
+    Err("unclosed string: expected `\"`, found EOI")
+}
+
+/// The length of the quoted part of a symbol at the start of `input`, like `|a key|`, up to and including its closing bar
+/// a backslash escapes the character after it, so `|a \| b|` is a single symbol
+fn quoted_symbol_len(input: &str) -> Result<usize, ParseError> {
+    let bytes = input.as_bytes();
+    let mut idx = 1;
+    while let Some(found) = memchr::memchr2(b'|', b'\\', &bytes[idx..]) {
+        idx += found;
+        if bytes[idx] == b'|' {
+            return Ok(idx + 1);
+        }
+        idx += 1 + input[idx + 1..].chars().next().map_or(0, char::len_utf8);
+        if idx >= bytes.len() {
+            break;
+        }
+    }
+    Err("unclosed symbol: expected `|`, found EOI")
+}
+
+/// The length of the text at the start of `input` up to where its parens balance, or up to whitespace if it does not start a list
+/// strings, `|symbols|`, and comments are skipped over, since the parens inside of them would throw the count off
+/// a list may come after a reader prefix like `'`, `#`, or `#u8`, but not after an atom
+/// this is never zero for input that is not empty, even if it starts with a stray `)`
+fn balanced_len(input: &str) -> usize {
+    let mut depth = 0usize;
+    let mut chars = input.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        match ch {
+            '(' | '[' if depth == 0 && idx > 0 && !input.starts_with('#') && !input[..idx].chars().all(|c| "'`,@".contains(c)) => return idx,
+            '(' | '[' => depth += 1,
+            ')' | ']' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return idx + 1;
+                }
+            }
+            '#' if input[idx..].starts_with("#|") => {
+                let end = match block_comment_len(&input[idx..]) {
+                    Some(len) => idx + len,
+                    None => return input.len(),
+                };
+                if depth == 0 {
+                    return end;
+                }
+                while chars.next_if(|&(i, _)| i < end).is_some() {}
+            }
+            '"' | '|' => {
+                while let Some((_, next)) = chars.next() {
+                    if next == ch {
+                        break;
+                    } else if next == '\\' {
+                        chars.next();
+                    }
+                }
+            }
+            ';' => while chars.next_if(|&(_, next)| next != '\n').is_some() {},
+            _ if ch.is_whitespace() && depth == 0 => return idx,
+            _ => {}
         }
     }
-    fn tab(&self) -> String {
-        repeat(' ').take(self.depth).collect()
+    input.len()
+}
+
+/// The marker ending each line of an atom split across lines by `FormatArgs::hard_wrap`
+pub const DEFAULT_CONTINUATION_MARKER: &str = "#|+|#";
+
+/// Write `text`, splitting it over several lines so that none are wider than `width`,
+/// ending all but the last of them with the continuation marker
+/// the lines after the first are indented like the atom's own line
+/// only plain symbols and numbers are split: strings, byte vectors, `|symbols|` and the like are written whole,
+/// past the width, since the whitespace inside them could not be told apart from the indentation of a split
+fn write_hard_wrapped<W: SexprWrite>(f: &mut W, text: &str, args: FormatArgs<'_>, width: usize) -> fmt::Result {
+    if text.contains(|ch: char| ch.is_whitespace() || matches!(ch, '"' | '|' | '#' | '\\' | ';')) {
+        return f.write_str(text);
+    }
+    let marker_len = args.continuation_marker.chars().count();
+    let mut rest = text;
+    let mut column = args.column;
+    while column + rest.chars().count() > width {
+        // always take at least one character, even when nothing fits, so that this finishes
+        let room = width.saturating_sub(column + marker_len).max(1);
+        let split = rest.char_indices().nth(room).map_or(rest.len(), |(idx, _)| idx);
+        if split == rest.len() { break; }
+        f.write_str(&rest[..split])?;
+        f.write_str(args.continuation_marker)?;
+        f.line_break(args.depth)?;
+        rest = &rest[split..];
+        column = args.depth;
     }
+    f.write_str(rest)
 }
 
-fn is_ident(s: &str) -> bool {
-    s.chars().all(|ch| ch != '(' && ch != ')' && !ch.is_whitespace())
+/// Undo the splitting of atoms by `FormatArgs::hard_wrap`, joining each line ending in `marker` to the next
+/// without the indentation of the next line, which is never part of the atom, since only atoms without whitespace are split
+pub fn join_continuations<'t>(text: &'t str, marker: &str) -> Cow<'t, str> {
+    if marker.is_empty() || !text.contains(marker) {
+        return Cow::Borrowed(text);
+    }
+    let mut joined = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find(marker) {
+        let after = &rest[idx + marker.len()..];
+        match after.strip_prefix("\r\n").or_else(|| after.strip_prefix('\n')) {
+            Some(next_line) => {
+                joined.push_str(&rest[..idx]);
+                rest = next_line.trim_start_matches([' ', '\t']);
+            }
+            None => {
+                joined.push_str(&rest[..idx + marker.len()]);
+                rest = after;
+            }
+        }
+    }
+    joined.push_str(rest);
+    Cow::Owned(joined)
 }
 
-// a wrapper struct to enable things that implement io::Write to be passed to write_helper
-struct ToWriteFmt<T>(T);
+/// Re-wrap the prose of the block comment `text` to fit within `width` columns, when indented by `tab`
+/// paragraphs (separated by blank lines) are kept apart, and nested `#|` and `|#` markers are kept as words
+/// if the closing `|#` was on its own line, it stays on its own line
+fn reflow_block_comment(text: &str, tab: &str, width: usize) -> String {
+    let inner = &text[2..text.len() - 2];
+    let closing_on_own_line = inner.trim_end_matches([' ', '\t']).ends_with('\n');
+    let continuation = format!("{}   ", tab); // lines up with the text after `#| `
+    let available = width.saturating_sub(continuation.len()).max(1);
+    let mut out = String::from("#|");
+    let mut paragraphs = Vec::new();
+    let mut paragraph = Vec::new();
+    for line in inner.lines() {
+        if line.trim().is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(core::mem::take(&mut paragraph));
+            }
+        } else {
+            paragraph.extend(line.split_whitespace());
+        }
+    }
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+    for (i, words) in paragraphs.iter().enumerate() {
+        if i > 0 {
+            out.push_str("\n\n");
+            out.push_str(&continuation);
+        } else {
+            out.push(' ');
+        }
+        let mut line_len = 0;
+        for (j, word) in words.iter().enumerate() {
+            let word_len = word.chars().count(); // in columns, not bytes
+            if j > 0 && line_len + 1 + word_len > available {
+                out.push('\n');
+                out.push_str(&continuation);
+                line_len = 0;
+            } else if j > 0 {
+                out.push(' ');
+                line_len += 1;
+            }
+            out.push_str(word);
+            line_len += word_len;
+        }
+    }
+    if closing_on_own_line {
+        out.push('\n');
+        out.push_str(tab);
+        out.push_str("|#");
+    } else {
+        out.push_str(" |#");
+    }
+    out
+}
+
+/// The heads that `FormatArgs::short_quantifiers` applies to
+pub const QUANTIFIERS: &[&str] = &["forall", "exists"];
+
+/// The head of an SMT-LIB annotated term, like `(! (f x) :named fx)`
+pub const ANNOTATION_HEAD: &str = "!";
+
+/// The forms whose first argument is a list of bindings like `((x 1) (y 2))`
+pub const BINDING_FORMS: &[&str] = &["let", "let*", "parameterize"];
+
+/// The width of the widest name among the bindings `(name value)` of a binding list, ignoring any comments,
+/// or 0 if some element is not a binding
+fn binder_names_width(first: &Sexpr<'_>, rest: &[Sexpr<'_>]) -> usize {
+    let mut width = 0;
+    for binding in core::iter::once(first).chain(rest.iter()).filter(|sexpr| !sexpr.is_comment()) {
+        match binding.kind {
+            Compound(ref name, ref values) if name.is_atom() && !values.is_empty() => {
+                width = core::cmp::max(width, name.to_compact_string().chars().count());
+            }
+            _ => return 0,
+        }
+    }
+    width
+}
 
-impl<'a, T> fmt::Write for ToWriteFmt<T> where T: io::Write
+/// Whether `sexpr` is a keyword atom, like `:key`
+fn is_keyword(sexpr: &Sexpr<'_>) -> bool {
+    matches!(sexpr.kind, Atom(ref text) if AtomKind::of(text) == AtomKind::Keyword)
+}
+
+fn is_ident(ch: char) -> bool {
+    !matches!(ch, '(' | ')' | '[' | ']') && !ch.is_whitespace()
+}
+
+/// The bytes which end an atom, or may end one: ascii whitespace, the parens and brackets of lists,
+/// and the bytes of characters which are not ascii, which are looked at as characters to see if they are whitespace
+const ATOM_STOPS: [bool; 256] = {
+    let mut stops = [false; 256];
+    let mut b = 0;
+    while b < 256 {
+        stops[b] = b >= 0x80 || matches!(b as u8, b' ' | b'\t' | b'\n' | b'\r' | b'\x0b' | b'\x0c' | b'(' | b')' | b'[' | b']');
+        b += 1;
+    }
+    stops
+};
+
+/// The length of the atom at the start of `input`, up to the first character which is not `is_ident`
+/// the bytes are looked at once each, in a table of the ones which end an atom,
+/// and only a character which is not ascii is decoded, to see whether it is whitespace
+///
+/// ```
+/// use sexpr_fmt::sexpr::ident_len;
+///
+/// assert_eq!(ident_len("foo bar"), 3);
+/// assert_eq!(ident_len("x[1]"), 1);
+/// // non-ascii whitespace, like the ideographic space, ends an atom too
+/// assert_eq!(ident_len("naïve\u{3000}x"), 6);
+/// ```
+pub fn ident_len(input: &str) -> usize {
+    let bytes = input.as_bytes();
+    let mut idx = 0;
+    loop {
+        match bytes[idx..].iter().position(|&b| ATOM_STOPS[b as usize]) {
+            None => return bytes.len(),
+            Some(i) if bytes[idx + i].is_ascii() => return idx + i,
+            Some(i) => {
+                idx += i;
+                let ch = input[idx..].chars().next().expect("a character starts at a byte which is not ascii");
+                if !is_ident(ch) {
+                    return idx;
+                }
+                idx += ch.len_utf8();
+            }
+        }
+    }
+}
+
+/// A destination that `Sexpr::write_helper` can write to
+/// it is told where each node starts, so that it may keep track of them if wanted
+pub(crate) trait SexprWrite: fmt::Write {
+    /// called just before the node at `path` is written
+    fn node_start(&mut self, _path: &[usize]) {}
+    /// called just before the `(` of the compound at `path`
+    fn open_group(&mut self, _path: &[usize], _multiline: bool) {}
+    /// called just after the matching `)`
+    fn close_group(&mut self) {}
+    /// separates two elements of a group that is kept on one line
+    fn soft_break(&mut self) -> fmt::Result {
+        self.write_str(" ")
+    }
+    /// starts a new line, indented by `indent` columns
+    fn line_break(&mut self, indent: usize) -> fmt::Result {
+        self.write_str("\n")?;
+        match SPACES.get(..indent) {
+            Some(spaces) => self.write_str(spaces),
+            None => self.write_str(&" ".repeat(indent)),
+        }
+    }
+}
+impl SexprWrite for Formatter<'_> {}
+impl SexprWrite for String {}
+#[cfg(feature = "std")]
+impl<T> SexprWrite for ToWriteFmt<T> where T: io::Write {}
+
+// a wrapper struct to enable things that implement io::Write to be passed to write_helper,
+// keeping the io::Error that fmt::Error has no room for
+#[cfg(feature = "std")]
+struct ToWriteFmt<T> {
+    writer: T,
+    error: Option<io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl<T> fmt::Write for ToWriteFmt<T> where T: io::Write
 {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.0.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+        self.writer.write_all(s.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            fmt::Error
+        })
+    }
+}
+
+// a wrapper struct to enable any fmt::Write to be passed to write_helper, with the plain layout
+struct Sink<'w, W>(&'w mut W);
+
+impl<W: fmt::Write> fmt::Write for Sink<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s)
+    }
+}
+impl<W: fmt::Write> SexprWrite for Sink<'_, W> {}
+
+/// A sexpr displayed with chosen FormatArgs, from `Sexpr::display_with`
+#[derive(Copy, Clone, Debug)]
+pub struct DisplayWith<'s, 'f> {
+    sexpr: &'s Sexpr<'s>,
+    fmt_args: FormatArgs<'f>,
+}
+impl fmt::Display for DisplayWith<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.sexpr.write_helper(f, self.fmt_args, &mut Vec::new())
     }
 }
 
 impl <'a> fmt::Display for Sexpr<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let fmt_args = FormatArgs::new();
-        self.write_helper(f, fmt_args)?;
+        self.write_helper(f, fmt_args, &mut Vec::new())?;
         Ok(())
     }
 }
@@ -0,0 +1,208 @@
+use std::collections::{HashMap, HashSet};
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+use crate::aliases::AliasGroup;
+use crate::rename;
+
+impl<'a> Sexpr<'a> {
+    /// Bind each subterm of at least `min_nodes` nodes which appears more than once to a name like `t0`,
+    /// largest first, and replace its appearances with the name
+    /// the bindings are put in `let`s around the smallest subtree holding all of the replaced appearances,
+    /// nested so that each binding comes after the ones it uses
+    /// subterms mentioning a bound variable, heads, quoted forms, and binding lists are left alone,
+    /// so the meaning of the sexpr does not change
+    pub fn share_common_subterms(&mut self, min_nodes: usize, aliases: &[AliasGroup]) {
+        let mut taken = HashSet::new();
+        self.collect_names(&mut taken);
+        let mut bound = HashSet::new();
+        collect_bound(self, aliases, &mut bound);
+        let mut next = 0;
+        let mut bindings: Vec<(String, Sexpr<'a>)> = Vec::new();
+        let mut sites: Vec<Vec<usize>> = Vec::new();
+        loop {
+            // count the appearances of every subterm which could be shared, remembering which was seen first
+            let mut counts: HashMap<String, (usize, usize, &Sexpr<'a>)> = HashMap::new();
+            let mut seen = 0;
+            let mut visit = |term| count(term, min_nodes, &bound, &mut counts, &mut seen);
+            for_each_shareable(self, aliases, &mut visit);
+            for (_, value) in bindings.iter() {
+                for_each_shareable(value, aliases, &mut visit);
+            }
+            let candidates = counts.into_values()
+                .filter(|(n, _, _)| *n > 1)
+                .map(|(_, first, term)| (term.node_count(), first, term.clone()));
+            let target = match candidates.max_by(|(a, i, _), (b, j, _)| a.cmp(b).then(j.cmp(i))) {
+                Some((_, _, target)) => target,
+                None => break,
+            };
+            let name = loop {
+                let name = format!("t{}", next);
+                next += 1;
+                if !taken.contains(&name) {
+                    break name;
+                }
+            };
+            *self = replaced(self, &target, &name, aliases, &mut Vec::new(), &mut sites);
+            for (_, value) in bindings.iter_mut() {
+                *value = replaced(value, &target, &name, aliases, &mut Vec::new(), &mut Vec::new());
+            }
+            bindings.push((name, target));
+        }
+        if bindings.is_empty() {
+            return;
+        }
+        // each binding goes in the `let` after the ones binding the names it uses
+        // (no subterm contains itself, so every binding eventually has its level)
+        let uses: Vec<Vec<&str>> = bindings.iter()
+            .map(|(_, value)| {
+                let mut used = HashSet::new();
+                value.collect_names(&mut used);
+                bindings.iter().map(|(other, _)| &**other).filter(|other| used.contains(*other)).collect()
+            })
+            .collect();
+        let mut levels: HashMap<String, usize> = HashMap::new();
+        while levels.len() < bindings.len() {
+            for ((name, _), uses) in bindings.iter().zip(uses.iter()) {
+                if levels.contains_key(name) || !uses.iter().all(|other| levels.contains_key(*other)) {
+                    continue;
+                }
+                let level = uses.iter().map(|other| levels[*other] + 1).max().unwrap_or(0);
+                levels.insert(name.clone(), level);
+            }
+        }
+        let common = sites.iter()
+            .fold(None, |common: Option<&[usize]>, site| Some(match common {
+                None => site,
+                Some(common) => {
+                    let len = common.iter().zip(site.iter()).take_while(|(a, b)| a == b).count();
+                    &common[..len]
+                }
+            }))
+            .unwrap_or(&[])
+            .to_vec();
+        let deepest = levels.values().copied().max().unwrap_or(0);
+        *self = rebuilt_at(self, &common, &mut |body| {
+            (0..=deepest).rev().fold(body.clone(), |body, level| {
+                let list: Vec<Sexpr<'a>> = bindings.iter()
+                    .filter(|(name, _)| levels[name] == level)
+                    .map(|(name, value)| Sexpr::compound(Sexpr::atom(name.clone()), vec![value.clone()]))
+                    .collect();
+                let mut list = list.into_iter();
+                let first = list.next().expect("every level has a binding");
+                Sexpr::compound(Sexpr::atom("let"), vec![Sexpr::compound(first, list.collect()), body])
+            })
+        });
+    }
+}
+
+/// Count an appearance of `term`, if it is big enough to share and can be moved out of where it is
+/// the counts are kept by the text of the term, along with the number of terms seen before it first appeared
+fn count<'s, 'a>(term: &'s Sexpr<'a>, min_nodes: usize, bound: &HashSet<String>,
+                 counts: &mut HashMap<String, (usize, usize, &'s Sexpr<'a>)>, seen: &mut usize) {
+    if term.node_count() < min_nodes || mentions(term, bound) || has_comments(term) {
+        return;
+    }
+    counts.entry(term.to_compact_string()).or_insert((0, *seen, term)).0 += 1;
+    *seen += 1;
+}
+
+/// Call `visit` on every compound below `sexpr` which may be replaced by a name:
+/// not heads, not the insides of quoted forms, and not the binding lists of binders
+fn for_each_shareable<'s, 'a>(sexpr: &'s Sexpr<'a>, aliases: &[AliasGroup], visit: &mut impl FnMut(&'s Sexpr<'a>)) {
+    if let Compound(ref head, ref args) = sexpr.kind {
+        let binder = rename::is_binder(head, args, aliases);
+        for (i, arg) in args.iter().enumerate() {
+            if binder && i == 0 {
+                continue;
+            }
+            if let Compound(..) = arg.kind {
+                visit(arg);
+            }
+            for_each_shareable(arg, aliases, visit);
+        }
+    }
+}
+
+/// `sexpr` with the shareable appearances of `target` replaced by the atom `name`,
+/// recording the path of each replaced appearance in `sites`
+fn replaced<'a>(sexpr: &Sexpr<'a>, target: &Sexpr<'a>, name: &str, aliases: &[AliasGroup],
+                path: &mut Vec<usize>, sites: &mut Vec<Vec<usize>>) -> Sexpr<'a> {
+    match sexpr.kind {
+        Compound(ref head, ref args) => {
+            let binder = rename::is_binder(head, args, aliases);
+            let args = args.iter()
+                .enumerate()
+                .map(|(i, arg)| {
+                    path.push(i + 1);
+                    let arg = if binder && i == 0 {
+                        arg.clone()
+                    } else if arg == target {
+                        sites.push(path.clone());
+                        Sexpr::atom(name.to_string())
+                    } else {
+                        replaced(arg, target, name, aliases, path, sites)
+                    };
+                    path.pop();
+                    arg
+                })
+                .collect();
+            Sexpr::compound((**head).clone(), args)
+        }
+        Atom(_) | Prefixed(..) | Comment(_) => sexpr.clone(),
+    }
+}
+
+/// `sexpr` with the subtree at `path` replaced by `f` of it
+fn rebuilt_at<'a>(sexpr: &Sexpr<'a>, path: &[usize], f: &mut dyn FnMut(&Sexpr<'a>) -> Sexpr<'a>) -> Sexpr<'a> {
+    let (&index, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return f(sexpr),
+    };
+    match sexpr.kind {
+        Compound(ref head, ref args) if index == 0 => Sexpr::compound(rebuilt_at(head, rest, f), args.clone()),
+        Compound(ref head, ref args) => {
+            let mut args = args.clone();
+            args[index - 1] = rebuilt_at(&args[index - 1], rest, f);
+            Sexpr::compound((**head).clone(), args)
+        }
+        Prefixed(prefix, ref inner) => Sexpr::prefixed(prefix, rebuilt_at(inner, rest, f)),
+        Atom(_) | Comment(_) => sexpr.clone(),
+    }
+}
+
+/// Collect every name bound by a binder in `sexpr`
+fn collect_bound(sexpr: &Sexpr<'_>, aliases: &[AliasGroup], bound: &mut HashSet<String>) {
+    match sexpr.kind {
+        Compound(ref head, ref args) => {
+            if rename::is_binder(head, args, aliases) {
+                bound.extend(rename::bound_names(&args[0]).into_iter().map(str::to_string));
+            }
+            collect_bound(head, aliases, bound);
+            for arg in args.iter() {
+                collect_bound(arg, aliases, bound);
+            }
+        }
+        Prefixed(_, ref inner) => collect_bound(inner, aliases, bound),
+        Atom(_) | Comment(_) => {}
+    }
+}
+
+/// Whether any atom of `sexpr` is one of `names`
+fn mentions(sexpr: &Sexpr<'_>, names: &HashSet<String>) -> bool {
+    match sexpr.kind {
+        Atom(ref text) => names.contains(&**text),
+        Compound(ref head, ref args) => mentions(head, names) || args.iter().any(|arg| mentions(arg, names)),
+        Prefixed(_, ref inner) => mentions(inner, names),
+        Comment(_) => false,
+    }
+}
+
+/// Whether there is a comment anywhere in `sexpr`
+fn has_comments(sexpr: &Sexpr<'_>) -> bool {
+    match sexpr.kind {
+        Comment(_) => true,
+        Compound(ref head, ref args) => has_comments(head) || args.iter().any(has_comments),
+        Prefixed(_, ref inner) => has_comments(inner),
+        Atom(_) => false,
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+
+/// The size and shape of one sexpr
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub atoms: usize,
+    pub compounds: usize,
+    pub comments: usize,
+    /// the most compounds nested inside each other, so zero for an atom
+    pub max_depth: usize,
+}
+impl Stats {
+    /// The number of atoms, comments, and compounds, like `Sexpr::node_count`
+    pub fn nodes(&self) -> usize {
+        self.atoms + self.compounds + self.comments
+    }
+}
+
+impl Sexpr<'_> {
+    /// Count the nodes of this sexpr and how deeply they are nested
+    pub fn stats(&self) -> Stats {
+        match self.kind {
+            Atom(_) => Stats { atoms: 1, ..Stats::default() },
+            Comment(_) => Stats { comments: 1, ..Stats::default() },
+            Prefixed(_, ref sexpr) => sexpr.stats(),
+            Compound(ref head, ref args) => {
+                let mut stats = Stats { compounds: 1, ..Stats::default() };
+                for child in std::iter::once(&**head).chain(args.iter()) {
+                    let child = child.stats();
+                    stats.atoms += child.atoms;
+                    stats.compounds += child.compounds;
+                    stats.comments += child.comments;
+                    stats.max_depth = stats.max_depth.max(child.max_depth);
+                }
+                stats.max_depth += 1;
+                stats
+            }
+        }
+    }
+}
+
+/// How many times each atom appears in `forms`, most frequent first (and alphabetically among equals)
+pub fn atom_frequencies(forms: &[Sexpr<'_>]) -> Vec<(String, usize)> {
+    fn count<'s>(sexpr: &'s Sexpr<'_>, counts: &mut HashMap<&'s str, usize>) {
+        match sexpr.kind {
+            Atom(ref text) => *counts.entry(text).or_insert(0) += 1,
+            Comment(_) => {}
+            Prefixed(_, ref sexpr) => count(sexpr, counts),
+            Compound(ref head, ref args) => {
+                count(head, counts);
+                for arg in args.iter() {
+                    count(arg, counts);
+                }
+            }
+        }
+    }
+    let mut counts = HashMap::new();
+    for form in forms {
+        count(form, &mut counts);
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_iter()
+        .map(|(atom, n)| (atom.to_string(), n))
+        .collect();
+    counts.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+    counts
+}
+
+/// How many of `sizes` fall in each power of two bucket `1`, `2..3`, `4..7`, ...,
+/// as the smallest size of the bucket and the count, from the first bucket to the last nonempty one
+pub fn size_histogram(sizes: &[usize]) -> Vec<(usize, usize)> {
+    let bucket = |size: usize| (usize::BITS - size.max(1).leading_zeros() - 1) as usize;
+    let buckets = sizes.iter().map(|&size| bucket(size) + 1).max().unwrap_or(0);
+    let mut histogram: Vec<(usize, usize)> = (0..buckets).map(|i| (1 << i, 0)).collect();
+    for &size in sizes {
+        histogram[bucket(size)].1 += 1;
+    }
+    histogram
+}
+
+/// The shape of the lines of a text, for comparing layouts
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineStats {
+    pub lines: usize,
+    /// the number of chars on the lines, leaving out line breaks
+    pub chars: usize,
+    /// the number of chars on the longest line
+    pub widest: usize,
+}
+impl LineStats {
+    /// Measure the lines of `text`, where a final line break does not start another line
+    pub fn of(text: &str) -> LineStats {
+        let mut stats = LineStats::default();
+        for line in text.lines() {
+            let width = line.chars().count();
+            stats.lines += 1;
+            stats.chars += width;
+            stats.widest = stats.widest.max(width);
+        }
+        stats
+    }
+    /// Add the lines measured by `other` to these
+    pub fn add(&mut self, other: LineStats) {
+        self.lines += other.lines;
+        self.chars += other.chars;
+        self.widest = self.widest.max(other.widest);
+    }
+    /// The average number of chars on a line
+    pub fn average(&self) -> f64 {
+        if self.lines == 0 { 0.0 } else { self.chars as f64 / self.lines as f64 }
+    }
+}
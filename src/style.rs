@@ -0,0 +1,112 @@
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Comment, Compound};
+
+/// The version of the style manifest format, bumped whenever the meaning of a setting changes
+pub const STYLE_VERSION: u32 = 1;
+
+/// The head of a style manifest, which also marks the comment that records one
+const MANIFEST_HEAD: &str = "sexpr-fmt-style";
+
+/// The head of a profile, `(profile name (setting values...) ...)`
+const PROFILE_HEAD: &str = "profile";
+
+/// The profiles which come with sexpr-fmt, written like the ones of a config file
+pub const BUILTIN_PROFILES: &str = "\
+(profile smt
+    (complexity-threshold 2)
+    (short-quantifiers true)
+    (pair-keywords true)
+    (never-break check-sat get-model set-logic set-option set-info))
+(profile clojure-community
+    (complexity-threshold 2)
+    (inline-first-arg-of defn defn- defmacro fn let loop binding when when-let if if-let doseq for)
+    (pair-keywords true)
+    (max-width 80))
+(profile kicad
+    (complexity-threshold 2)
+    (align-numbers true)
+    (never-break at xy size layer layers font uuid tstamp))
+(profile compact
+    (complexity-threshold 3)
+    (short-quantifiers true)
+    (keep-blank-lines 0))
+";
+
+/// A named bundle of settings, which `--profile name` applies all at once
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// the name and values of each setting, like in a style manifest
+    pub settings: Vec<(String, Vec<String>)>,
+}
+
+/// Read the profiles defined in `text`, like `(profile team (complexity-threshold 2) (always-break assert))`
+/// the settings are written like in a style manifest, so that the settings of one can be pasted into a profile
+///
+/// ```
+/// use sexpr_fmt::style::profiles;
+///
+/// let profiles = profiles("(profile team (complexity-threshold 2) (always-break assert define-fun))").unwrap();
+/// assert_eq!(profiles[0].name, "team");
+/// assert_eq!(profiles[0].settings[1], ("always-break".to_string(), vec!["assert".to_string(), "define-fun".to_string()]));
+/// ```
+pub fn profiles(text: &str) -> Result<Vec<Profile>, String> {
+    let forms = Sexpr::parse_many(text).map_err(ToString::to_string)?;
+    forms.iter().filter(|form| !form.is_comment()).map(profile).collect()
+}
+
+/// The profile defined by `form`
+fn profile(form: &Sexpr<'_>) -> Result<Profile, String> {
+    let expected = || format!("expected a profile like `(profile name (setting values...) ...)`, found `{}`", form.to_compact_string());
+    let (name, settings) = match form.kind {
+        Compound(ref head, ref args) if head.is_named(PROFILE_HEAD) => args.split_first().ok_or_else(expected)?,
+        _ => return Err(expected()),
+    };
+    let name = match name.kind {
+        Atom(ref name) => name.to_string(),
+        _ => return Err(expected()),
+    };
+    let settings = settings.iter().filter(|setting| !setting.is_comment()).map(|setting| match setting.kind {
+        Compound(ref head, ref values) if head.is_atom() && values.iter().all(Sexpr::is_atom) => {
+            Ok((head.to_compact_string(), values.iter().map(value).collect()))
+        }
+        _ => Err(format!("expected a setting like `(complexity-threshold 2)` in the profile `{}`, found `{}`",
+                         name, setting.to_compact_string())),
+    }).collect::<Result<_, _>>()?;
+    Ok(Profile { name, settings })
+}
+
+/// The value written as the atom `atom`, which is unquoted if it is a string
+fn value(atom: &Sexpr<'_>) -> String {
+    let text = atom.to_compact_string();
+    serde_json::from_str::<String>(&text).unwrap_or(text)
+}
+
+/// Builds a style manifest `(sexpr-fmt-style (version 1) (setting values...) ...)`
+/// from the name and values of each setting, in a fixed order so that equal styles give equal manifests
+pub fn manifest(settings: Vec<(&str, Vec<String>)>) -> Sexpr<'static> {
+    let version = Sexpr::compound(Sexpr::atom("version"), vec![Sexpr::atom(STYLE_VERSION.to_string())]);
+    let settings = settings.into_iter().map(|(name, values)| {
+        Sexpr::compound(Sexpr::atom(name.to_string()), values.into_iter().map(Sexpr::atom).collect())
+    });
+    Sexpr::compound(Sexpr::atom(MANIFEST_HEAD), std::iter::once(version).chain(settings).collect())
+}
+
+/// The block comment recording `manifest`, to be put at the end of a formatted file
+pub fn manifest_comment(manifest: &Sexpr<'_>) -> String {
+    format!("#| {} |#", manifest.to_compact_string())
+}
+
+/// If `form` is a comment recording a style manifest, the manifest in its compact form
+pub fn recorded_manifest(form: &Sexpr<'_>) -> Option<String> {
+    let text = match form.kind {
+        Comment(ref text) => text,
+        _ => return None,
+    };
+    let inner = text.strip_prefix("#|")?.strip_suffix("|#")?.trim();
+    let manifest = Sexpr::parse(inner).ok()?;
+    match manifest.kind {
+        Compound(ref head, _) if head.is_named(MANIFEST_HEAD) => Some(manifest.to_compact_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,93 @@
+//! Helpers for testing tools whose output is s-expressions
+use std::fmt::Write;
+use std::io::IsTerminal;
+use crate::sexpr::Sexpr;
+use crate::treediff::{self, TreeEdit};
+
+/// Assert that two strings hold the same s-expressions, ignoring how they are laid out
+/// on failure, panics with both sides and the structural differences between them
+///
+/// ```
+/// sexpr_fmt::assert_sexpr_eq!("(and a\n    b)", "(and a b)");
+/// ```
+#[macro_export]
+macro_rules! assert_sexpr_eq {
+    ($actual:expr, $expected:expr $(,)?) => {
+        $crate::testing::assert_sexpr_eq(&$actual, &$expected, ::std::option::Option::None)
+    };
+    ($actual:expr, $expected:expr, $($arg:tt)+) => {
+        $crate::testing::assert_sexpr_eq(&$actual, &$expected, ::std::option::Option::Some(::std::format!($($arg)+)))
+    };
+}
+
+/// The function behind `assert_sexpr_eq!`, which parses both sides and compares them structurally
+#[track_caller]
+pub fn assert_sexpr_eq(actual: &dyn AsRef<str>, expected: &dyn AsRef<str>, message: Option<String>) {
+    #[track_caller]
+    fn parse<'t>(side: &str, text: &'t str) -> Vec<Sexpr<'t>> {
+        Sexpr::parse_many(text)
+            .unwrap_or_else(|e| panic!("assertion failed: could not parse `{}` as s-expressions: {}\n{}", side, e, text))
+    }
+    let actual = parse("actual", actual.as_ref());
+    let expected = parse("expected", expected.as_ref());
+    if actual == expected {
+        return;
+    }
+    panic!("{}", failure(&actual, &expected, message, Colors::detect()));
+}
+
+/// The escape codes to highlight the failure with, which are empty when not writing to a terminal
+struct Colors {
+    removed: &'static str,
+    added: &'static str,
+    reset: &'static str,
+}
+impl Colors {
+    fn detect() -> Colors {
+        if std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none() {
+            Colors { removed: "\x1b[31m", added: "\x1b[32m", reset: "\x1b[0m" }
+        } else {
+            Colors { removed: "", added: "", reset: "" }
+        }
+    }
+}
+
+/// Describe how `actual` differs from `expected`, with `-` for what only `actual` has and `+` for what only `expected` has
+fn failure(actual: &[Sexpr<'_>], expected: &[Sexpr<'_>], message: Option<String>, colors: Colors) -> String {
+    let mut out = String::from("assertion failed: `actual` and `expected` are not the same s-expressions");
+    if let Some(message) = message {
+        let _ = write!(out, ": {}", message);
+    }
+    let show = |forms: &[Sexpr<'_>]| forms.iter().map(Sexpr::to_compact_string).collect::<Vec<_>>().join("\n          ");
+    let _ = write!(out, "\n  actual: {}\nexpected: {}\ndifferences:", show(actual), show(expected));
+    let removed = |out: &mut String, sexpr: &Sexpr<'_>| {
+        let _ = write!(out, "\n    {}- {}{}", colors.removed, sexpr.to_compact_string(), colors.reset);
+    };
+    let added = |out: &mut String, sexpr: &Sexpr<'_>| {
+        let _ = write!(out, "\n    {}+ {}{}", colors.added, sexpr.to_compact_string(), colors.reset);
+    };
+    if let ([actual], [expected]) = (actual, expected) {
+        for edit in treediff::diff(actual, expected) {
+            match edit {
+                TreeEdit::Delete { path, old, .. } => {
+                    let _ = write!(out, "\n  at {}:", crate::annotate::format_path(&path));
+                    removed(&mut out, old);
+                }
+                TreeEdit::Insert { path, new, .. } => {
+                    let _ = write!(out, "\n  at {}:", crate::annotate::format_path(&path));
+                    added(&mut out, new);
+                }
+                TreeEdit::Replace { old_path, old, new, .. } => {
+                    let _ = write!(out, "\n  at {}:", crate::annotate::format_path(&old_path));
+                    removed(&mut out, old);
+                    added(&mut out, new);
+                }
+            }
+        }
+    } else {
+        for change in treediff::diff_forms(actual, expected) {
+            let _ = write!(out, "\n  {}", change);
+        }
+    }
+    out
+}
@@ -0,0 +1,163 @@
+/// A contiguous region of lines which differ between two texts
+#[derive(Debug, PartialEq)]
+pub struct Hunk<'a> {
+    /// index of the first removed line in the old text
+    /// (or of the line the insertion comes before, if nothing was removed)
+    pub old_start: usize,
+    /// index of the first inserted line in the new text
+    pub new_start: usize,
+    pub removed: Vec<&'a str>,
+    pub inserted: Vec<&'a str>,
+}
+
+/// One step of turning a sequence into another
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Edit {
+    Keep,
+    Remove,
+    Insert,
+}
+
+/// Compute the hunks which turn the lines of `old` into the lines of `new`, using Myers' algorithm
+pub fn line_hunks<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Hunk<'a>> {
+    let edits = shortest_edit(old, new);
+    let mut hunks: Vec<Hunk<'a>> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut in_hunk = false;
+    for edit in edits {
+        if edit != Edit::Keep && !in_hunk {
+            hunks.push(Hunk { old_start: i, new_start: j, removed: Vec::new(), inserted: Vec::new() });
+        }
+        in_hunk = edit != Edit::Keep;
+        match edit {
+            Edit::Keep => { i += 1; j += 1; }
+            Edit::Remove => {
+                hunks.last_mut().expect("in a hunk").removed.push(old[i]);
+                i += 1;
+            }
+            Edit::Insert => {
+                hunks.last_mut().expect("in a hunk").inserted.push(new[j]);
+                j += 1;
+            }
+        }
+    }
+    hunks
+}
+
+/// Rebuild the new text from `old`, applying only the hunks for which `accept` holds
+pub fn apply_hunks<'a>(old: &[&'a str], hunks: &[Hunk<'a>], accept: &[bool]) -> Vec<&'a str> {
+    let mut result = Vec::new();
+    let mut i = 0;
+    for (hunk, &accepted) in hunks.iter().zip(accept.iter()) {
+        result.extend_from_slice(&old[i..hunk.old_start]);
+        if accepted {
+            result.extend_from_slice(&hunk.inserted);
+        } else {
+            result.extend_from_slice(&hunk.removed);
+        }
+        i = hunk.old_start + hunk.removed.len();
+    }
+    result.extend_from_slice(&old[i..]);
+    result
+}
+
+/// The most edits `shortest_edit` searches through for a shortest sequence, since the search takes memory
+/// growing with the square of the number of edits; past it, the differing middle is replaced as a whole
+const MAX_EDIT_DISTANCE: usize = 2_000;
+
+/// The shortest sequence of edits turning `old` into `new`
+/// if they differ by more than `MAX_EDIT_DISTANCE` edits, everything between their common start and end
+/// is removed and inserted again instead, so that texts which are nothing alike (like a minified file and
+/// the same file formatted) do not take quadratic memory
+///
+/// ```
+/// use sexpr_fmt::textdiff::{shortest_edit, Edit};
+///
+/// assert_eq!(shortest_edit(b"abc", b"axc"), [Edit::Keep, Edit::Remove, Edit::Insert, Edit::Keep]);
+/// let old = vec![0; 1];
+/// let new: Vec<u32> = (0..100_000).collect();
+/// let edits = shortest_edit(&old, &new);
+/// assert_eq!(edits.iter().filter(|&&edit| edit == Edit::Insert).count(), 99_999);
+/// ```
+pub fn shortest_edit<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    // the common start and end are kept without searching through them
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix = old_rest.iter().rev().zip(new_rest.iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old_middle, new_middle) = (&old_rest[..old_rest.len() - suffix], &new_rest[..new_rest.len() - suffix]);
+    let mut edits = vec![Edit::Keep; prefix];
+    match search(old_middle, new_middle) {
+        Some(middle) => edits.extend(middle),
+        None => {
+            edits.extend(core::iter::repeat_n(Edit::Remove, old_middle.len()));
+            edits.extend(core::iter::repeat_n(Edit::Insert, new_middle.len()));
+        }
+    }
+    edits.extend(core::iter::repeat_n(Edit::Keep, suffix));
+    edits
+}
+
+/// The shortest sequence of edits turning `old` into `new`, using Myers' algorithm,
+/// or nothing if it is longer than `MAX_EDIT_DISTANCE`
+fn search<T: PartialEq>(old: &[T], new: &[T]) -> Option<Vec<Edit>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (old.len() + new.len()).min(MAX_EDIT_DISTANCE);
+    let offset = max as isize + 1;
+    // v[k + offset] holds the furthest x reached on diagonal k
+    let mut v = vec![0isize; 2 * max + 3];
+    // the part of v for diagonals -d to d, as it was before each step d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let mut found = false;
+    'search: for d in 0..=max as isize {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+    if !found {
+        return None;
+    }
+    // walk back through the trace to recover the edits
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let at = |k: isize| v[(k + d) as usize];
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+            (at(prev_k), at(prev_k) - prev_k)
+        };
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            edits.push(if x == prev_x { Edit::Insert } else { Edit::Remove });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    edits.reverse();
+    Some(edits)
+}
@@ -0,0 +1,81 @@
+use serde_json::{json, Value};
+use crate::sexpr::{Sexpr, Span};
+use crate::sexpr::SexprKind::{Atom, Compound, Prefixed, Comment};
+use crate::atom::AtomKind;
+
+/// What a token of the input is
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Open,
+    Close,
+    Atom(AtomKind),
+    Comment,
+    /// reader sugar like `'` or `,@`
+    Prefix,
+}
+
+/// A token of the input, for editors to highlight: where it was read from, and how many compounds it is in
+/// the parens of a top-level compound are at depth 0, and the atoms inside of it at depth 1
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+    pub depth: usize,
+}
+impl Token {
+    /// The token as json, like `{"kind": "atom", "atom": "number", "start": 4, "end": 6, "depth": 1}`
+    pub fn to_json(&self) -> Value {
+        let kind = match self.kind {
+            TokenKind::Open => "open",
+            TokenKind::Close => "close",
+            TokenKind::Atom(_) => "atom",
+            TokenKind::Comment => "comment",
+            TokenKind::Prefix => "prefix",
+        };
+        let mut value = json!({ "kind": kind, "start": self.span.start, "end": self.span.end, "depth": self.depth });
+        if let TokenKind::Atom(atom) = self.kind {
+            value["atom"] = json!(match atom {
+                AtomKind::Symbol => "symbol",
+                AtomKind::Number => "number",
+                AtomKind::String => "string",
+                AtomKind::Keyword => "keyword",
+            });
+        }
+        value
+    }
+}
+
+/// The tokens of parsed top-level forms, in the order they appear in the input
+/// forms which were not parsed (and so have no spans) have no tokens
+pub fn tokens(forms: &[Sexpr<'_>]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for form in forms {
+        collect(form, 0, &mut tokens);
+    }
+    tokens
+}
+
+fn collect(sexpr: &Sexpr<'_>, depth: usize, tokens: &mut Vec<Token>) {
+    let span = match sexpr.span() {
+        Some(span) => span,
+        None => return,
+    };
+    let token = |kind, start, end| Token { kind, span: Span { start, end }, depth };
+    match sexpr.kind {
+        _ if sexpr.is_blank() => {}
+        Atom(ref text) => tokens.push(token(TokenKind::Atom(AtomKind::of(text)), span.start, span.end)),
+        Comment(_) => tokens.push(token(TokenKind::Comment, span.start, span.end)),
+        Prefixed(prefix, ref inner) => {
+            tokens.push(token(TokenKind::Prefix, span.start, span.start + prefix.len()));
+            collect(inner, depth, tokens);
+        }
+        Compound(ref head, ref args) => {
+            tokens.push(token(TokenKind::Open, span.start, span.start + 1));
+            collect(head, depth + 1, tokens);
+            for arg in args.iter() {
+                collect(arg, depth + 1, tokens);
+            }
+            tokens.push(token(TokenKind::Close, span.end - 1, span.end));
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::borrow::Cow;
+use ::tree_sitter::Node;
+use crate::sexpr::{Sexpr, ParseError, Span, PREFIXES};
+
+impl<'s> Sexpr<'s> {
+    /// Convert the top-level forms of a tree-sitter tree, such as one from the Scheme or Clojure grammars,
+    /// whose `root` node was parsed from `source`, so that a dialect's own parser can be used to read it
+    /// what the grammars call lists are compounds, reader prefixes like `'` before them are kept as prefixes,
+    /// comments are kept as comments, and every other node (atoms, strings, vectors, maps, and so on) is kept as it was written
+    ///
+    /// ```
+    /// use sexpr_fmt::{Sexpr, FormatArgs};
+    ///
+    /// let source = "(define (f x) ; doubles x\n  `(* 2 ,x)) #(1 2)";
+    /// let mut parser = tree_sitter::Parser::new();
+    /// parser.set_language(&tree_sitter_scheme::LANGUAGE.into()).unwrap();
+    /// let tree = parser.parse(source, None).unwrap();
+    /// let forms = Sexpr::from_tree_sitter(tree.root_node(), source).unwrap();
+    /// assert_eq!(forms.len(), 2);
+    /// assert_eq!(forms[0].format(FormatArgs::new()), "(define\n    (f x) ; doubles x\n    `(* 2 ,x)\n)");
+    /// assert_eq!(forms[1].to_compact_string(), "#(1 2)");
+    /// ```
+    pub fn from_tree_sitter(root: Node<'_>, source: &'s str) -> Result<Vec<Sexpr<'s>>, ParseError> {
+        if root.has_error() {
+            return Err("the tree-sitter tree has a syntax error in it");
+        }
+        let mut cursor = root.walk();
+        let forms = root.named_children(&mut cursor).map(|node| convert(node, source));
+        forms.collect()
+    }
+}
+
+/// The kinds of the anonymous nodes which open and close a list
+const OPEN: &str = "(";
+const CLOSE: &str = ")";
+
+fn convert<'s>(node: Node<'_>, source: &'s str) -> Result<Sexpr<'s>, ParseError> {
+    let text = source.get(node.byte_range()).ok_or("the tree-sitter tree was not parsed from this source")?;
+    let mut cursor = node.walk();
+    let children: Vec<Node<'_>> = node.children(&mut cursor).collect();
+    let named: Vec<Node<'_>> = children.iter().copied().filter(Node::is_named).collect();
+    let mut sexpr = match children.as_slice() {
+        _ if node.kind().contains("comment") => Sexpr::comment(text.trim_end()),
+        [open, .., close] if open.kind() == OPEN && close.kind() == CLOSE && !open.is_named() => {
+            let mut elements = named.into_iter().map(|child| convert(child, source));
+            match elements.next() {
+                Some(head) => Sexpr::compound(head?, elements.collect::<Result<_, _>>()?),
+                None => Sexpr::compound(Sexpr::blank(), Vec::new()),
+            }
+        }
+        // a prefix before a list, like `'(a b)`, applies to it, but one before an atom is part of the atom, like the reader does
+        [prefix, inner] if !prefix.is_named() && PREFIXES.contains(&prefix.kind()) && is_list(inner) => {
+            let prefix = PREFIXES.iter().find(|&&p| p == prefix.kind()).expect("the prefix is one of them");
+            Sexpr::prefixed(prefix, convert(*inner, source)?)
+        }
+        _ => Sexpr::atom(Cow::Borrowed(text)),
+    };
+    sexpr.set_span(source, Span { start: node.start_byte(), end: node.end_byte() });
+    Ok(sexpr)
+}
+
+/// Whether `node` is delimited by parens, like the lists of the lisp grammars
+fn is_list(node: &Node<'_>) -> bool {
+    let mut cursor = node.walk();
+    let children: Vec<Node<'_>> = node.children(&mut cursor).collect();
+    matches!(children.as_slice(), [open, .., close] if open.kind() == OPEN && close.kind() == CLOSE)
+}
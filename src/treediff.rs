@@ -0,0 +1,217 @@
+use std::fmt;
+use crate::sexpr::{Sexpr, Span};
+use crate::sexpr::SexprKind::{Compound, Prefixed};
+use crate::textdiff::{self, Edit};
+
+/// Where a subtree is in a sequence of top-level forms:
+/// the number of the form (starting from 1), and the path inside of it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub form: usize,
+    pub path: Vec<usize>,
+}
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.form, crate::annotate::format_path(&self.path))
+    }
+}
+
+/// A difference between two sequences of forms
+#[derive(Clone, Debug)]
+pub enum Change<'s, 'a> {
+    Deleted(Location, &'s Sexpr<'a>),
+    Inserted(Location, &'s Sexpr<'a>),
+    Replaced(Location, &'s Sexpr<'a>, Location, &'s Sexpr<'a>),
+    Moved(Location, Location, &'s Sexpr<'a>),
+}
+impl fmt::Display for Change<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Deleted(at, sexpr) => write!(f, "deleted {}: {}", at, sexpr.to_compact_string()),
+            Change::Inserted(at, sexpr) => write!(f, "inserted {}: {}", at, sexpr.to_compact_string()),
+            Change::Replaced(old_at, old, new_at, new) => {
+                write!(f, "replaced {}: {}\n      -> {}: {}", old_at, old.to_compact_string(), new_at, new.to_compact_string())
+            }
+            Change::Moved(from, to, sexpr) => write!(f, "moved {} -> {}: {}", from, to, sexpr.to_compact_string()),
+        }
+    }
+}
+
+/// Compare two sequences of forms structurally, ignoring how they are laid out
+/// the changes are listed in the order they are found, with moves after everything else
+pub fn diff_forms<'s, 'a>(old: &'s [Sexpr<'a>], new: &'s [Sexpr<'a>]) -> Vec<Change<'s, 'a>> {
+    let mut changes = Vec::new();
+    let old_children: Vec<Child<'s, 'a>> = old.iter().enumerate()
+        .map(|(i, sexpr)| (Location { form: i + 1, path: Vec::new() }, sexpr))
+        .collect();
+    let new_children: Vec<Child<'s, 'a>> = new.iter().enumerate()
+        .map(|(i, sexpr)| (Location { form: i + 1, path: Vec::new() }, sexpr))
+        .collect();
+    diff_children(&old_children, &new_children, &mut changes);
+    find_moves(changes)
+}
+
+/// A difference between two sexprs, with the paths and the spans (if they were parsed) of the subtrees involved
+#[derive(Clone, Debug)]
+pub enum TreeEdit<'s, 'a> {
+    Insert { path: Vec<usize>, span: Option<Span>, new: &'s Sexpr<'a> },
+    Delete { path: Vec<usize>, span: Option<Span>, old: &'s Sexpr<'a> },
+    Replace { old_path: Vec<usize>, old_span: Option<Span>, old: &'s Sexpr<'a>, new_path: Vec<usize>, new_span: Option<Span>, new: &'s Sexpr<'a> },
+}
+impl fmt::Display for TreeEdit<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use crate::annotate::format_path;
+        let at = |f: &mut fmt::Formatter<'_>, path: &[usize], span: &Option<Span>| match span {
+            Some(span) => write!(f, "{} ({:?})", format_path(path), span),
+            None => write!(f, "{}", format_path(path)),
+        };
+        match self {
+            TreeEdit::Insert { path, span, new } => {
+                write!(f, "inserted ")?;
+                at(f, path, span)?;
+                write!(f, ": {}", new.to_compact_string())
+            }
+            TreeEdit::Delete { path, span, old } => {
+                write!(f, "deleted ")?;
+                at(f, path, span)?;
+                write!(f, ": {}", old.to_compact_string())
+            }
+            TreeEdit::Replace { old_path, old_span, old, new_path, new_span, new } => {
+                write!(f, "replaced ")?;
+                at(f, old_path, old_span)?;
+                write!(f, ": {}\n      -> ", old.to_compact_string())?;
+                at(f, new_path, new_span)?;
+                write!(f, ": {}", new.to_compact_string())
+            }
+        }
+    }
+}
+
+/// Compare two sexprs structurally, ignoring how they are laid out
+/// a subtree that moved shows up as a deletion and an insertion
+pub fn diff<'s, 'a>(old: &'s Sexpr<'a>, new: &'s Sexpr<'a>) -> Vec<TreeEdit<'s, 'a>> {
+    let root = || Location { form: 1, path: Vec::new() };
+    if old == new {
+        return Vec::new();
+    }
+    let mut changes = Vec::new();
+    if comparable(old, new) {
+        diff_children(&children(&root(), old), &children(&root(), new), &mut changes);
+    } else {
+        changes.push(Change::Replaced(root(), old, root(), new));
+    }
+    changes.into_iter()
+        .map(|change| match change {
+            Change::Inserted(at, new) => TreeEdit::Insert { path: at.path, span: new.span(), new },
+            Change::Deleted(at, old) => TreeEdit::Delete { path: at.path, span: old.span(), old },
+            Change::Replaced(old_at, old, new_at, new) => TreeEdit::Replace {
+                old_path: old_at.path, old_span: old.span(), old,
+                new_path: new_at.path, new_span: new.span(), new,
+            },
+            Change::Moved(..) => unreachable!("moves are only found by `diff_forms`"),
+        })
+        .collect()
+}
+
+type Child<'s, 'a> = (Location, &'s Sexpr<'a>);
+
+/// The children of a compound (its head, and then its arguments) or a prefixed form, with their locations
+fn children<'s, 'a>(at: &Location, sexpr: &'s Sexpr<'a>) -> Vec<Child<'s, 'a>> {
+    let child = |i: usize, sexpr| {
+        let mut path = at.path.clone();
+        path.push(i);
+        (Location { form: at.form, path }, sexpr)
+    };
+    match sexpr.kind {
+        Compound(ref head, ref args) => std::iter::once(&**head).chain(args.iter())
+            .enumerate()
+            .map(|(i, sexpr)| child(i, sexpr))
+            .collect(),
+        Prefixed(_, ref sexpr) => vec![child(1, &**sexpr)],
+        _ => Vec::new(),
+    }
+}
+
+/// Whether two differing subtrees have enough in common to compare their insides
+fn comparable(old: &Sexpr<'_>, new: &Sexpr<'_>) -> bool {
+    match (&old.kind, &new.kind) {
+        (Compound(old_head, _), Compound(new_head, _)) => old_head == new_head,
+        (Prefixed(old_prefix, _), Prefixed(new_prefix, _)) => old_prefix == new_prefix,
+        _ => false,
+    }
+}
+
+/// Compare the children of two lists, recursing into pairs that changed but are still comparable
+fn diff_children<'s, 'a>(old: &[Child<'s, 'a>], new: &[Child<'s, 'a>], changes: &mut Vec<Change<'s, 'a>>) {
+    let old_sexprs: Vec<&Sexpr<'a>> = old.iter().map(|(_, sexpr)| *sexpr).collect();
+    let new_sexprs: Vec<&Sexpr<'a>> = new.iter().map(|(_, sexpr)| *sexpr).collect();
+    let edits = textdiff::shortest_edit(&old_sexprs, &new_sexprs);
+    let (mut i, mut j) = (0, 0);
+    let mut edits = edits.into_iter().peekable();
+    while edits.peek().is_some() {
+        if edits.peek() == Some(&Edit::Keep) {
+            edits.next();
+            i += 1;
+            j += 1;
+            continue;
+        }
+        // a gap between two kept children: pair up what was removed with what was inserted
+        let (gap_i, gap_j) = (i, j);
+        while let Some(&edit) = edits.peek() {
+            match edit {
+                Edit::Keep => break,
+                Edit::Remove => i += 1,
+                Edit::Insert => j += 1,
+            }
+            edits.next();
+        }
+        let removed = &old[gap_i..i];
+        let inserted = &new[gap_j..j];
+        for k in 0..std::cmp::max(removed.len(), inserted.len()) {
+            match (removed.get(k), inserted.get(k)) {
+                (Some((old_at, old)), Some((new_at, new))) if comparable(old, new) => {
+                    diff_children(&children(old_at, old), &children(new_at, new), changes);
+                }
+                // something that shows up elsewhere in the other list has moved, rather than been replaced
+                (Some((old_at, old)), Some((new_at, new))) if !new_sexprs.contains(old) && !old_sexprs.contains(new) => {
+                    changes.push(Change::Replaced(old_at.clone(), old, new_at.clone(), new));
+                }
+                (old, new) => {
+                    if let Some((old_at, old)) = old {
+                        changes.push(Change::Deleted(old_at.clone(), old));
+                    }
+                    if let Some((new_at, new)) = new {
+                        changes.push(Change::Inserted(new_at.clone(), new));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Turn each deletion whose subtree was inserted somewhere else into a move
+fn find_moves<'s, 'a>(changes: Vec<Change<'s, 'a>>) -> Vec<Change<'s, 'a>> {
+    let mut unclaimed: Vec<bool> = changes.iter().map(|change| matches!(change, Change::Inserted(..))).collect();
+    let mut moved = vec![false; changes.len()];
+    let mut moves = Vec::new();
+    for (i, change) in changes.iter().enumerate() {
+        if let Change::Deleted(from, sexpr) = change {
+            let found = changes.iter().enumerate()
+                .position(|(j, other)| unclaimed[j] && matches!(other, Change::Inserted(_, new) if new == sexpr));
+            if let Some(j) = found {
+                if let Change::Inserted(to, _) = &changes[j] {
+                    moves.push(Change::Moved(from.clone(), to.clone(), sexpr));
+                }
+                unclaimed[j] = false;
+                moved[i] = true;
+                moved[j] = true;
+            }
+        }
+    }
+    changes.into_iter()
+        .zip(moved)
+        .filter(|(_, moved)| !moved)
+        .map(|(change, _)| change)
+        .chain(moves)
+        .collect()
+}
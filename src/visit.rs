@@ -0,0 +1,191 @@
+use crate::prelude::*;
+use crate::sexpr::Sexpr;
+use crate::sexpr::SexprKind::{Atom, Comment, Compound, Prefixed};
+
+impl<'a> Sexpr<'a> {
+    /// The head of this compound, like the `f` of `(f x y)`
+    pub fn head(&self) -> Option<&Sexpr<'a>> {
+        match self.kind {
+            Compound(ref head, _) => Some(head),
+            _ => None,
+        }
+    }
+    /// The arguments of this compound, like the `x y` of `(f x y)`, or nothing if it is not one
+    pub fn args(&self) -> &[Sexpr<'a>] {
+        match self.kind {
+            Compound(_, ref args) => args,
+            _ => &[],
+        }
+    }
+    /// The text of this atom
+    pub fn as_atom(&self) -> Option<&str> {
+        match self.kind {
+            Atom(ref text) => Some(text),
+            _ => None,
+        }
+    }
+    /// The reader prefix of this, like the `'` of `'(a b)`
+    pub fn prefix(&self) -> Option<&'static str> {
+        match self.kind {
+            Prefixed(prefix, _) => Some(prefix),
+            _ => None,
+        }
+    }
+    /// The sexprs directly inside of this: the head and then the arguments of a compound,
+    /// or the form a prefix applies to
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = &Sexpr<'a>> + '_ {
+        let prefixed = match self.kind {
+            Prefixed(_, ref sexpr) => Some(&**sexpr),
+            _ => None,
+        };
+        self.head().into_iter().chain(self.args()).chain(prefixed)
+    }
+    /// Every sexpr in this one, starting with itself, each before the ones inside of it
+    ///
+    /// ```
+    /// use sexpr_fmt::Sexpr;
+    ///
+    /// let sexpr = Sexpr::parse("(f (g x) y)").unwrap();
+    /// let atoms: Vec<&str> = sexpr.iter().filter_map(Sexpr::as_atom).collect();
+    /// assert_eq!(atoms, ["f", "g", "x", "y"]);
+    /// let post: Vec<String> = sexpr.iter_post_order().map(Sexpr::to_compact_string).collect();
+    /// assert_eq!(post, ["f", "g", "x", "(g x)", "y", "(f (g x) y)"]);
+    /// ```
+    pub fn iter(&self) -> Iter<'_, 'a> {
+        Iter { stack: vec![(self, false)], order: Order::Pre }
+    }
+    /// Every sexpr in this one, each after the ones inside of it, ending with itself
+    pub fn iter_post_order(&self) -> Iter<'_, 'a> {
+        Iter { stack: vec![(self, false)], order: Order::Post }
+    }
+    /// Walk this sexpr depth first, calling `visitor` on entering and leaving each sexpr in it
+    ///
+    /// ```
+    /// use sexpr_fmt::Sexpr;
+    /// use sexpr_fmt::visit::Visitor;
+    ///
+    /// // the deepest nesting of `let` forms
+    /// struct Lets { depth: usize, deepest: usize }
+    /// impl Visitor for Lets {
+    ///     fn enter(&mut self, sexpr: &Sexpr<'_>) -> bool {
+    ///         if sexpr.head().is_some_and(|head| head.is_named("let")) {
+    ///             self.depth += 1;
+    ///             self.deepest = self.deepest.max(self.depth);
+    ///         }
+    ///         true
+    ///     }
+    ///     fn leave(&mut self, sexpr: &Sexpr<'_>) {
+    ///         if sexpr.head().is_some_and(|head| head.is_named("let")) {
+    ///             self.depth -= 1;
+    ///         }
+    ///     }
+    /// }
+    /// let sexpr = Sexpr::parse("(let ((x 1)) (f (let ((y x)) y) (let () 0)))").unwrap();
+    /// let mut lets = Lets { depth: 0, deepest: 0 };
+    /// sexpr.walk(&mut lets);
+    /// assert_eq!(lets.deepest, 2);
+    /// ```
+    pub fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        if visitor.enter(self) {
+            for child in self.children() {
+                child.walk(visitor);
+            }
+        }
+        visitor.leave(self);
+    }
+    /// Change the sexprs in this one with `f`, bottom up: `f` is given each sexpr after the ones inside of it,
+    /// and can replace it by assigning to it; the measures of everything around a replaced sexpr are worked out again
+    ///
+    /// ```
+    /// use sexpr_fmt::Sexpr;
+    ///
+    /// let mut sexpr = Sexpr::parse("(define (f x) (g x))").unwrap();
+    /// sexpr.transform(|sexpr| if sexpr.as_atom() == Some("x") {
+    ///     *sexpr = Sexpr::atom("arg");
+    /// });
+    /// assert_eq!(sexpr.to_compact_string(), "(define (f arg) (g arg))");
+    /// ```
+    pub fn transform(&mut self, mut f: impl FnMut(&mut Sexpr<'a>)) {
+        self.transform_helper(&mut f);
+    }
+    fn transform_helper<F: FnMut(&mut Sexpr<'a>)>(&mut self, f: &mut F) {
+        match self.kind {
+            Atom(_) | Comment(_) => {}
+            Prefixed(_, ref mut sexpr) => sexpr.transform_helper(f),
+            Compound(ref mut head, ref mut args) => {
+                head.transform_helper(f);
+                args.iter_mut().for_each(|arg| arg.transform_helper(f));
+            }
+        }
+        self.remeasure();
+        f(self);
+    }
+    /// This sexpr with each sexpr in it replaced by what `f` makes of it, bottom up like `transform`
+    ///
+    /// ```
+    /// use sexpr_fmt::Sexpr;
+    ///
+    /// // fold the sums of numbers
+    /// let sexpr = Sexpr::parse("(* y (+ 1 (+ 2 3)))").unwrap();
+    /// let folded = sexpr.map(|sexpr| {
+    ///     let numbers: Option<Vec<i64>> = sexpr.args().iter().map(|arg| arg.as_atom()?.parse().ok()).collect();
+    ///     match numbers {
+    ///         Some(numbers) if sexpr.head().is_some_and(|head| head.is_named("+")) => Sexpr::atom(numbers.iter().sum::<i64>().to_string()),
+    ///         _ => sexpr,
+    ///     }
+    /// });
+    /// assert_eq!(folded.to_compact_string(), "(* y 6)");
+    /// assert_eq!(folded.complexity(), 1);
+    /// ```
+    pub fn map(mut self, mut f: impl FnMut(Sexpr<'a>) -> Sexpr<'a>) -> Sexpr<'a> {
+        self.transform(|sexpr| {
+            let old = core::mem::replace(sexpr, Sexpr::blank());
+            *sexpr = f(old);
+        });
+        self
+    }
+}
+
+/// What `Sexpr::walk` calls on each sexpr it comes to
+pub trait Visitor {
+    /// Called on coming to `sexpr`, before the sexprs inside of it; returning false skips them
+    fn enter(&mut self, _sexpr: &Sexpr<'_>) -> bool {
+        true
+    }
+    /// Called after the sexprs inside of `sexpr`, or right after `enter` if they were skipped
+    fn leave(&mut self, _sexpr: &Sexpr<'_>) {}
+}
+
+/// Whether a sexpr comes before or after the ones inside of it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Order {
+    Pre,
+    Post,
+}
+
+/// A depth-first iterator over a sexpr and everything inside of it, from `Sexpr::iter` or `Sexpr::iter_post_order`
+#[derive(Clone, Debug)]
+pub struct Iter<'s, 'a> {
+    // the sexprs still to come, the next one last, and whether their children are already on the stack
+    stack: Vec<(&'s Sexpr<'a>, bool)>,
+    order: Order,
+}
+impl<'s, 'a> Iterator for Iter<'s, 'a> {
+    type Item = &'s Sexpr<'a>;
+    fn next(&mut self) -> Option<&'s Sexpr<'a>> {
+        loop {
+            let (sexpr, expanded) = self.stack.pop()?;
+            match self.order {
+                Order::Pre => {
+                    self.stack.extend(sexpr.children().rev().map(|child| (child, false)));
+                    return Some(sexpr);
+                }
+                Order::Post if expanded => return Some(sexpr),
+                Order::Post => {
+                    self.stack.push((sexpr, true));
+                    self.stack.extend(sexpr.children().rev().map(|child| (child, false)));
+                }
+            }
+        }
+    }
+}
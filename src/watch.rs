@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// The extensions of the files that are watched inside of a watched directory
+const WATCHED_EXTENSIONS: &[&str] = &["lisp", "lsp", "cl", "el", "scm", "ss", "rkt", "clj", "cljs", "cljc", "edn",
+    "smt2", "smt", "sexp", "sexpr", "wat", "kicad_pcb", "kicad_sch", "kicad_sym", "kicad_mod"];
+
+/// Every file among `paths`, looking through directories for files with one of the watched extensions
+fn watched_files(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = paths.to_vec();
+    while let Some(path) = pending.pop() {
+        if path.is_dir() {
+            for entry in fs::read_dir(&path)? {
+                let entry_path = entry?.path();
+                let is_watched = entry_path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| WATCHED_EXTENSIONS.contains(&ext));
+                if entry_path.is_dir() || is_watched {
+                    pending.push(entry_path);
+                }
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Call `on_change` for each of the watched files when it first appears, and again every time it is modified,
+/// checking every `interval`
+/// this never returns, except when looking through a directory fails
+pub fn watch<F>(paths: &[PathBuf], interval: Duration, mut on_change: F) -> io::Result<()>
+    where F: FnMut(&Path)
+{
+    let mut seen: HashMap<PathBuf, Option<SystemTime>> = HashMap::new();
+    loop {
+        for file in watched_files(paths)? {
+            let before = modified(&file);
+            if seen.get(&file) != Some(&before) {
+                on_change(&file);
+                // if the callback rewrote the file, don't count that as another change
+                seen.insert(file.clone(), modified(&file));
+            }
+        }
+        thread::sleep(interval);
+    }
+}
@@ -0,0 +1,25 @@
+use sexpr_fmt::assert_sexpr_eq;
+
+#[test]
+fn layout_is_ignored() {
+    assert_sexpr_eq!("(assert\n    (and a b))", "(assert (and a b))");
+    assert_sexpr_eq!(String::from("(a) (b)"), "(a)\n(b)");
+}
+
+#[test]
+#[should_panic(expected = "at .1.2:")]
+fn differences_have_paths() {
+    assert_sexpr_eq!("(assert (and a b))", "(assert (and a c))");
+}
+
+#[test]
+#[should_panic(expected = "could not parse `actual`")]
+fn unparsable_sides_fail() {
+    assert_sexpr_eq!("(assert", "(assert)");
+}
+
+#[test]
+#[should_panic(expected = "not the same s-expressions: after rewriting")]
+fn messages_are_included() {
+    assert_sexpr_eq!("(f x)", "(f y)", "after rewriting {}", "f");
+}
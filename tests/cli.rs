@@ -0,0 +1,224 @@
+//! Tests of the `sexpr-fmt` command, run on its binary
+use std::io::{Read, Write};
+use std::process::{Command, Output, Stdio};
+
+/// Run `sexpr-fmt` with `args`, giving it `input` on stdin
+fn run(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sexpr-fmt"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    // written on its own thread, so that a full stdout pipe cannot stop us from reading it
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().ok();
+    output
+}
+
+/// Format `input` with `args`, check that formatting the result again leaves it as it is, and give back the result
+fn format_twice(args: &[&str], input: &str) -> String {
+    let once = run(args, input.as_bytes());
+    assert!(once.status.success(), "{}", String::from_utf8_lossy(&once.stderr));
+    let formatted = String::from_utf8(once.stdout).unwrap();
+    let twice = run(args, formatted.as_bytes());
+    assert_eq!(String::from_utf8(twice.stdout).unwrap(), formatted, "formatting `{}` with {:?} again changed it", input, args);
+    formatted
+}
+
+#[test]
+fn api_formats_a_huge_line_with_one_edit() {
+    // minified files: one line which is formatted into thousands, or tens of thousands
+    for count in [3_000, 20_000] {
+        let forms: Vec<String> = (0..count).map(|i| format!("(assert (> x{} (+ y {})))", i, i)).collect();
+        let request = serde_json::json!({ "apiVersion": 1, "id": 1, "text": forms.join(" ") });
+        let output = run(&["--api"], format!("{}\n", request).as_bytes());
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let edits = response["edits"].as_array().unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0]["range"]["start"], serde_json::json!({ "line": 0, "character": 0 }));
+    }
+}
+
+#[test]
+fn a_closed_pipe_ends_quietly() {
+    let forms: String = (0..50_000).map(|i| format!("(f x{})\n", i)).collect();
+    let one_form = format!("(all {})", forms);
+    let cases: [(&[&str], &str); 4] = [
+        (&["hash"], &forms),
+        (&["stats", "--top", "50000"], &forms),
+        (&["--pipeline", "from-sexpr | to-json"], &one_form),
+        (&[], &forms),
+    ];
+    for (args, input) in cases {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_sexpr-fmt"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let input = input.to_string();
+        let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        // like `| head -c 1`: read the start of the output, then stop reading it
+        let mut stdout = child.stdout.take().unwrap();
+        stdout.read_exact(&mut [0]).unwrap();
+        drop(stdout);
+        let output = child.wait_with_output().unwrap();
+        writer.join().unwrap().ok();
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        assert!(output.stderr.is_empty(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+}
+
+#[test]
+fn deep_nesting_is_a_parse_error() {
+    let deep = format!("{}x{}", "(f ".repeat(200_000), ")".repeat(200_000));
+    let output = run(&[], deep.as_bytes());
+    // 2 is the exit code of a parse error, rather than 134 for overflowing the stack
+    assert_eq!(output.status.code(), Some(2), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nested more than 1000 deep"));
+}
+
+#[test]
+fn daemon_refuses_an_oversized_request_in_either_framing() {
+    let huge = vec![b'x'; 16 * 1024 * 1024 + 1];
+    let framed = [("nul", huge.clone()), ("length", [format!("{}\n", huge.len()).into_bytes(), huge].concat())];
+    for (framing, input) in framed.iter() {
+        let output = run(&["--daemon", framing], input);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("is longer than the most we read"), "{}", framing);
+    }
+}
+
+#[test]
+fn api_options_cover_the_later_settings() {
+    let request = serde_json::json!({
+        "apiVersion": 1, "id": 1, "text": "(foo [a b])",
+        "options": { "complexity-threshold": 9, "max-width": 10, "normalize-brackets": "round" },
+    });
+    let output = run(&["--api"], format!("{}\n", request).as_bytes());
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["edits"][0]["newText"], "(foo\n    (a b)\n)\n", "{}", response);
+}
+
+#[test]
+fn compressed_stdin_is_read_by_every_mode() {
+    let compressed = sexpr_fmt::compress::Compression::Gzip.compress(b"(f   x)\n").unwrap();
+    for args in [&["--check"][..], &["--emit", "tokens-json"], &["--embedded"], &["--emacs"]] {
+        let output = run(args, &compressed);
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("UTF-8"), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+    let output = run(&["--emit", "tokens-json"], &compressed);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"tokens\""));
+    let output = run(&["--check"], &compressed);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn hard_wrap_splits_only_plain_symbols() {
+    let input = "(f \"aaaa bbbb cccc dddd\" #u8(1 2) abcdefghijklmnop)\n";
+    let formatted = format_twice(&["--max-width", "12", "--hard-wrap"], input);
+    assert!(formatted.contains("\"aaaa bbbb cccc dddd\"") && formatted.contains("#u8(1 2)"), "{}", formatted);
+    assert!(formatted.contains("abc#|+|#\n"), "{}", formatted);
+    // joined back together, the split symbol reads as it was
+    assert_eq!(format_twice(&["-c", "9", "--hard-wrap"], &formatted), "(f \"aaaa bbbb cccc dddd\" #u8(1 2) abcdefghijklmnop)\n");
+}
+
+#[test]
+fn sorting_keeps_the_blank_lines_before_each_form() {
+    let input = "(declare-fun b () Int)\n\n(declare-fun a () Int)\n(assert b)\n";
+    let formatted = format_twice(&["-c", "9", "--sort-toplevel", "declare-fun"], input);
+    // the assert was right after the declaration of `a`, and is still not apart from the one before it
+    assert_eq!(formatted, "(declare-fun a () Int)\n(declare-fun b () Int)\n(assert b)\n");
+    let sorted = "(declare-fun a () Int)\n(declare-fun b () Int)\n\n(define-fun c () Int a)\n(assert c)\n";
+    assert_eq!(format_twice(&["-c", "9", "--sort-definitions"], sorted), sorted);
+}
+
+#[test]
+fn sorting_flags_leave_the_files_after_them() {
+    let path = std::env::temp_dir().join(format!("sexpr-fmt-sorting-{}.smt2", std::process::id()));
+    std::fs::write(&path, "(declare-fun b () Int)\n(declare-fun a () Int)\n").unwrap();
+    for flag in ["--sort-toplevel", "--sort-args"] {
+        let output = run(&["-c", "9", flag, "declare-fun", path.to_str().unwrap()], b"");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(!output.stdout.is_empty(), "{} took the file as one of its heads", flag);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn quoted_symbols_are_one_atom() {
+    let formatted = format_twice(&["-c", "0"], "(f |b c| :|a key| |x \\| y| 1)\n");
+    assert_eq!(formatted, "(f\n    |b c|\n    :|a key|\n    |x \\| y|\n    1\n)\n");
+    let keys = run(&["--input-format", "json", "--json-keys", "symbol", "-c", "0"], br#"{"x":{"b c":1}}"#);
+    let keys = String::from_utf8(keys.stdout).unwrap();
+    assert!(keys.contains("(|b c|"), "{}", keys);
+    // the symbols written for the keys read back as they are
+    assert_eq!(format_twice(&["-c", "0"], &keys).trim_end(), keys.trim_end());
+}
+
+#[test]
+fn brackets_are_lists() {
+    let formatted = format_twice(&["-c", "0"], "(define (f x) [x 1])\n");
+    assert_eq!(formatted, "(define\n    (f\n        x\n    )\n    [x\n        1\n    ]\n)\n");
+    assert_eq!(format_twice(&["--minify"], "(define (f x) [x 1])\n"), "(define(f x)[x 1])\n");
+    let output = run(&[], b"(a b)\n]");
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("<stdin>:2:1: unexpected `]`"));
+    let tokens: serde_json::Value = serde_json::from_slice(&run(&["--emit", "tokens-json"], b"[x 1]").stdout).unwrap();
+    assert_eq!(tokens["tokens"][0]["kind"], "open");
+    // the atom is `x`, without the bracket
+    assert_eq!((&tokens["tokens"][1]["start"], &tokens["tokens"][1]["end"]), (&serde_json::json!(1), &serde_json::json!(2)));
+}
+
+#[test]
+fn embedded_blocks_which_do_not_parse_fail_where_they_go_wrong() {
+    let formatted = "text\n\n```lisp\n(f x)\n```\n";
+    assert_eq!(format_twice(&["--embedded", "-c", "9"], "text\n\n```lisp\n(f   x)\n```\n"), formatted);
+    let output = run(&["--embedded"], b"text\n\n```lisp\n(f x)\n  (g (h\n```\n");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("<stdin>:5:3: "), "{}", stderr);
+}
+
+#[test]
+fn pipelines_run_over_every_form() {
+    let output = run(&["--pipeline", "fmt -c 9 --max-width 8"], b"(f a) (g bbbb cccc)");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "(f a)\n(g\n    bbbb\n    cccc\n)\n");
+    let output = run(&["--pipeline", "fmt"], b"(f a) (g");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("<stdin>:1:7: "));
+    let output = run(&["--pipeline", "fmt --always-break f"], b"(f a)");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("the fmt stage takes only the settings"));
+}
+
+#[test]
+fn json_arrays_and_objects_are_told_apart() {
+    let output = run(&["--input-format", "json", "-c", "9"], br#"{"a":[1,2],"b":{},"c":[]}"#);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "(a [1 2] b () c [])\n");
+    // and lists in brackets come back from json as they went in
+    let json = run(&["--output-format", "json"], b"(f [a b])").stdout;
+    let output = run(&["--input-format", "json", "-c", "9"], &json);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "(f [a b])\n");
+}
+
+#[test]
+fn numbers_line_up_in_nested_rows() {
+    let formatted = format_twice(&["--align-numbers", "-c", "2"], "(fp (pad 1 smd (at 1.0 2.54)) (pad 22 smd (at -10.25 3)))");
+    assert_eq!(formatted, "(fp\n    (pad  1 smd (at    1.0 2.54))\n    (pad 22 smd (at -10.25    3))\n)\n");
+}
+
+#[test]
+fn fences_need_a_group_and_line_up_with_where_the_block_starts() {
+    let output = run(&["--embedded", "--fence", r#"r""""#], b"x = r\"\"\"(f a)\"\"\"\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("has no group"));
+    let formatted = format_twice(&["--embedded", "--fence", r#"r"""(.*?)""""#, "-c", "1"], "    x = r\"\"\"(define (f a) (g a))\"\"\"\n");
+    assert_eq!(formatted, "    x = r\"\"\"(define\n                (f a)\n                (g a)\n            )\"\"\"\n");
+}
@@ -0,0 +1,52 @@
+use proptest::prelude::*;
+use std::borrow::Cow;
+use sexpr_fmt::{FormatArgs, Sexpr};
+use sexpr_fmt::sexpr::join_continuations;
+
+/// Random formatting arguments, covering the options that change the layout
+fn format_args() -> impl Strategy<Value = FormatArgs<'static>> {
+    (0u32..4, any::<bool>(), any::<bool>(), any::<bool>(), any::<bool>(), prop::option::of(4usize..40), any::<bool>())
+        .prop_map(|(complexity_threshold, short_quantifiers, inline_unquoted, align_numbers, pair_keywords, max_width, hard_wrap)| {
+            let mut args = FormatArgs::new();
+            args.complexity_threshold = complexity_threshold;
+            args.short_quantifiers = short_quantifiers;
+            args.inline_unquoted = inline_unquoted;
+            args.align_numbers = align_numbers;
+            args.pair_keywords = pair_keywords;
+            args.max_width = max_width;
+            args.hard_wrap = hard_wrap;
+            args
+        })
+}
+
+/// What `formatted` reads back as, joining the atoms split by `hard_wrap` first, like the command does
+fn reread<'t>(formatted: &'t str, args: FormatArgs<'_>) -> Cow<'t, str> {
+    if args.hard_wrap {
+        join_continuations(formatted, args.continuation_marker)
+    } else {
+        Cow::Borrowed(formatted)
+    }
+}
+
+proptest! {
+    #[test]
+    fn formatting_keeps_the_structure(sexpr in Sexpr::arbitrary(), args in format_args()) {
+        let formatted = sexpr.format(args);
+        let read = reread(&formatted, args);
+        prop_assert_eq!(Sexpr::parse(&read), Ok(sexpr));
+    }
+
+    #[test]
+    fn formatting_is_idempotent(sexpr in Sexpr::arbitrary(), args in format_args()) {
+        let formatted = sexpr.format(args);
+        let reformatted = Sexpr::parse(&reread(&formatted, args)).unwrap().format(args);
+        prop_assert_eq!(reformatted, formatted);
+    }
+
+    #[test]
+    fn every_form_of_a_file_is_kept(forms in prop::collection::vec(Sexpr::arbitrary(), 0..5), args in format_args()) {
+        let formatted: String = forms.iter().map(|sexpr| sexpr.format(args) + "\n").collect();
+        let read = reread(&formatted, args);
+        prop_assert_eq!(Sexpr::parse_many(&read), Ok(forms));
+    }
+}
@@ -0,0 +1,49 @@
+use sexpr_fmt::{FormatArgs, Sexpr};
+
+/// Parses `input`, checking that formatting it on one line gives it back
+fn roundtrip(input: &str) {
+    let mut args = FormatArgs::new();
+    args.complexity_threshold = u32::MAX;
+    let sexpr = Sexpr::parse(input).unwrap_or_else(|e| panic!("could not parse `{}`: {}", input, e));
+    assert_eq!(sexpr.format(args), input);
+}
+
+#[test]
+fn greek_atoms() {
+    roundtrip("(λ (x) x)");
+    roundtrip("(+ α β γ)");
+}
+
+#[test]
+fn math_symbols() {
+    roundtrip("(∀ ((x ℕ)) (≥ x 0))");
+}
+
+#[test]
+fn cjk_atoms() {
+    roundtrip("(定義 変数 値)");
+}
+
+#[test]
+fn emoji_atoms() {
+    roundtrip("(🦀 🎉 (👍🏽 x))");
+}
+
+#[test]
+fn multi_byte_characters_next_to_parens() {
+    roundtrip("(é (ü) ö)");
+    let sexpr = Sexpr::parse("(é(ü)ö)").unwrap();
+    assert_eq!(sexpr.format(FormatArgs::new()), "(é\n    (ü)\n    ö\n)");
+}
+
+#[test]
+fn multi_byte_characters_in_comments() {
+    roundtrip("(a #| ünïcödé 日本 🦀 |# b)");
+}
+
+#[test]
+fn spans_are_byte_offsets() {
+    let forms = Sexpr::parse_many("(λ x) (日本)").unwrap();
+    let spans: Vec<_> = forms.iter().map(|sexpr| sexpr.span().unwrap().range()).collect();
+    assert_eq!(spans, vec![0..6, 7..15]);
+}
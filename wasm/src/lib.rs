@@ -0,0 +1,72 @@
+//! `format(input, options)` for javascript, to run the formatter in browser playgrounds and web extensions
+//! every problem, like input that cannot be read or an option of the wrong type, is thrown as an `Error` rather than aborting the module
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use sexpr_fmt::{FormatArgs, Sexpr};
+use sexpr_fmt::compat::LayoutVersion;
+
+/// The options javascript may pass, named like the command line flags, such as `{"complexity-threshold": 2}`
+/// options which are left out keep the defaults of the command line
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", default)]
+struct Options {
+    complexity_threshold: Option<u32>,
+    short_quantifiers: bool,
+    inline_unquoted: bool,
+    reflow_comments: bool,
+    comment_width: Option<usize>,
+    tab_width: Option<usize>,
+    align_numbers: bool,
+    pair_keywords: bool,
+    align_bindings: bool,
+    max_width: Option<usize>,
+    compat: Option<String>,
+    minify: bool,
+}
+impl Options {
+    fn format_args(&self) -> Result<FormatArgs<'_>, String> {
+        let mut args = FormatArgs::new();
+        if let Some(threshold) = self.complexity_threshold {
+            args.complexity_threshold = threshold;
+        }
+        args.short_quantifiers = self.short_quantifiers;
+        args.inline_unquoted = self.inline_unquoted;
+        args.reflow_comments = self.reflow_comments;
+        if let Some(width) = self.comment_width {
+            args.comment_width = width;
+        }
+        if let Some(width) = self.tab_width {
+            args.tab_width = width;
+        }
+        args.align_numbers = self.align_numbers;
+        args.pair_keywords = self.pair_keywords;
+        args.align_bindings = self.align_bindings;
+        args.max_width = self.max_width;
+        if let Some(ref version) = self.compat {
+            args.layout_version = version.parse::<LayoutVersion>()?;
+        }
+        Ok(args)
+    }
+}
+
+/// Format every top-level form of `input`, each on its own line, with `options` (an object, or `undefined` for the defaults)
+#[wasm_bindgen]
+pub fn format(input: &str, options: JsValue) -> Result<String, JsError> {
+    let options: Options = if options.is_undefined() || options.is_null() {
+        Options::default()
+    } else {
+        serde_wasm_bindgen::from_value(options).map_err(|error| JsError::new(&format!("bad options: {}", error)))?
+    };
+    let args = options.format_args().map_err(|error| JsError::new(&error))?;
+    let forms = Sexpr::parse_many(input).map_err(JsError::new)?;
+    let mut out = String::new();
+    for form in forms {
+        if options.minify {
+            out.push_str(&form.to_minified_string());
+        } else {
+            out.push_str(&form.format(args));
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}